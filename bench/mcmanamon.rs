@@ -1,7 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 use cv_camstream::{GrayFloatImage, StereoFrame};
-use cv_disparity::{prelude::*, mcmanamon::{McManamon, Params}};
+use cv_disparity::{prelude::*, config::Config};
 use image;
 
 fn mcmanamon_bench(c: &mut Criterion) {
@@ -11,12 +11,7 @@ fn mcmanamon_bench(c: &mut Criterion) {
     let right_img = image::open("res/renders/simple_rocks_01_right.png").unwrap();
 
     // Build disparity alg
-    let mut disp = McManamon::new(Params {
-        min_disparity: 0,
-        max_disparity: 100,
-        dyn_disparity_threshold: 10,
-        correlation_window_size: (11, 11)
-    });
+    let mut disp = Config::load("res/configs/mcmanamon.toml").unwrap().build().unwrap();
 
     // Build frame
     let frame = StereoFrame {
@@ -0,0 +1,671 @@
+//! # AD-Census disparity computation
+//!
+//! This module provides an implementation of the AD-Census algorithm from
+//! ("On Building an Accurate Stereo Matching System on Graphics Hardware")[https://doi.org/10.1109/ICCVW.2011.6130280]
+//! by Mei et al.
+//!
+//! The algorithm runs in four stages:
+//!
+//! 1. An initial cost combining absolute difference and census costs, each passed through a
+//!    robust exponential so that neither dominates.
+//! 2. Cost aggregation over cross-based support regions, which adapt their shape to the image by
+//!    extending arms from each pixel until the intensity changes too much.
+//! 3. Scanline optimisation along four directions, as in semi-global matching, with penalties
+//!    reduced across intensity edges.
+//! 4. Refinement, where pixels failing a left-right consistency check are filled by iterative
+//!    voting over the reliable disparities in their support region, followed by sub pixel
+//!    interpolation.
+//!
+//! Pixels which are still unreliable after voting are left invalid. The support regions are built
+//! from the left image only.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use serde::Deserialize;
+
+use crate::cost::{subpixel_minimum, AbsDiff, Census, MatchingCost};
+use crate::cost_volume::{CostVolume, CostVolumeAlgorithm};
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct AdCensus {
+    params: Params
+}
+
+/// AD-Census parameters.
+///
+/// Intensity thresholds are in the same units as the image intensities. The defaults are the
+/// values from the paper rescaled to intensities in [0, 1].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Size of the census window as (width, height).
+    pub census_window: (usize, usize),
+
+    /// Scale of the robust function applied to the absolute difference cost.
+    pub lambda_ad: f32,
+
+    /// Scale of the robust function applied to the census cost.
+    pub lambda_census: f32,
+
+    /// Intensity difference which stops a cross arm.
+    pub cross_tau_1: f32,
+
+    /// Stricter intensity difference which stops a cross arm longer than `cross_l_2`.
+    pub cross_tau_2: f32,
+
+    /// Maximum length of a cross arm.
+    pub cross_l_1: usize,
+
+    /// Length beyond which a cross arm is subject to `cross_tau_2`.
+    pub cross_l_2: usize,
+
+    /// Number of cross-based aggregation iterations.
+    pub aggregation_iterations: usize,
+
+    /// Scanline optimisation penalty for disparity changes of one.
+    pub so_pi_1: f32,
+
+    /// Scanline optimisation penalty for larger disparity changes.
+    pub so_pi_2: f32,
+
+    /// Intensity difference above which scanline penalties are reduced.
+    pub so_tau: f32,
+
+    /// Number of region voting iterations.
+    pub vote_iterations: usize,
+
+    /// Minimum number of reliable pixels in a support region for a vote to be held.
+    pub vote_tau_s: usize,
+
+    /// Minimum fraction of the reliable pixels which must agree for a vote to succeed.
+    pub vote_tau_h: f32
+}
+
+/// Arm lengths of a pixel's cross-based support region.
+#[derive(Copy, Clone, Debug, Default)]
+struct Cross {
+    left: usize,
+    right: usize,
+    up: usize,
+    down: usize
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            max_disparity: 64,
+            census_window: (9, 7),
+            lambda_ad: 10.0 / 255.0,
+            lambda_census: 30.0,
+            cross_tau_1: 20.0 / 255.0,
+            cross_tau_2: 6.0 / 255.0,
+            cross_l_1: 34,
+            cross_l_2: 17,
+            aggregation_iterations: 4,
+            so_pi_1: 1.0,
+            so_pi_2: 3.0,
+            so_tau: 15.0 / 255.0,
+            vote_iterations: 5,
+            vote_tau_s: 20,
+            vote_tau_h: 0.4
+        }
+    }
+}
+
+impl AdCensus {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        Self { params }
+    }
+
+    /// Compute the initial AD-Census cost volume.
+    ///
+    /// Disparities which would place the match outside the right image are given the maximum
+    /// cost of 2.
+    fn initial_costs(&self, frame: &StereoFrame) -> Result<CostVolume> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let ad = AbsDiff::new(frame, f32::INFINITY);
+        let census = Census::new(frame, self.params.census_window)?;
+
+        let mut volume = CostVolume::filled(
+            width, height,
+            self.params.min_disparity..self.params.max_disparity,
+            2.0
+        );
+
+        for y in 0..height {
+            for x in 0..width {
+                let costs = volume.costs_mut(x, y);
+
+                for (k, c) in costs.iter_mut().enumerate() {
+                    let d = k + self.params.min_disparity;
+
+                    if d > x {
+                        break;
+                    }
+
+                    *c = robust(ad.cost(x, y, d), self.params.lambda_ad)
+                        + robust(census.cost(x, y, d), self.params.lambda_census);
+                }
+            }
+        }
+
+        Ok(volume)
+    }
+
+    /// Build the cross-based support region of every pixel in the image.
+    fn build_crosses(&self, image: &GrayFloatImage) -> Vec<Cross> {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+
+        let mut crosses = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                crosses.push(Cross {
+                    left: self.arm_length(image, x, y, (-1, 0)),
+                    right: self.arm_length(image, x, y, (1, 0)),
+                    up: self.arm_length(image, x, y, (0, -1)),
+                    down: self.arm_length(image, x, y, (0, 1))
+                });
+            }
+        }
+
+        crosses
+    }
+
+    /// Length of the arm extending from the given pixel in the given direction.
+    fn arm_length(
+        &self,
+        image: &GrayFloatImage,
+        x: usize,
+        y: usize,
+        dir: (isize, isize)
+    ) -> usize {
+        let width = image.width() as isize;
+        let height = image.height() as isize;
+        let centre = image.get(x, y);
+
+        let mut len = 0;
+
+        while len < self.params.cross_l_1 {
+            let n = len as isize + 1;
+            let qx = x as isize + dir.0 * n;
+            let qy = y as isize + dir.1 * n;
+
+            if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                break;
+            }
+
+            let q = image.get(qx as usize, qy as usize);
+            let prev = image.get((qx - dir.0) as usize, (qy - dir.1) as usize);
+
+            let diff_centre = (q - centre).abs();
+            let diff_prev = (q - prev).abs();
+
+            if diff_centre >= self.params.cross_tau_1 || diff_prev >= self.params.cross_tau_1 {
+                break;
+            }
+
+            if len + 1 > self.params.cross_l_2 && diff_centre >= self.params.cross_tau_2 {
+                break;
+            }
+
+            len += 1;
+        }
+
+        len
+    }
+
+    /// Aggregate a single disparity slice over the support regions.
+    ///
+    /// With `horizontal_first` each pixel's horizontal arm is summed first and the results
+    /// summed along the pixel's vertical arm, otherwise the order is reversed. Raw sums are
+    /// returned, which are normalised by aggregating a slice of ones.
+    fn aggregate_slice(
+        crosses: &[Cross],
+        slice: &[f32],
+        width: usize,
+        height: usize,
+        horizontal_first: bool
+    ) -> Vec<f32> {
+        let mut first = vec![0.0f32; width * height];
+        let mut second = vec![0.0f32; width * height];
+
+        // Prefix sums along a row or column, with a leading zero
+        let mut prefix: Vec<f32> = Vec::with_capacity(width.max(height) + 1);
+
+        // Sum along rows
+        let sum_rows = |src: &[f32], dst: &mut [f32], prefix: &mut Vec<f32>| {
+            for y in 0..height {
+                let mut sum = 0.0;
+                prefix.clear();
+                prefix.push(sum);
+                for x in 0..width {
+                    sum += src[y * width + x];
+                    prefix.push(sum);
+                }
+
+                for x in 0..width {
+                    let c = crosses[y * width + x];
+                    dst[y * width + x] = prefix[x + c.right + 1] - prefix[x - c.left];
+                }
+            }
+        };
+
+        // Sum along columns
+        let sum_cols = |src: &[f32], dst: &mut [f32], prefix: &mut Vec<f32>| {
+            for x in 0..width {
+                let mut sum = 0.0;
+                prefix.clear();
+                prefix.push(sum);
+                for y in 0..height {
+                    sum += src[y * width + x];
+                    prefix.push(sum);
+                }
+
+                for y in 0..height {
+                    let c = crosses[y * width + x];
+                    dst[y * width + x] = prefix[y + c.down + 1] - prefix[y - c.up];
+                }
+            }
+        };
+
+        if horizontal_first {
+            sum_rows(slice, &mut first, &mut prefix);
+            sum_cols(&first, &mut second, &mut prefix);
+        }
+        else {
+            sum_cols(slice, &mut first, &mut prefix);
+            sum_rows(&first, &mut second, &mut prefix);
+        }
+
+        second
+    }
+
+    /// Aggregate the cost volume over the cross-based support regions, alternating between
+    /// horizontal-first and vertical-first regions on each iteration.
+    fn aggregate(&self, volume: &mut CostVolume, crosses: &[Cross]) {
+        let width = volume.width();
+        let height = volume.height();
+
+        // Number of pixels in each support region, for both orders
+        let ones = vec![1.0f32; width * height];
+        let counts = [
+            Self::aggregate_slice(crosses, &ones, width, height, true),
+            Self::aggregate_slice(crosses, &ones, width, height, false)
+        ];
+
+        for d in volume.disparities() {
+            let mut slice = volume.slice(d);
+
+            for iter in 0..self.params.aggregation_iterations {
+                let horizontal_first = iter % 2 == 0;
+
+                let sums = Self::aggregate_slice(crosses, &slice, width, height, horizontal_first);
+                let count = &counts[if horizontal_first { 0 } else { 1 }];
+
+                for ((s, sum), c) in slice.iter_mut().zip(sums.iter()).zip(count.iter()) {
+                    *s = sum / c;
+                }
+            }
+
+            volume.set_slice(d, &slice);
+        }
+    }
+
+    /// Scanline optimisation penalties, given the intensity changes along the path in each image.
+    fn penalties(&self, diff_left: f32, diff_right: f32) -> (f32, f32) {
+        let tau = self.params.so_tau;
+        let (p1, p2) = (self.params.so_pi_1, self.params.so_pi_2);
+
+        match (diff_left < tau, diff_right < tau) {
+            (true, true) => (p1, p2),
+            (true, false) | (false, true) => (p1 / 4.0, p2 / 4.0),
+            (false, false) => (p1 / 10.0, p2 / 10.0)
+        }
+    }
+
+    /// Take one step along a scanline optimisation path from pixel `q` to pixel `p`.
+    fn path_step(
+        &self,
+        frame: &StereoFrame,
+        costs: &[f32],
+        prev: &[f32],
+        p: (usize, usize),
+        q: (usize, usize),
+        out: &mut [f32]
+    ) {
+        let prev_min = prev.iter().cloned().fold(f32::INFINITY, f32::min);
+        let diff_left = (frame.left.get(p.0, p.1) - frame.left.get(q.0, q.1)).abs();
+
+        for (k, (o, &cost)) in out.iter_mut().zip(costs.iter()).enumerate() {
+            let d = k + self.params.min_disparity;
+
+            let diff_right = match p.0 >= d && q.0 >= d {
+                true => (frame.right.get(p.0 - d, p.1) - frame.right.get(q.0 - d, q.1)).abs(),
+                false => diff_left
+            };
+
+            let (p1, p2) = self.penalties(diff_left, diff_right);
+
+            let mut best = prev[k].min(prev_min + p2);
+            if k > 0 {
+                best = best.min(prev[k - 1] + p1);
+            }
+            if k + 1 < costs.len() {
+                best = best.min(prev[k + 1] + p1);
+            }
+
+            *o = cost + best - prev_min;
+        }
+    }
+
+    /// Run scanline optimisation along the four axis directions and average the results.
+    fn scanline_optimise(&self, frame: &StereoFrame, volume: &CostVolume) -> CostVolume {
+        let width = volume.width();
+        let height = volume.height();
+        let num_disp = volume.num_disparities();
+
+        let mut total = CostVolume::filled(width, height, volume.disparities(), 0.0);
+        let mut step = vec![0.0f32; num_disp];
+
+        // Horizontal paths, keeping only the previous pixel's path costs
+        for &forward in &[true, false] {
+            for y in 0..height {
+                let mut prev: Vec<f32> = Vec::new();
+
+                for i in 0..width {
+                    let x = if forward { i } else { width - 1 - i };
+
+                    if i == 0 {
+                        prev = volume.costs(x, y).to_vec();
+                    }
+                    else {
+                        let qx = if forward { x - 1 } else { x + 1 };
+                        let costs = volume.costs(x, y);
+                        self.path_step(frame, costs, &prev, (x, y), (qx, y), &mut step);
+                        prev.copy_from_slice(&step);
+                    }
+
+                    for (t, &c) in total.costs_mut(x, y).iter_mut().zip(prev.iter()) {
+                        *t += c;
+                    }
+                }
+            }
+        }
+
+        // Vertical paths, keeping the previous row's path costs
+        for &forward in &[true, false] {
+            let mut prev_row: Vec<f32> = Vec::with_capacity(width * num_disp);
+
+            for i in 0..height {
+                let y = if forward { i } else { height - 1 - i };
+
+                if i == 0 {
+                    prev_row.clear();
+                    for x in 0..width {
+                        prev_row.extend_from_slice(volume.costs(x, y));
+                    }
+                }
+                else {
+                    let qy = if forward { y - 1 } else { y + 1 };
+
+                    for x in 0..width {
+                        let prev = &mut prev_row[x * num_disp..(x + 1) * num_disp];
+                        let costs = volume.costs(x, y);
+                        self.path_step(frame, costs, prev, (x, y), (x, qy), &mut step);
+                        prev.copy_from_slice(&step);
+                    }
+                }
+
+                for x in 0..width {
+                    let prev = &prev_row[x * num_disp..(x + 1) * num_disp];
+                    for (t, &c) in total.costs_mut(x, y).iter_mut().zip(prev.iter()) {
+                        *t += c;
+                    }
+                }
+            }
+        }
+
+        for t in total.as_mut_slice().iter_mut() {
+            *t /= 4.0;
+        }
+
+        total
+    }
+
+    /// Winner-take-all disparity index for the left image at every pixel.
+    fn left_wta(&self, volume: &CostVolume) -> Vec<usize> {
+        let mut disps = Vec::with_capacity(volume.width() * volume.height());
+
+        for y in 0..volume.height() {
+            for x in 0..volume.width() {
+                disps.push(argmin(volume.costs(x, y)));
+            }
+        }
+
+        disps
+    }
+
+    /// Winner-take-all disparity index for the right image at every pixel, using the left cost
+    /// volume sampled along the right image's lines of sight.
+    fn right_wta(&self, volume: &CostVolume) -> Vec<usize> {
+        let mut disps = Vec::with_capacity(volume.width() * volume.height());
+
+        for y in 0..volume.height() {
+            for x in 0..volume.width() {
+                let mut best = (0, f32::INFINITY);
+
+                for k in 0..volume.num_disparities() {
+                    let xl = x + k + self.params.min_disparity;
+
+                    if xl >= volume.width() {
+                        break;
+                    }
+
+                    let c = volume.costs(xl, y)[k];
+                    if c < best.1 {
+                        best = (k, c);
+                    }
+                }
+
+                disps.push(best.0);
+            }
+        }
+
+        disps
+    }
+
+    /// Fill unreliable pixels by voting over the reliable disparities in their support regions.
+    fn region_voting(
+        &self,
+        crosses: &[Cross],
+        disps: &mut Vec<usize>,
+        reliable: &mut Vec<bool>,
+        width: usize,
+        height: usize
+    ) {
+        let num_disp = self.params.max_disparity - self.params.min_disparity;
+        let mut hist = vec![0usize; num_disp];
+
+        for _ in 0..self.params.vote_iterations {
+            let mut new_disps = disps.clone();
+            let mut new_reliable = reliable.clone();
+
+            for y in 0..height {
+                for x in 0..width {
+                    if reliable[y * width + x] {
+                        continue;
+                    }
+
+                    for h in hist.iter_mut() {
+                        *h = 0;
+                    }
+
+                    // Support region is the horizontal arms of the pixels on the vertical arm
+                    let c = crosses[y * width + x];
+                    for qy in y - c.up..y + c.down + 1 {
+                        let qc = crosses[qy * width + x];
+
+                        for qx in x - qc.left..x + qc.right + 1 {
+                            if reliable[qy * width + qx] {
+                                hist[disps[qy * width + qx]] += 1;
+                            }
+                        }
+                    }
+
+                    let total: usize = hist.iter().sum();
+                    if total <= self.params.vote_tau_s {
+                        continue;
+                    }
+
+                    let (best, &count) = hist
+                        .iter()
+                        .enumerate()
+                        .max_by_key(|&(_, c)| *c)
+                        .unwrap();
+
+                    if count as f32 / total as f32 > self.params.vote_tau_h {
+                        new_disps[y * width + x] = best;
+                        new_reliable[y * width + x] = true;
+                    }
+                }
+            }
+
+            *disps = new_disps;
+            *reliable = new_reliable;
+        }
+    }
+
+    /// Run the cost initialisation, aggregation and scanline optimisation stages, returning the
+    /// optimised volume and the support regions used to aggregate it.
+    fn optimised_volume(&self, frame: &StereoFrame) -> Result<(CostVolume, Vec<Cross>)> {
+        if self.params.max_disparity <= self.params.min_disparity {
+            return Err(Error::InvalidParams(
+                "max_disparity must be greater than min_disparity".into()
+            ));
+        }
+
+        // ---- COST INITIALISATION ----
+
+        let mut volume = self.initial_costs(frame)?;
+
+        // ---- CROSS-BASED AGGREGATION ----
+
+        let crosses = self.build_crosses(&frame.left);
+        self.aggregate(&mut volume, &crosses);
+
+        // ---- SCANLINE OPTIMISATION ----
+
+        Ok((self.scanline_optimise(frame, &volume), crosses))
+    }
+}
+
+impl DisparityAlgorithm for AdCensus {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let (volume, crosses) = self.optimised_volume(frame)?;
+
+        // ---- REFINEMENT ----
+
+        let mut disps = self.left_wta(&volume);
+        let right_disps = self.right_wta(&volume);
+
+        // Left-right consistency check
+        let mut reliable: Vec<bool> = (0..width * height)
+            .map(|i| {
+                let x = i % width;
+                let d = disps[i] + self.params.min_disparity;
+
+                d <= x && (disps[i] as isize - right_disps[i - d] as isize).abs() <= 1
+            })
+            .collect();
+
+        // Remember which pixels were originally reliable, their costs allow sub pixel refinement
+        let consistent = reliable.clone();
+
+        self.region_voting(&crosses, &mut disps, &mut reliable, width, height);
+
+        let mut disp_map = DisparityMap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+
+                if !reliable[i] {
+                    continue;
+                }
+
+                let disp_val = match consistent[i] {
+                    true => subpixel_minimum(volume.costs(x, y)),
+                    false => disps[i] as f32
+                };
+
+                disp_map.put(x, y, self.params.min_disparity as f32 + disp_val);
+            }
+        }
+
+        disp_map.update_range();
+
+        Ok(disp_map)
+    }
+
+    /// Margins covering the census window and the longest cross arms. Scanline optimisation
+    /// couples whole rows and columns, so tiled results will still differ slightly.
+    fn margins(&self) -> Margins {
+        let arm = self.params.cross_l_1;
+
+        Margins {
+            left: self.params.census_window.0 / 2 + arm + self.params.max_disparity,
+            right: self.params.census_window.0 / 2 + arm,
+            top: self.params.census_window.1 / 2 + arm,
+            bottom: self.params.census_window.1 / 2 + arm
+        }
+    }
+}
+
+impl CostVolumeAlgorithm for AdCensus {
+    /// The cost volume after scanline optimisation, before the consistency check and voting.
+    fn cost_volume(&mut self, frame: &StereoFrame) -> Result<CostVolume> {
+        Ok(self.optimised_volume(frame)?.0)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Robust function mapping a cost into [0, 1).
+fn robust(cost: f32, lambda: f32) -> f32 {
+    1.0 - (-cost / lambda).exp()
+}
+
+/// Index of the minimum value in the slice.
+fn argmin(vals: &[f32]) -> usize {
+    vals.iter()
+        .enumerate()
+        .fold(0, |min_idx, (idx, &val)| if val < vals[min_idx] { idx } else { min_idx })
+}
@@ -0,0 +1,269 @@
+//! # Adaptive support weight disparity computation
+//!
+//! This module provides an implementation of the adaptive support weight algorithm from
+//! ("Adaptive Support-Weight Approach for Correspondence Search")[https://doi.org/10.1109/TPAMI.2006.70]
+//! by Yoon and Kweon.
+//!
+//! Rather than weighting every pixel in a square window equally, each pixel's contribution to the
+//! aggregated cost is weighted by its similarity in intensity to, and its proximity to, the
+//! centre pixel in both images. Pixels on the far side of an edge therefore contribute very
+//! little, and disparity discontinuities stay sharp.
+//!
+//! With colour input the similarity weights use the Euclidean distance between colours and the
+//! matching cost is averaged over the channels.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::StereoFrame;
+use serde::Deserialize;
+
+use crate::colour::{colour_distance, ColourStereoFrame};
+use crate::cost::{subpixel_minimum, CostFunction};
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::prior::DisparityPrior;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct AdaptiveSupportWeight {
+    params: Params,
+
+    /// Offsets of each pixel in the support window from its centre.
+    offsets: Vec<(isize, isize)>,
+
+    /// Proximity weight of each pixel in the support window, which is fixed for a window size.
+    proximity: Vec<f32>
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Side length of the square support window, which should be odd.
+    pub window_size: usize,
+
+    /// Intensity difference at which the similarity weight falls to 1/e, in the same units as
+    /// the image intensities.
+    pub gamma_similarity: f32,
+
+    /// Distance in pixels at which the proximity weight falls to 1/e.
+    pub gamma_proximity: f32,
+
+    /// Per-pixel matching cost which is aggregated over the window.
+    #[serde(default)]
+    pub cost: CostFunction
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl AdaptiveSupportWeight {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        let radius = (params.window_size as isize - 1) / 2;
+
+        let mut offsets = Vec::new();
+        let mut proximity = Vec::new();
+
+        for j in -radius..radius + 1 {
+            for i in -radius..radius + 1 {
+                let dist = ((i * i + j * j) as f32).sqrt();
+
+                offsets.push((i, j));
+                proximity.push((-dist / params.gamma_proximity).exp());
+            }
+        }
+
+        Self {
+            params,
+            offsets,
+            proximity
+        }
+    }
+
+    /// Calculate the support weights of the window around every pixel in a row of an image.
+    ///
+    /// `distance` gives the difference in appearance between two pixels of the image. Weights
+    /// are stored in `weights` indexed as `[x * window_len + k]`, with window pixels outside the
+    /// image given a weight of zero.
+    fn row_weights<F>(
+        &self,
+        (width, height): (usize, usize),
+        y: usize,
+        distance: F,
+        weights: &mut Vec<f32>
+    )
+    where
+        F: Fn((usize, usize), (usize, usize)) -> f32
+    {
+        weights.clear();
+
+        for x in 0..width {
+            for (k, &(i, j)) in self.offsets.iter().enumerate() {
+                let qx = x as isize + i;
+                let qy = y as isize + j;
+
+                if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                    weights.push(0.0);
+                    continue;
+                }
+
+                let similarity = distance((x, y), (qx as usize, qy as usize));
+
+                weights.push(
+                    (-similarity / self.params.gamma_similarity).exp() * self.proximity[k]
+                );
+            }
+        }
+    }
+
+    /// Compute the disparity map for the given frame, with an optional search range prior.
+    ///
+    /// If the colour frame is given, the greyscale frame must be its greyscale frame, and colour
+    /// is used for both the weights and the matching cost.
+    fn compute_impl(
+        &mut self,
+        frame: &StereoFrame,
+        prior: Option<&DisparityPrior>,
+        colour: Option<&ColourStereoFrame>
+    ) -> Result<DisparityMap> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+        let window_len = self.offsets.len();
+
+        let mut disp_map = DisparityMap::new(width, height);
+
+        let size = (width, height);
+
+        let cost = match colour {
+            Some(c) => self.params.cost.build_colour(c)?,
+            None => self.params.cost.build(frame)?
+        };
+
+        // Support weights for every window in the current row of each image
+        let mut left_weights: Vec<f32> = Vec::with_capacity(width * window_len);
+        let mut right_weights: Vec<f32> = Vec::with_capacity(width * window_len);
+
+        // Aggregated cost for each disparity at the current pixel
+        let mut costs: Vec<f32> = Vec::with_capacity(self.params.max_disparity);
+
+        for y in 0..height {
+            match colour {
+                Some(c) => {
+                    let left_dist = |p: (usize, usize), q: (usize, usize)| {
+                        colour_distance(c.left.get(p.0, p.1), c.left.get(q.0, q.1))
+                    };
+                    let right_dist = |p: (usize, usize), q: (usize, usize)| {
+                        colour_distance(c.right.get(p.0, p.1), c.right.get(q.0, q.1))
+                    };
+
+                    self.row_weights(size, y, left_dist, &mut left_weights);
+                    self.row_weights(size, y, right_dist, &mut right_weights);
+                },
+                None => {
+                    let left_dist = |p: (usize, usize), q: (usize, usize)| {
+                        (frame.left.get(p.0, p.1) - frame.left.get(q.0, q.1)).abs()
+                    };
+                    let right_dist = |p: (usize, usize), q: (usize, usize)| {
+                        (frame.right.get(p.0, p.1) - frame.right.get(q.0, q.1)).abs()
+                    };
+
+                    self.row_weights(size, y, left_dist, &mut left_weights);
+                    self.row_weights(size, y, right_dist, &mut right_weights);
+                }
+            }
+
+            for x in 0..width {
+                let mut search = match prior {
+                    Some(p) => p.search_range(
+                        x, y,
+                        self.params.min_disparity..self.params.max_disparity
+                    ),
+                    None => self.params.min_disparity..self.params.max_disparity
+                };
+
+                // The centre pixel must lie inside the right image
+                search.end = search.end.min(x + 1);
+
+                if search.start >= search.end {
+                    continue;
+                }
+
+                costs.clear();
+
+                for d in search.clone() {
+                    let mut num = 0.0f32;
+                    let mut den = 0.0f32;
+
+                    let left_window = &left_weights[x * window_len..(x + 1) * window_len];
+                    let right_window =
+                        &right_weights[(x - d) * window_len..(x - d + 1) * window_len];
+
+                    for (k, &(i, j)) in self.offsets.iter().enumerate() {
+                        // The weight is zero if either window pixel is outside its image
+                        let weight = left_window[k] * right_window[k];
+
+                        if weight <= 0.0 {
+                            continue;
+                        }
+
+                        let qx = (x as isize + i) as usize;
+                        let qy = (y as isize + j) as usize;
+
+                        num += weight * cost.cost(qx, qy, d);
+                        den += weight;
+                    }
+
+                    costs.push(num / den);
+                }
+
+                disp_map.put(x, y, search.start as f32 + subpixel_minimum(&costs));
+            }
+        }
+
+        disp_map.update_range();
+
+        Ok(disp_map)
+    }
+}
+
+impl DisparityAlgorithm for AdaptiveSupportWeight {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        self.compute_impl(frame, None, None)
+    }
+
+    fn compute_with_prior(
+        &mut self,
+        frame: &StereoFrame,
+        prior: &DisparityPrior
+    ) -> Result<DisparityMap> {
+        prior.check_size(frame.width() as usize, frame.height() as usize)?;
+
+        self.compute_impl(frame, Some(prior), None)
+    }
+
+    /// Compute the disparity map for the given colour frame, using colour in both the support
+    /// weights and the matching cost.
+    fn compute_colour(&mut self, frame: &ColourStereoFrame) -> Result<DisparityMap> {
+        self.compute_impl(&frame.grey, None, Some(frame))
+    }
+
+    fn margins(&self) -> Margins {
+        let radius = self.params.window_size / 2;
+
+        Margins {
+            left: radius + self.params.max_disparity,
+            right: radius,
+            top: radius,
+            bottom: radius
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! # Batch processing
+//!
+//! This module computes the disparity maps of every stereo pair in a directory tree, writing the
+//! maps to a second tree which mirrors the first, and summarises the runtime and statistics of
+//! each frame.
+//!
+//! Pairs are matched by file name. The left and right patterns contain a single `{}` standing
+//! for the frame name, so with the default patterns `scene/0001_left.png` and
+//! `scene/0001_right.png` form the frame `scene/0001`, whose map is written to
+//! `<output>/scene/0001.pfm`.
+//!
+//! A frame which fails to load or compute does not stop the batch, its error is recorded in the
+//! [`Summary`] instead. The summary can be saved as CSV or JSON.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::colour::ColourStereoFrame;
+use crate::datasets::kitti;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::io;
+use crate::quality;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Placeholder for the frame name in the file name patterns.
+const PLACEHOLDER: &str = "{}";
+
+/// Columns of the CSV summary.
+const CSV_HEADER: &str = "name,width,height,runtime,density,min_disparity,max_disparity,\
+    mean_disparity,smoothness,error";
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    /// File name pattern of the left images, with `{}` standing for the frame name.
+    pub left_pattern: String,
+
+    /// File name pattern of the right images, with `{}` standing for the frame name.
+    pub right_pattern: String,
+
+    /// Search subdirectories of the input as well.
+    pub recursive: bool,
+
+    /// Format the disparity maps are written in.
+    pub format: OutputFormat,
+
+    /// Also write a normalised 8-bit preview of each map, as `<name>_preview.png`.
+    pub preview: bool
+}
+
+/// A stereo pair found in the input tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pair {
+    /// Path of the frame relative to the input root, without an extension, such as
+    /// `scene/0001`.
+    pub name: PathBuf,
+
+    pub left: PathBuf,
+    pub right: PathBuf
+}
+
+/// Runtime and statistics of a single frame.
+#[derive(Serialize, Debug, Clone)]
+pub struct FrameSummary {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+
+    /// Time taken to compute the map in seconds, not counting loading and saving.
+    pub runtime: f64,
+
+    /// Fraction of the map's pixels with a valid disparity.
+    pub density: f32,
+
+    pub min_disparity: Option<f32>,
+    pub max_disparity: Option<f32>,
+    pub mean_disparity: Option<f32>,
+
+    /// Mean absolute difference between valid neighbouring disparities, see
+    /// [`quality::smoothness`].
+    pub smoothness: f32,
+
+    /// Why the frame failed, in which case the other fields are zero.
+    pub error: Option<String>
+}
+
+/// Summary of a batch run.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Summary {
+    pub frames: Vec<FrameSummary>
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Format the disparity maps of a batch are written in.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Float PFM, see [`io::write_pfm`].
+    Pfm,
+
+    /// KITTI style 16-bit PNG, see [`kitti::write_disparity`].
+    Png
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            left_pattern: "{}_left.png".into(),
+            right_pattern: "{}_right.png".into(),
+            recursive: true,
+            format: OutputFormat::Pfm,
+            preview: false
+        }
+    }
+}
+
+impl OutputFormat {
+    /// File extension of the format, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Pfm => "pfm",
+            OutputFormat::Png => "png"
+        }
+    }
+
+    /// Write a map in this format.
+    pub fn write<P: AsRef<Path>>(&self, map: &DisparityMap, path: P) -> Result<()> {
+        match self {
+            OutputFormat::Pfm => io::write_pfm(map, path),
+            OutputFormat::Png => kitti::write_disparity(map, path)
+        }
+    }
+}
+
+impl FrameSummary {
+    /// Summarise a computed map.
+    pub fn new(name: &str, map: &DisparityMap, runtime: f64) -> Self {
+        let mut sum = 0.0f64;
+        let mut count = 0usize;
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if map.is_valid(x, y) {
+                    let d = map.get(x, y);
+
+                    sum += d as f64;
+                    count += 1;
+                    min = min.min(d);
+                    max = max.max(d);
+                }
+            }
+        }
+
+        let valid = count > 0;
+
+        Self {
+            name: name.into(),
+            width: map.width(),
+            height: map.height(),
+            runtime,
+            density: map.density(),
+            min_disparity: Some(min).filter(|_| valid),
+            max_disparity: Some(max).filter(|_| valid),
+            mean_disparity: Some((sum / count.max(1) as f64) as f32).filter(|_| valid),
+            smoothness: quality::smoothness(map),
+            error: None
+        }
+    }
+
+    /// Summary of a frame which failed.
+    pub fn failed(name: &str, error: &Error) -> Self {
+        Self {
+            name: name.into(),
+            width: 0,
+            height: 0,
+            runtime: 0.0,
+            density: 0.0,
+            min_disparity: None,
+            max_disparity: None,
+            mean_disparity: None,
+            smoothness: 0.0,
+            error: Some(error.to_string())
+        }
+    }
+
+    fn write_csv_row<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let optional = |v: Option<f32>| v.map(|v| v.to_string()).unwrap_or_default();
+
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&self.name),
+            self.width,
+            self.height,
+            self.runtime,
+            self.density,
+            optional(self.min_disparity),
+            optional(self.max_disparity),
+            optional(self.mean_disparity),
+            self.smoothness,
+            csv_field(self.error.as_deref().unwrap_or(""))
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Summary {
+    /// Total time spent computing the frames, in seconds.
+    pub fn total_runtime(&self) -> f64 {
+        self.frames.iter().map(|f| f.runtime).sum()
+    }
+
+    /// The frames which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &FrameSummary> {
+        self.frames.iter().filter(|f| f.error.is_some())
+    }
+
+    /// Save the summary, as JSON if the path ends in `.json` and as CSV otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => self.write_json_to(&mut writer)?,
+            _ => self.write_csv_to(&mut writer)?
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Write the summary as CSV, with a header row and one row per frame.
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+
+        for frame in &self.frames {
+            frame.write_csv_row(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the summary as a JSON object with a `frames` array.
+    pub fn write_json_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)?;
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Compute every pair under `input`, writing the maps to the mirrored tree under `output`.
+pub fn run<A: DisparityAlgorithm + ?Sized, P: AsRef<Path>, Q: AsRef<Path>>(
+    algorithm: &mut A,
+    input: P,
+    output: Q,
+    params: &Params
+) -> Result<Summary> {
+    let output = output.as_ref();
+    let mut summary = Summary::default();
+
+    for pair in find_pairs(input, params)? {
+        let name = pair.name.to_string_lossy().replace('\\', "/");
+
+        let frame = match compute_pair(algorithm, &pair, output, params) {
+            Ok((map, runtime)) => FrameSummary::new(&name, &map, runtime),
+            Err(e) => FrameSummary::failed(&name, &e)
+        };
+
+        summary.frames.push(frame);
+    }
+
+    Ok(summary)
+}
+
+/// Find the stereo pairs under the given root, sorted by name.
+///
+/// Left images without a matching right image are skipped.
+pub fn find_pairs<P: AsRef<Path>>(root: P, params: &Params) -> Result<Vec<Pair>> {
+    let left = split_pattern(&params.left_pattern)?;
+    let right = split_pattern(&params.right_pattern)?;
+
+    let mut pairs = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    let root = root.as_ref();
+
+    while let Some(rel_dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&rel_dir))? {
+            let path = entry?.path();
+            let file_name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(n) => n,
+                None => continue
+            };
+
+            if path.is_dir() {
+                if params.recursive {
+                    dirs.push(rel_dir.join(file_name));
+                }
+                continue;
+            }
+
+            let name = match match_pattern(file_name, left) {
+                Some(n) => n,
+                None => continue
+            };
+
+            let right_path = path.with_file_name(format!("{}{}{}", right.0, name, right.1));
+
+            if right_path.is_file() {
+                pairs.push(Pair {
+                    name: rel_dir.join(name),
+                    left: path.clone(),
+                    right: right_path
+                });
+            }
+        }
+    }
+
+    pairs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(pairs)
+}
+
+/// Load, compute and save a single pair, returning the map and the time taken to compute it.
+fn compute_pair<A: DisparityAlgorithm + ?Sized>(
+    algorithm: &mut A,
+    pair: &Pair,
+    output: &Path,
+    params: &Params
+) -> Result<(DisparityMap, f64)> {
+    let frame = ColourStereoFrame::from_dynamic(
+        &image::open(&pair.left)?,
+        &image::open(&pair.right)?
+    )?;
+
+    let start = Instant::now();
+    let map = algorithm.compute_colour(&frame)?;
+    let runtime = start.elapsed().as_secs_f64();
+
+    // Append to the frame name rather than setting an extension, in case the name has a dot
+    let base = output.join(&pair.name);
+    let out_path = |suffix: &str| {
+        let mut file_name = base.file_name().unwrap_or_default().to_os_string();
+        file_name.push(suffix);
+        base.with_file_name(file_name)
+    };
+
+    if let Some(dir) = base.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    params.format.write(&map, out_path(&format!(".{}", params.format.extension())))?;
+
+    if params.preview {
+        map.to_luma_normalised().save(out_path("_preview.png"))?;
+    }
+
+    Ok((map, runtime))
+}
+
+/// Split a file name pattern into the parts before and after its placeholder.
+fn split_pattern(pattern: &str) -> Result<(&str, &str)> {
+    match pattern.matches(PLACEHOLDER).count() {
+        1 => {
+            let i = pattern.find(PLACEHOLDER).unwrap();
+            Ok((&pattern[..i], &pattern[i + PLACEHOLDER.len()..]))
+        },
+        _ => Err(Error::InvalidParams(format!(
+            "file name pattern {:?} must contain exactly one {}", pattern, PLACEHOLDER
+        )))
+    }
+}
+
+/// The frame name a file name gives under the pattern, if it matches.
+fn match_pattern<'a>(file_name: &'a str, (prefix, suffix): (&str, &str)) -> Option<&'a str> {
+    if file_name.len() <= prefix.len() + suffix.len() {
+        return None;
+    }
+
+    file_name.strip_prefix(prefix)?.strip_suffix(suffix)
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline.
+fn csv_field(field: &str) -> String {
+    match field.contains(|c| c == ',' || c == '"' || c == '\n') {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.into()
+    }
+}
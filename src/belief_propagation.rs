@@ -0,0 +1,386 @@
+//! # Hierarchical belief propagation disparity computation
+//!
+//! This module provides a global stereo solver using the hierarchical loopy belief propagation
+//! algorithm from
+//! ("Efficient Belief Propagation for Early Vision")[https://doi.org/10.1007/s11263-006-7899-4]
+//! by Felzenszwalb and Huttenlocher.
+//!
+//! Disparity is modelled as a Markov random field on the 4-connected pixel grid, with a truncated
+//! data cost at each pixel and a truncated linear smoothness cost between neighbours. Messages
+//! are passed on a coarse to fine pyramid, with each level initialised from the one above, and a
+//! checkerboard schedule so that only half the messages are updated on each iteration. The
+//! truncated linear smoothness cost lets each message be computed in linear time in the number of
+//! disparities.
+//!
+//! This is an offline mode. Messages for all four directions are held for every pixel and
+//! disparity, which for a 640x480 frame with 64 disparities is around 300 MB.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::StereoFrame;
+use serde::Deserialize;
+
+use crate::colour::ColourStereoFrame;
+use crate::cost::CostFunction;
+use crate::cost_volume::{CostVolume, CostVolumeAlgorithm};
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Index of the messages a pixel receives from the neighbour above it.
+const FROM_UP: usize = 0;
+
+/// Index of the messages a pixel receives from the neighbour below it.
+const FROM_DOWN: usize = 1;
+
+/// Index of the messages a pixel receives from the neighbour to its left.
+const FROM_LEFT: usize = 2;
+
+/// Index of the messages a pixel receives from the neighbour to its right.
+const FROM_RIGHT: usize = 3;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct BeliefPropagation {
+    params: Params
+}
+
+/// Belief propagation parameters.
+///
+/// The defaults are the values from the paper rescaled to intensities in [0, 1].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Per-pixel matching cost used as the data cost.
+    pub cost: CostFunction,
+
+    /// Weight applied to the data cost.
+    pub data_weight: f32,
+
+    /// Truncation of the matching cost, before weighting.
+    pub data_truncation: f32,
+
+    /// Smoothness cost per unit of disparity difference between neighbours.
+    pub smoothness_weight: f32,
+
+    /// Truncation of the smoothness cost.
+    pub smoothness_truncation: f32,
+
+    /// Number of levels in the pyramid.
+    pub levels: usize,
+
+    /// Number of message passing iterations at each level.
+    pub iterations: usize
+}
+
+/// Data costs and messages for one level of the pyramid.
+struct Level {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+    messages: [Vec<f32>; 4]
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            max_disparity: 64,
+            cost: CostFunction::AbsDiff,
+            data_weight: 0.07 * 255.0,
+            data_truncation: 15.0 / 255.0,
+            smoothness_weight: 1.0,
+            smoothness_truncation: 1.7,
+            levels: 5,
+            iterations: 5
+        }
+    }
+}
+
+impl Level {
+    fn new(width: usize, height: usize, num_disp: usize, data: Vec<f32>) -> Self {
+        let size = width * height * num_disp;
+
+        Self {
+            width,
+            height,
+            data,
+            messages: [vec![0.0; size], vec![0.0; size], vec![0.0; size], vec![0.0; size]]
+        }
+    }
+
+    /// Build the next coarser level, whose data costs are the sums of each 2x2 block.
+    fn coarser(&self, num_disp: usize) -> Self {
+        let width = (self.width + 1) / 2;
+        let height = (self.height + 1) / 2;
+
+        let mut data = vec![0.0f32; width * height * num_disp];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let src = (y * self.width + x) * num_disp;
+                let dst = ((y / 2) * width + x / 2) * num_disp;
+
+                for k in 0..num_disp {
+                    data[dst + k] += self.data[src + k];
+                }
+            }
+        }
+
+        Self::new(width, height, num_disp, data)
+    }
+
+    /// Initialise the messages of this level from those of the next coarser level.
+    fn init_from(&mut self, coarser: &Level, num_disp: usize) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let dst = (y * self.width + x) * num_disp;
+                let src = ((y / 2) * coarser.width + x / 2) * num_disp;
+
+                for (fine, coarse) in self.messages.iter_mut().zip(coarser.messages.iter()) {
+                    fine[dst..dst + num_disp].copy_from_slice(&coarse[src..src + num_disp]);
+                }
+            }
+        }
+    }
+
+    /// Sum the data cost and the incoming messages at pixel `p`, leaving out the messages from
+    /// the direction `exclude`.
+    fn gather(&self, p: usize, num_disp: usize, exclude: Option<usize>, out: &mut [f32]) {
+        let idx = p * num_disp;
+        out.copy_from_slice(&self.data[idx..idx + num_disp]);
+
+        for (dir, msgs) in self.messages.iter().enumerate() {
+            if Some(dir) == exclude {
+                continue;
+            }
+
+            for (o, m) in out.iter_mut().zip(msgs[idx..idx + num_disp].iter()) {
+                *o += m;
+            }
+        }
+    }
+}
+
+impl BeliefPropagation {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        Self { params }
+    }
+
+    /// Compute the truncated, weighted data costs of the full resolution frame.
+    ///
+    /// Disparities which would place the match outside the right image get the maximum cost.
+    /// If the colour frame is given, the greyscale frame must be its greyscale frame.
+    fn data_costs(
+        &self,
+        frame: &StereoFrame,
+        colour: Option<&ColourStereoFrame>,
+        num_disp: usize
+    ) -> Result<Vec<f32>> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let cost = match colour {
+            Some(c) => self.params.cost.build_colour(c)?,
+            None => self.params.cost.build(frame)?
+        };
+        let max_cost = self.params.data_weight * self.params.data_truncation;
+
+        let mut data = vec![max_cost; width * height * num_disp];
+
+        for y in 0..height {
+            for x in 0..width {
+                let idx = (y * width + x) * num_disp;
+
+                for k in 0..num_disp {
+                    let d = k + self.params.min_disparity;
+
+                    if d > x {
+                        break;
+                    }
+
+                    data[idx + k] = self.params.data_weight
+                        * cost.cost(x, y, d).min(self.params.data_truncation);
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Compute the message sent by a node whose summed costs are `h`.
+    ///
+    /// The truncated linear smoothness cost is applied with a forward and backward pass of the
+    /// distance transform, and the message is normalised to zero mean.
+    fn message(&self, h: &[f32], out: &mut [f32]) {
+        let c = self.params.smoothness_weight;
+        let n = out.len();
+
+        out.copy_from_slice(h);
+
+        for k in 1..n {
+            out[k] = out[k].min(out[k - 1] + c);
+        }
+        for k in (0..n - 1).rev() {
+            out[k] = out[k].min(out[k + 1] + c);
+        }
+
+        let min_h = h.iter().cloned().fold(f32::INFINITY, f32::min);
+        let trunc = min_h + self.params.smoothness_truncation;
+
+        let mut sum = 0.0f32;
+        for o in out.iter_mut() {
+            *o = o.min(trunc);
+            sum += *o;
+        }
+
+        let mean = sum / n as f32;
+        for o in out.iter_mut() {
+            *o -= mean;
+        }
+    }
+
+    /// Update the messages sent by every pixel of the given checkerboard parity.
+    fn update(&self, level: &mut Level, num_disp: usize, parity: usize) {
+        let width = level.width;
+        let height = level.height;
+
+        let mut h = vec![0.0f32; num_disp];
+        let mut out = vec![0.0f32; num_disp];
+
+        for y in 0..height {
+            for x in 0..width {
+                if (x + y) % 2 != parity {
+                    continue;
+                }
+
+                let p = y * width + x;
+
+                // Each entry is (receiver, direction the receiver hears from, direction left out)
+                let sends = [
+                    (if y > 0 { Some(p - width) } else { None }, FROM_DOWN, FROM_UP),
+                    (if y + 1 < height { Some(p + width) } else { None }, FROM_UP, FROM_DOWN),
+                    (if x > 0 { Some(p - 1) } else { None }, FROM_RIGHT, FROM_LEFT),
+                    (if x + 1 < width { Some(p + 1) } else { None }, FROM_LEFT, FROM_RIGHT)
+                ];
+
+                for &(receiver, dir, exclude) in sends.iter() {
+                    let q = match receiver {
+                        Some(q) => q,
+                        None => continue
+                    };
+
+                    level.gather(p, num_disp, Some(exclude), &mut h);
+                    self.message(&h, &mut out);
+
+                    level.messages[dir][q * num_disp..(q + 1) * num_disp].copy_from_slice(&out);
+                }
+            }
+        }
+    }
+
+    /// Run message passing over the pyramid and return the final beliefs at full resolution.
+    fn beliefs(
+        &self,
+        frame: &StereoFrame,
+        colour: Option<&ColourStereoFrame>
+    ) -> Result<CostVolume> {
+        if self.params.max_disparity <= self.params.min_disparity {
+            return Err(Error::InvalidParams(
+                "max_disparity must be greater than min_disparity".into()
+            ));
+        }
+
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+        let num_disp = self.params.max_disparity - self.params.min_disparity;
+
+        // ---- PYRAMID ----
+
+        let data = self.data_costs(frame, colour, num_disp)?;
+        let mut levels = vec![Level::new(width, height, num_disp, data)];
+
+        for _ in 1..self.params.levels.max(1) {
+            let next = levels[levels.len() - 1].coarser(num_disp);
+            levels.push(next);
+        }
+
+        // ---- MESSAGE PASSING ----
+
+        // Work from the coarsest level down, initialising each level from the one above
+        for l in (0..levels.len()).rev() {
+            if l + 1 < levels.len() {
+                let (fine, coarse) = levels.split_at_mut(l + 1);
+                fine[l].init_from(&coarse[0], num_disp);
+            }
+
+            for iter in 0..self.params.iterations {
+                self.update(&mut levels[l], num_disp, iter % 2);
+            }
+        }
+
+        // ---- BELIEFS ----
+
+        let mut beliefs = CostVolume::new(
+            width, height,
+            self.params.min_disparity..self.params.max_disparity
+        );
+
+        for y in 0..height {
+            for x in 0..width {
+                levels[0].gather(y * width + x, num_disp, None, beliefs.costs_mut(x, y));
+            }
+        }
+
+        Ok(beliefs)
+    }
+}
+
+impl DisparityAlgorithm for BeliefPropagation {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        Ok(self.beliefs(frame, None)?.wta())
+    }
+
+    /// Compute the disparity map for the given colour frame, with the data cost averaged over
+    /// the channels.
+    fn compute_colour(&mut self, frame: &ColourStereoFrame) -> Result<DisparityMap> {
+        Ok(self.beliefs(&frame.grey, Some(frame))?.wta())
+    }
+
+    /// Belief propagation is global, so no finite margin makes tiled results match whole frame
+    /// results. The margin covers the disparity range and one pixel on the coarsest level.
+    fn margins(&self) -> Margins {
+        let scale = 1 << self.params.levels.max(1).saturating_sub(1).min(16);
+
+        Margins {
+            left: scale + self.params.max_disparity,
+            right: scale,
+            top: scale,
+            bottom: scale
+        }
+    }
+}
+
+impl CostVolumeAlgorithm for BeliefPropagation {
+    /// The final beliefs, the data cost plus all incoming messages, at every pixel.
+    fn cost_volume(&mut self, frame: &StereoFrame) -> Result<CostVolume> {
+        self.beliefs(frame, None)
+    }
+}
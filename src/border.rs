@@ -0,0 +1,84 @@
+//! # Border handling
+//!
+//! This module provides the border modes algorithms use when a correlation window extends past
+//! the edge of an image.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::GrayFloatImage;
+use serde::Deserialize;
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// How pixels outside an image are treated when a window overlaps its edge.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum BorderMode {
+    /// Pixels whose window leaves the image are not estimated and are marked invalid.
+    Invalid,
+
+    /// Pixels outside the image take the value of the nearest edge pixel.
+    Replicate,
+
+    /// Pixels outside the image are mirrored about the edge pixel, which is not repeated.
+    Reflect,
+
+    /// The window is shrunk to the part which lies inside both images, and the criterion scaled
+    /// up to the full window size so that it can be compared with full windows.
+    ShrinkWindow
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for BorderMode {
+    fn default() -> Self {
+        BorderMode::Invalid
+    }
+}
+
+impl BorderMode {
+    /// Map a possibly out of bounds coordinate onto an axis of length `len`.
+    ///
+    /// Returns `None` if the coordinate is outside the axis and the mode does not extend images.
+    pub fn map_coord(&self, i: isize, len: usize) -> Option<usize> {
+        if i >= 0 && (i as usize) < len {
+            return Some(i as usize);
+        }
+
+        if len == 0 {
+            return None;
+        }
+
+        match self {
+            BorderMode::Invalid | BorderMode::ShrinkWindow => None,
+            BorderMode::Replicate => Some(i.max(0).min(len as isize - 1) as usize),
+            BorderMode::Reflect => {
+                if len == 1 {
+                    return Some(0);
+                }
+
+                let period = 2 * (len as isize - 1);
+                let mut i = i.rem_euclid(period);
+
+                if i >= len as isize {
+                    i = period - i;
+                }
+
+                Some(i as usize)
+            }
+        }
+    }
+
+    /// Sample an image at a possibly out of bounds position.
+    pub fn sample(&self, image: &GrayFloatImage, x: isize, y: isize) -> Option<f32> {
+        let x = self.map_coord(x, image.width() as usize)?;
+        let y = self.map_coord(y, image.height() as usize)?;
+
+        Some(image.get(x, y))
+    }
+}
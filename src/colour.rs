@@ -0,0 +1,155 @@
+//! # Colour stereo input
+//!
+//! This module provides colour images and stereo frames, so that algorithms can match on all
+//! three channels of colour captures rather than on intensity alone.
+//!
+//! A colour frame always carries the greyscale frame alongside it. Algorithms without colour
+//! support match on the greyscale frame, which keeps greyscale as the fast path, while those with
+//! colour support sum per-channel costs or use colour differences in their support weights.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use image::DynamicImage;
+
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A floating point RGB image, with each channel stored as a separate plane in [0, 1].
+pub struct ColourImage {
+    channels: [GrayFloatImage; 3]
+}
+
+/// A stereo frame of colour images, along with their greyscale equivalent.
+pub struct ColourStereoFrame {
+    /// The greyscale frame, used by algorithms which do not support colour.
+    pub grey: StereoFrame,
+
+    pub left: ColourImage,
+    pub right: ColourImage
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl ColourImage {
+    /// Create a new black image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            channels: [
+                GrayFloatImage::new(width, height),
+                GrayFloatImage::new(width, height),
+                GrayFloatImage::new(width, height)
+            ]
+        }
+    }
+
+    /// Convert a dynamic image to a colour image. Greyscale images give three equal channels.
+    pub fn from_dynamic(image: &DynamicImage) -> Self {
+        let rgb = image.to_rgb8();
+        let (width, height) = rgb.dimensions();
+
+        let mut colour = Self::new(width as usize, height as usize);
+
+        for (x, y, pixel) in rgb.enumerate_pixels() {
+            colour.put(
+                x as usize, y as usize,
+                [
+                    pixel[0] as f32 / 255.0,
+                    pixel[1] as f32 / 255.0,
+                    pixel[2] as f32 / 255.0
+                ]
+            );
+        }
+
+        colour
+    }
+
+    pub fn width(&self) -> usize {
+        self.channels[0].width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.channels[0].height() as usize
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [f32; 3] {
+        [
+            self.channels[0].get(x, y),
+            self.channels[1].get(x, y),
+            self.channels[2].get(x, y)
+        ]
+    }
+
+    pub fn put(&mut self, x: usize, y: usize, val: [f32; 3]) {
+        for (channel, v) in self.channels.iter_mut().zip(val.iter()) {
+            channel.put(x, y, *v);
+        }
+    }
+
+    /// A single channel of the image, with 0, 1 and 2 being red, green and blue.
+    pub fn channel(&self, index: usize) -> &GrayFloatImage {
+        &self.channels[index]
+    }
+}
+
+impl ColourStereoFrame {
+    /// Create a new colour frame from a pair of dynamic images, with zero timestamps.
+    pub fn from_dynamic(left: &DynamicImage, right: &DynamicImage) -> Result<Self> {
+        let grey = StereoFrame {
+            left: GrayFloatImage::from_dynamic(left),
+            left_timestamp: 0,
+            right: GrayFloatImage::from_dynamic(right),
+            right_timestamp: 0
+        };
+
+        Self::new(grey, ColourImage::from_dynamic(left), ColourImage::from_dynamic(right))
+    }
+
+    /// Create a new colour frame from its greyscale frame and colour images, which must all be
+    /// the same size.
+    pub fn new(grey: StereoFrame, left: ColourImage, right: ColourImage) -> Result<Self> {
+        let size = (grey.width() as usize, grey.height() as usize);
+
+        let sizes = [
+            (grey.right.width() as usize, grey.right.height() as usize),
+            (left.width(), left.height()),
+            (right.width(), right.height())
+        ];
+
+        if sizes.iter().any(|&s| s != size) {
+            return Err(Error::InvalidParams(format!(
+                "colour frame images must all be {:?} pixels", size
+            )));
+        }
+
+        Ok(Self { grey, left, right })
+    }
+
+    pub fn width(&self) -> usize {
+        self.left.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.left.height()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Euclidean distance between two colours.
+pub fn colour_distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(p, q)| (p - q) * (p - q))
+        .sum::<f32>()
+        .sqrt()
+}
@@ -0,0 +1,173 @@
+//! # Pipeline configuration
+//!
+//! This module loads a disparity pipeline from a TOML or JSON config file, so that the same file
+//! can drive tests, benches and the command line interface:
+//!
+//! ```toml
+//! algorithm = "mcmanamon"
+//! left_right_threshold = 1.0
+//!
+//! [params]
+//! min_disparity = 0
+//! max_disparity = 100
+//! dyn_disparity_threshold = 10
+//! correlation_window_size = [11, 11]
+//! border_mode = "Invalid"
+//!
+//! [[pre]]
+//! filter = "gaussian"
+//! sigma = 0.8
+//!
+//! [[post]]
+//! filter = "weighted_median"
+//! radius = 3
+//! ```
+//!
+//! The `params` table is the algorithm's `Params`, see [`crate::registry`]. Each `pre` and `post`
+//! table names a filter and gives its parameters, with missing fields taking their defaults. The
+//! filters are applied in the order they are listed. A JSON config has the same structure, with
+//! `pre` and `post` as arrays of objects.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::*;
+use crate::pipeline::{Gaussian, ImageFilter, Normalise, Pipeline};
+use crate::refine::{
+    DisparityFilter, FastGlobalSmoother, Guided, JointBilateral, WeightedMedian
+};
+use crate::registry;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A disparity pipeline config.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// Name of the algorithm, see [`registry::ALGORITHMS`].
+    pub algorithm: String,
+
+    /// Parameters of the algorithm, which may be left out if they have defaults.
+    pub params: Option<toml::Value>,
+
+    /// Filters applied to the images before matching.
+    #[serde(default)]
+    pub pre: Vec<PreFilter>,
+
+    /// Filters applied to the disparity map after matching.
+    #[serde(default)]
+    pub post: Vec<PostFilter>,
+
+    /// Threshold of the left-right consistency check, which is skipped if not given.
+    pub left_right_threshold: Option<f32>
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// A pre-filter, named by the `filter` field of its table.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "filter", rename_all = "snake_case")]
+pub enum PreFilter {
+    Gaussian(Gaussian),
+    Normalise(Normalise)
+}
+
+/// A post-filter, named by the `filter` field of its table.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "filter", rename_all = "snake_case")]
+pub enum PostFilter {
+    JointBilateral(JointBilateral),
+    Guided(Guided),
+    WeightedMedian(WeightedMedian),
+    FastGlobalSmoother(FastGlobalSmoother)
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Config {
+    /// Read a config file, which is JSON if its extension is `.json` and TOML otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&config)
+                .map_err(|e| Error::Format(format!("{}: {}", path.display(), e))),
+            _ => toml::from_str(&config)
+                .map_err(|e| Error::Format(format!("{}: {}", path.display(), e)))
+        }
+    }
+
+    /// Parse a config from a TOML string.
+    pub fn parse(config: &str) -> Result<Self> {
+        toml::from_str(config).map_err(|e| Error::Format(e.to_string()))
+    }
+
+    /// Parse a config from a JSON string.
+    pub fn parse_json(config: &str) -> Result<Self> {
+        serde_json::from_str(config).map_err(|e| Error::Format(e.to_string()))
+    }
+
+    /// A config running only the given algorithm.
+    pub fn new(name: &str, params: Option<toml::Value>) -> Self {
+        Self {
+            algorithm: name.into(),
+            params,
+            pre: Vec::new(),
+            post: Vec::new(),
+            left_right_threshold: None
+        }
+    }
+
+    /// Build the pipeline the config describes.
+    pub fn build(&self) -> Result<Pipeline> {
+        let mut pipeline = Pipeline::new(
+            registry::build(&self.algorithm, self.params.as_ref())?
+        );
+
+        for filter in &self.pre {
+            pipeline = pipeline.pre_filter(filter.build());
+        }
+
+        for filter in &self.post {
+            pipeline = pipeline.post_filter(filter.build());
+        }
+
+        if let Some(threshold) = self.left_right_threshold {
+            pipeline = pipeline.left_right_check(threshold);
+        }
+
+        Ok(pipeline)
+    }
+}
+
+impl PreFilter {
+    pub fn build(&self) -> Box<dyn ImageFilter> {
+        match self {
+            PreFilter::Gaussian(f) => Box::new(f.clone()),
+            PreFilter::Normalise(f) => Box::new(f.clone())
+        }
+    }
+}
+
+impl PostFilter {
+    pub fn build(&self) -> Box<dyn DisparityFilter> {
+        match self {
+            PostFilter::JointBilateral(f) => Box::new(f.clone()),
+            PostFilter::Guided(f) => Box::new(f.clone()),
+            PostFilter::WeightedMedian(f) => Box::new(f.clone()),
+            PostFilter::FastGlobalSmoother(f) => Box::new(f.clone())
+        }
+    }
+}
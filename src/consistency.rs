@@ -0,0 +1,117 @@
+//! # Left-right consistency checking
+//!
+//! This module provides the left-right consistency check shared by algorithms which compute
+//! disparity maps referenced to both images.
+//!
+//! A left-referenced map gives, for the left pixel `x`, the disparity `d` of its match at `x - d`
+//! in the right image. A right-referenced map gives, for the right pixel `x`, the disparity of its
+//! match at `x + d` in the left image. A left pixel is consistent when the right pixel it matches
+//! maps back to it, within a threshold.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::StereoFrame;
+
+use crate::disparity::{DisparityAlgorithm, DisparityMap, Validity};
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Check a left-referenced map against a right-referenced map of the same size, removing the left
+/// pixels which fail.
+///
+/// Pixels whose match falls outside the right image are marked as occluded, other failures are
+/// marked as invalid, and the values of both are reset. Returns the number of pixels removed.
+pub fn left_right_check(left: &mut DisparityMap, right: &DisparityMap, threshold: f32) -> usize {
+    let mut removed = 0;
+
+    for y in 0..left.height() {
+        for x in 0..left.width() {
+            if let Some(validity) = check_pixel(left, right, x, y, threshold) {
+                match validity {
+                    Validity::Occluded => left.occlude(x, y),
+                    _ => left.invalidate(x, y)
+                }
+                removed += 1;
+            }
+        }
+    }
+
+    removed
+}
+
+/// Fraction of the valid pixels of a left-referenced map which pass the left-right check against
+/// a right-referenced map, without modifying either map.
+///
+/// Returns zero if the left map has no valid pixels.
+pub fn consistency_rate(left: &DisparityMap, right: &DisparityMap, threshold: f32) -> f32 {
+    let mut valid = 0usize;
+    let mut consistent = 0usize;
+
+    for y in 0..left.height() {
+        for x in 0..left.width() {
+            if !left.is_valid(x, y) {
+                continue;
+            }
+
+            valid += 1;
+
+            if check_pixel(left, right, x, y, threshold).is_none() {
+                consistent += 1;
+            }
+        }
+    }
+
+    match valid {
+        0 => 0.0,
+        _ => consistent as f32 / valid as f32
+    }
+}
+
+/// Compute the left and right-referenced maps of a frame with the given algorithm, and return
+/// the left map with the pixels failing the left-right check removed.
+pub fn compute_checked<A: DisparityAlgorithm + ?Sized>(
+    algorithm: &mut A,
+    frame: &StereoFrame,
+    threshold: f32
+) -> Result<DisparityMap> {
+    let mut left = algorithm.compute(frame)?;
+    let right = algorithm.compute_right(frame)?;
+
+    left_right_check(&mut left, &right, threshold);
+    left.update_range();
+
+    Ok(left)
+}
+
+/// Check a single left pixel, returning the validity it should be given if it fails, or `None`
+/// if it is invalid already or passes.
+fn check_pixel(
+    left: &DisparityMap,
+    right: &DisparityMap,
+    x: usize,
+    y: usize,
+    threshold: f32
+) -> Option<Validity> {
+    if !left.is_valid(x, y) {
+        return None;
+    }
+
+    let d = left.get(x, y);
+    let xr = (x as f32 - d).round();
+
+    if xr < 0.0 || xr >= right.width() as f32 {
+        return Some(Validity::Occluded);
+    }
+
+    let xr = xr as usize;
+
+    match right.is_valid(xr, y) && (right.get(xr, y) - d).abs() <= threshold {
+        true => None,
+        false => Some(Validity::Invalid)
+    }
+}
@@ -0,0 +1,311 @@
+//! # Matching costs
+//!
+//! This module provides the per-pixel matching costs shared by the aggregation based algorithms,
+//! along with winner-take-all selection of the best disparity from a curve of aggregated costs.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use serde::Deserialize;
+
+use crate::border::BorderMode;
+use crate::colour::ColourStereoFrame;
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// A cost of matching a pixel in the left image with a pixel in the right image.
+pub trait MatchingCost {
+    /// Cost of matching left pixel `(x, y)` with right pixel `(x - d, y)`.
+    ///
+    /// Callers must ensure that `d <= x`.
+    fn cost(&self, x: usize, y: usize, d: usize) -> f32;
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Selectable pixel matching cost functions.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum CostFunction {
+    /// Absolute intensity difference.
+    AbsDiff,
+
+    /// Absolute intensity difference, truncated at the given value.
+    TruncatedAbsDiff(f32),
+
+    /// Squared intensity difference.
+    SquaredDiff,
+
+    /// Hamming distance between census transforms over a (width, height) window.
+    Census(usize, usize)
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Absolute difference cost, optionally truncated.
+pub struct AbsDiff<'a> {
+    frame: &'a StereoFrame,
+    truncation: f32
+}
+
+/// Squared difference cost.
+pub struct SquaredDiff<'a> {
+    frame: &'a StereoFrame
+}
+
+/// Absolute difference cost on colour images, averaged over the channels so that it has the same
+/// scale as the greyscale cost, and optionally truncated.
+pub struct ColourAbsDiff<'a> {
+    frame: &'a ColourStereoFrame,
+    truncation: f32
+}
+
+/// Squared difference cost on colour images, averaged over the channels.
+pub struct ColourSquaredDiff<'a> {
+    frame: &'a ColourStereoFrame
+}
+
+/// Census transform cost.
+///
+/// Each pixel is described by a bit string recording which of its neighbours in the census
+/// window are darker than it, and the cost is the Hamming distance between the strings. Being
+/// based only on the ordering of intensities it is robust to gain and bias differences between
+/// the cameras.
+pub struct Census {
+    width: usize,
+    left: Vec<u64>,
+    right: Vec<u64>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for CostFunction {
+    fn default() -> Self {
+        CostFunction::AbsDiff
+    }
+}
+
+impl CostFunction {
+    /// Build the matching cost for the given frame.
+    pub fn build<'a>(&self, frame: &'a StereoFrame) -> Result<Box<dyn MatchingCost + 'a>> {
+        Ok(match *self {
+            CostFunction::AbsDiff => Box::new(AbsDiff::new(frame, f32::INFINITY)),
+            CostFunction::TruncatedAbsDiff(t) => Box::new(AbsDiff::new(frame, t)),
+            CostFunction::SquaredDiff => Box::new(SquaredDiff::new(frame)),
+            CostFunction::Census(w, h) => Box::new(Census::new(frame, (w, h))?)
+        })
+    }
+
+    /// Build the matching cost for the given colour frame.
+    ///
+    /// Difference costs use every channel. The census transform only depends on the ordering of
+    /// intensities, so it is built from the greyscale frame.
+    pub fn build_colour<'a>(
+        &self,
+        frame: &'a ColourStereoFrame
+    ) -> Result<Box<dyn MatchingCost + 'a>> {
+        Ok(match *self {
+            CostFunction::AbsDiff => Box::new(ColourAbsDiff::new(frame, f32::INFINITY)),
+            CostFunction::TruncatedAbsDiff(t) => Box::new(ColourAbsDiff::new(frame, t)),
+            CostFunction::SquaredDiff => Box::new(ColourSquaredDiff::new(frame)),
+            CostFunction::Census(w, h) => Box::new(Census::new(&frame.grey, (w, h))?)
+        })
+    }
+}
+
+impl<'a> AbsDiff<'a> {
+    /// Create a new absolute difference cost, truncated at `truncation`.
+    ///
+    /// Use `f32::INFINITY` for no truncation.
+    pub fn new(frame: &'a StereoFrame, truncation: f32) -> Self {
+        Self { frame, truncation }
+    }
+}
+
+impl<'a> MatchingCost for AbsDiff<'a> {
+    fn cost(&self, x: usize, y: usize, d: usize) -> f32 {
+        (self.frame.left.get(x, y) - self.frame.right.get(x - d, y))
+            .abs()
+            .min(self.truncation)
+    }
+}
+
+impl<'a> SquaredDiff<'a> {
+    pub fn new(frame: &'a StereoFrame) -> Self {
+        Self { frame }
+    }
+}
+
+impl<'a> MatchingCost for SquaredDiff<'a> {
+    fn cost(&self, x: usize, y: usize, d: usize) -> f32 {
+        let diff = self.frame.left.get(x, y) - self.frame.right.get(x - d, y);
+        diff * diff
+    }
+}
+
+impl<'a> ColourAbsDiff<'a> {
+    /// Create a new colour absolute difference cost, truncated at `truncation`.
+    ///
+    /// Use `f32::INFINITY` for no truncation.
+    pub fn new(frame: &'a ColourStereoFrame, truncation: f32) -> Self {
+        Self { frame, truncation }
+    }
+}
+
+impl<'a> MatchingCost for ColourAbsDiff<'a> {
+    fn cost(&self, x: usize, y: usize, d: usize) -> f32 {
+        let left = self.frame.left.get(x, y);
+        let right = self.frame.right.get(x - d, y);
+
+        let sum: f32 = left.iter().zip(right.iter()).map(|(l, r)| (l - r).abs()).sum();
+
+        (sum / 3.0).min(self.truncation)
+    }
+}
+
+impl<'a> ColourSquaredDiff<'a> {
+    pub fn new(frame: &'a ColourStereoFrame) -> Self {
+        Self { frame }
+    }
+}
+
+impl<'a> MatchingCost for ColourSquaredDiff<'a> {
+    fn cost(&self, x: usize, y: usize, d: usize) -> f32 {
+        let left = self.frame.left.get(x, y);
+        let right = self.frame.right.get(x - d, y);
+
+        left.iter().zip(right.iter()).map(|(l, r)| (l - r) * (l - r)).sum::<f32>() / 3.0
+    }
+}
+
+impl Census {
+    /// Compute the census transforms of both images in the frame over the given window.
+    ///
+    /// The window may hold at most 65 pixels, so that each bit string fits in a `u64`. Window
+    /// pixels outside the image replicate the nearest edge pixel.
+    pub fn new(frame: &StereoFrame, window: (usize, usize)) -> Result<Self> {
+        if window.0 * window.1 > 65 || window.0 % 2 == 0 || window.1 % 2 == 0 {
+            return Err(Error::InvalidParams(format!(
+                "census window must be odd and hold at most 65 pixels, got {:?}", window
+            )));
+        }
+
+        Ok(Self {
+            width: frame.left.width() as usize,
+            left: census_transform(&frame.left, window),
+            right: census_transform(&frame.right, window)
+        })
+    }
+}
+
+impl MatchingCost for Census {
+    fn cost(&self, x: usize, y: usize, d: usize) -> f32 {
+        let idx = y * self.width + x;
+
+        (self.left[idx] ^ self.right[idx - d]).count_ones() as f32
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Find the position of the minimum of the criterion curve, with sub pixel interpolation.
+///
+/// The returned value is relative to the first element of `crits`.
+pub(crate) fn subpixel_minimum(crits: &[f32]) -> f32 {
+    // Find index of minimum value
+    let min_index = crits
+        .iter()
+        .enumerate()
+        .fold(0, |min_idx, (idx, &val)| {
+            if val < crits[min_idx] {
+                idx
+            }
+            else {
+                min_idx
+            }
+        });
+
+    // If on the outer edge of the criterion
+    if min_index == 0 || min_index == crits.len() - 1 || crits.len() < 3 {
+        return min_index as f32;
+    }
+
+    // Get left and right values of the criterion
+    let c_left = crits[min_index - 1];
+    let c_right = crits[min_index + 1];
+
+    // If left is higher than right
+    let denom = match c_left > c_right {
+        true => 2.0 * (c_left - crits[min_index]),
+        false => 2.0 * (c_right - crits[min_index])
+    };
+
+    min_index as f32 + ((c_left - c_right) / denom)
+}
+
+/// Horizontal central difference gradient of an image in row-major order, with the border
+/// replicated.
+pub(crate) fn gradient_x(image: &GrayFloatImage) -> Vec<f32> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+
+    let mut grad = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let prev = image.get(x.saturating_sub(1), y);
+            let next = image.get((x + 1).min(width - 1), y);
+
+            grad.push((next - prev) / 2.0);
+        }
+    }
+
+    grad
+}
+
+/// Compute the census transform of an image over the given (width, height) window.
+fn census_transform(image: &GrayFloatImage, window: (usize, usize)) -> Vec<u64> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+    let semi_width = (window.0 / 2) as isize;
+    let semi_height = (window.1 / 2) as isize;
+
+    let mut transform = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let centre = image.get(x, y);
+            let mut bits = 0u64;
+
+            for j in -semi_height..semi_height + 1 {
+                for i in -semi_width..semi_width + 1 {
+                    if i == 0 && j == 0 {
+                        continue;
+                    }
+
+                    let val = BorderMode::Replicate
+                        .sample(image, x as isize + i, y as isize + j)
+                        .unwrap_or(centre);
+
+                    bits = (bits << 1) | (val < centre) as u64;
+                }
+            }
+
+            transform.push(bits);
+        }
+    }
+
+    transform
+}
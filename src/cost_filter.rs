@@ -0,0 +1,165 @@
+//! # Cost volume filtering disparity computation
+//!
+//! This module provides the cost volume filtering approach from
+//! ("Fast Cost-Volume Filtering for Visual Correspondence and Beyond")[https://doi.org/10.1109/TPAMI.2012.156]
+//! by Hosni et al.
+//!
+//! A cost slice is computed for each disparity, mixing truncated absolute differences of the
+//! intensities and of the horizontal gradients. Each slice is then smoothed with a guided filter
+//! using the left image as the guide, which aggregates costs over edge-aware support regions at a
+//! cost independent of their size, and the disparity is chosen by winner-take-all.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::StereoFrame;
+use serde::Deserialize;
+
+use crate::cost::gradient_x;
+use crate::cost_volume::{CostVolume, CostVolumeAlgorithm};
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::guided_filter::GuidedFilter;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct CostFilter {
+    params: Params
+}
+
+/// Cost volume filtering parameters.
+///
+/// The defaults are the values from the paper rescaled to intensities in [0, 1].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Weight of the gradient cost against the intensity cost, in [0, 1].
+    pub alpha: f32,
+
+    /// Truncation of the intensity cost.
+    pub tau_intensity: f32,
+
+    /// Truncation of the gradient cost.
+    pub tau_gradient: f32,
+
+    /// Radius of the guided filter window.
+    pub radius: usize,
+
+    /// Guided filter regularisation, in squared intensity units.
+    pub epsilon: f32
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            max_disparity: 64,
+            alpha: 0.9,
+            tau_intensity: 7.0 / 255.0,
+            tau_gradient: 2.0 / 255.0,
+            radius: 9,
+            epsilon: 0.0001
+        }
+    }
+}
+
+impl CostFilter {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        Self { params }
+    }
+
+    /// Compute the filtered cost volume for the given frame.
+    fn filtered_volume(&self, frame: &StereoFrame) -> Result<CostVolume> {
+        if self.params.max_disparity <= self.params.min_disparity {
+            return Err(Error::InvalidParams(
+                "max_disparity must be greater than min_disparity".into()
+            ));
+        }
+
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let left_grad = gradient_x(&frame.left);
+        let right_grad = gradient_x(&frame.right);
+
+        let filter = GuidedFilter::new(&frame.left, self.params.radius, self.params.epsilon);
+
+        // Matches outside the right image get the largest possible cost
+        let (alpha, tau_i, tau_g) =
+            (self.params.alpha, self.params.tau_intensity, self.params.tau_gradient);
+        let max_cost = (1.0 - alpha) * tau_i + alpha * tau_g;
+
+        let mut volume = CostVolume::new(
+            width, height,
+            self.params.min_disparity..self.params.max_disparity
+        );
+        let mut slice = vec![0.0f32; width * height];
+
+        for d in volume.disparities() {
+
+            // ---- COST SLICE ----
+
+            for y in 0..height {
+                for x in 0..width {
+                    let i = y * width + x;
+
+                    slice[i] = match x >= d {
+                        true => {
+                            let intensity = (frame.left.get(x, y) - frame.right.get(x - d, y))
+                                .abs()
+                                .min(tau_i);
+                            let gradient = (left_grad[i] - right_grad[i - d]).abs().min(tau_g);
+
+                            (1.0 - alpha) * intensity + alpha * gradient
+                        },
+                        false => max_cost
+                    };
+                }
+            }
+
+            // ---- FILTERING ----
+
+            volume.set_slice(d, &filter.filter(&slice));
+        }
+
+        Ok(volume)
+    }
+}
+
+impl DisparityAlgorithm for CostFilter {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        Ok(self.filtered_volume(frame)?.wta())
+    }
+
+    /// Margins covering the guided filter, which is two box filters deep.
+    fn margins(&self) -> Margins {
+        let reach = 2 * self.params.radius;
+
+        Margins {
+            left: reach + self.params.max_disparity,
+            right: reach,
+            top: reach,
+            bottom: reach
+        }
+    }
+}
+
+impl CostVolumeAlgorithm for CostFilter {
+    /// The guided filtered cost volume.
+    fn cost_volume(&mut self, frame: &StereoFrame) -> Result<CostVolume> {
+        self.filtered_volume(frame)
+    }
+}
@@ -0,0 +1,258 @@
+//! # Cost volumes
+//!
+//! This module provides an explicit W x H x D cost volume, holding the matching cost of every
+//! pixel at every disparity in a range. Algorithms which build a volume can emit it through the
+//! [`CostVolumeAlgorithm`] trait, so that it can be filtered, saved to disk as a NumPy `.npy`
+//! array, or sliced at a single pixel to inspect the cost curve behind a mismatch.
+//!
+//! Costs are stored pixel by pixel, with the disparities of each pixel contiguous, which is the
+//! C order of an array of shape (height, width, disparities). Lower costs are better. Costs which
+//! were never evaluated are infinite.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use cv_camstream::StereoFrame;
+
+use crate::cost::subpixel_minimum;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Magic string at the start of every `.npy` file, followed by format version 1.0.
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// An algorithm which can emit the cost volume its disparity map is chosen from.
+pub trait CostVolumeAlgorithm: DisparityAlgorithm {
+    /// Compute the cost volume for the given frame.
+    ///
+    /// This is the volume the algorithm takes its winner-take-all decision from, after any
+    /// aggregation or optimisation, so the volume's cost curves explain the output disparities.
+    fn cost_volume(&mut self, frame: &StereoFrame) -> Result<CostVolume>;
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Matching costs for every pixel of an image over a range of disparities.
+///
+/// A dense volume covers the disparities `0..max`, a range-limited one covers `min..max`.
+#[derive(Clone, Debug)]
+pub struct CostVolume {
+    width: usize,
+    height: usize,
+    disparities: Range<usize>,
+    data: Vec<f32>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl CostVolume {
+    /// Create a new volume over the given disparity range, with every cost unevaluated.
+    pub fn new(width: usize, height: usize, disparities: Range<usize>) -> Self {
+        Self::filled(width, height, disparities, f32::INFINITY)
+    }
+
+    /// Create a new volume over the disparities `0..max_disparity`, with every cost unevaluated.
+    pub fn dense(width: usize, height: usize, max_disparity: usize) -> Self {
+        Self::new(width, height, 0..max_disparity)
+    }
+
+    /// Create a new volume over the given disparity range, with every cost set to `val`.
+    pub fn filled(width: usize, height: usize, disparities: Range<usize>, val: f32) -> Self {
+        let num_disp = disparities.end.saturating_sub(disparities.start);
+
+        Self {
+            width,
+            height,
+            disparities,
+            data: vec![val; width * height * num_disp]
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The range of disparities covered by the volume.
+    pub fn disparities(&self) -> Range<usize> {
+        self.disparities.clone()
+    }
+
+    /// The number of disparities covered by the volume.
+    pub fn num_disparities(&self) -> usize {
+        self.disparities.len()
+    }
+
+    /// Get the cost of the given pixel at disparity `d`, which is infinite outside the volume's
+    /// disparity range.
+    pub fn get(&self, x: usize, y: usize, d: usize) -> f32 {
+        match self.disparities.contains(&d) {
+            true => self.costs(x, y)[d - self.disparities.start],
+            false => f32::INFINITY
+        }
+    }
+
+    /// Set the cost of the given pixel at disparity `d`, which must be in the volume's range.
+    pub fn set(&mut self, x: usize, y: usize, d: usize, cost: f32) {
+        let start = self.disparities.start;
+        self.costs_mut(x, y)[d - start] = cost;
+    }
+
+    /// Costs of every disparity in the range at the given pixel.
+    pub fn costs(&self, x: usize, y: usize) -> &[f32] {
+        let n = self.num_disparities();
+        let idx = (y * self.width + x) * n;
+        &self.data[idx..idx + n]
+    }
+
+    pub fn costs_mut(&mut self, x: usize, y: usize) -> &mut [f32] {
+        let n = self.num_disparities();
+        let idx = (y * self.width + x) * n;
+        &mut self.data[idx..idx + n]
+    }
+
+    /// All costs in storage order.
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    /// Copy out the costs of every pixel at disparity `d`, as a row-major image.
+    pub fn slice(&self, d: usize) -> Vec<f32> {
+        let n = self.num_disparities();
+        let k = d - self.disparities.start;
+
+        self.data.iter().skip(k).step_by(n).cloned().collect()
+    }
+
+    /// Set the costs of every pixel at disparity `d` from a row-major image.
+    pub fn set_slice(&mut self, d: usize, slice: &[f32]) {
+        let n = self.num_disparities();
+        let k = d - self.disparities.start;
+
+        for (c, &s) in self.data.iter_mut().skip(k).step_by(n).zip(slice.iter()) {
+            *c = s;
+        }
+    }
+
+    /// The cost curve of the given pixel, as (disparity, cost) pairs for every evaluated
+    /// disparity. This is the data to plot when debugging a mismatch.
+    pub fn curve(&self, x: usize, y: usize) -> Vec<(usize, f32)> {
+        self.disparities
+            .clone()
+            .zip(self.costs(x, y).iter())
+            .filter(|(_, c)| c.is_finite())
+            .map(|(d, &c)| (d, c))
+            .collect()
+    }
+
+    /// Pick the disparity with the lowest cost at every pixel, with sub pixel interpolation.
+    ///
+    /// Interpolation only uses the run of evaluated costs around the minimum. Pixels with no
+    /// evaluated costs are left invalid.
+    pub fn wta(&self) -> DisparityMap {
+        let mut disp_map = DisparityMap::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let costs = self.costs(x, y);
+
+                if costs.is_empty() {
+                    continue;
+                }
+
+                let best = costs
+                    .iter()
+                    .enumerate()
+                    .fold(0, |min_idx, (idx, &val)| {
+                        if val < costs[min_idx] { idx } else { min_idx }
+                    });
+
+                if !costs[best].is_finite() {
+                    continue;
+                }
+
+                let mut start = best;
+                while start > 0 && costs[start - 1].is_finite() {
+                    start -= 1;
+                }
+
+                let mut end = best + 1;
+                while end < costs.len() && costs[end].is_finite() {
+                    end += 1;
+                }
+
+                disp_map.put(
+                    x, y,
+                    (self.disparities.start + start) as f32
+                        + subpixel_minimum(&costs[start..end])
+                );
+            }
+        }
+
+        disp_map.update_range();
+
+        disp_map
+    }
+
+    /// Save the volume as a NumPy `.npy` file, with shape (height, width, disparities) and
+    /// little-endian `f32` values.
+    pub fn save_npy<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        self.write_npy(&mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Write the volume in the NumPy `.npy` format to the given writer.
+    pub fn write_npy<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            self.height,
+            self.width,
+            self.num_disparities()
+        );
+
+        // The header is padded with spaces and a newline so the data is 64 byte aligned
+        let unpadded = NPY_MAGIC.len() + 2 + header.len() + 1;
+        let padding = (64 - unpadded % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        writer.write_all(NPY_MAGIC)?;
+        writer.write_all(&(header.len() as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+
+        for c in &self.data {
+            writer.write_all(&c.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
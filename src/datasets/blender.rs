@@ -0,0 +1,312 @@
+//! # Blender render scenes
+//!
+//! This module reads stereo scenes rendered in Blender, such as those in `res/renders`, from a
+//! small TOML manifest, and converts their rendered depth into ground truth disparity.
+//!
+//! A manifest lists each scene's left and right images, and optionally its depth pass and the
+//! stereo camera used to render it, with paths relative to the manifest:
+//!
+//! ```toml
+//! [[scene]]
+//! name = "simple_rocks_01"
+//! left = "simple_rocks_01_left.png"
+//! right = "simple_rocks_01_right.png"
+//! depth = "simple_rocks_01_depth.exr"
+//!
+//! [scene.camera]
+//! baseline = 0.2        # metres
+//! focal_length = 35.0   # millimetres
+//! sensor_width = 36.0   # millimetres
+//! ```
+//!
+//! The depth pass is read from a single channel OpenEXR file, or the `Z` channel of a multilayer
+//! file, in metres along the optical axis. Blender's focal length and sensor width give the focal
+//! length in pixels, assuming the sensor is fit horizontally, and the disparity of a pixel is
+//! then `focal * baseline / depth`. Background pixels, which Blender renders at a very large
+//! depth, are left without a ground truth disparity.
+//!
+//! Scenes without a depth pass can still be computed, but have no ground truth, so new renders
+//! can be dropped in before their depth is exported.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use exr::prelude::simple_image::{read_options, Image, Samples};
+use serde::Deserialize;
+
+use crate::colour::ColourStereoFrame;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::eval::{GroundTruth, Region, Report, SceneResult};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Depth at or beyond which a pixel is treated as background, in metres.
+const BACKGROUND_DEPTH: f32 = 1.0e9;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A manifest of rendered scenes.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Manifest {
+    /// The scenes, in the order they appear in the manifest.
+    #[serde(rename = "scene", default)]
+    pub scenes: Vec<SceneEntry>,
+
+    /// Directory the scene paths are relative to.
+    #[serde(skip)]
+    dir: PathBuf
+}
+
+/// A single scene in a manifest.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SceneEntry {
+    pub name: String,
+
+    /// Left and right images.
+    pub left: PathBuf,
+    pub right: PathBuf,
+
+    /// Depth pass of the left camera, if it has been exported.
+    pub depth: Option<PathBuf>,
+
+    /// How the depth pass measures depth.
+    #[serde(default)]
+    pub depth_type: DepthType,
+
+    /// The stereo camera, required to convert the depth pass to disparity.
+    pub camera: Option<Camera>
+}
+
+/// A Blender stereo camera.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Distance between the left and right cameras in metres.
+    pub baseline: f32,
+
+    /// Lens focal length in millimetres.
+    pub focal_length: f32,
+
+    /// Sensor width in millimetres.
+    pub sensor_width: f32
+}
+
+/// A scene loaded from a manifest.
+pub struct Scene {
+    pub name: String,
+    pub frame: ColourStereoFrame,
+
+    /// Ground truth, if the scene has a depth pass and camera.
+    pub truth: Option<GroundTruth>
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// How a depth pass measures the depth of each pixel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepthType {
+    /// Distance along the camera's optical axis, as written by Blender's Z pass.
+    Planar,
+
+    /// Distance from the camera centre along each pixel's ray.
+    Radial
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for DepthType {
+    fn default() -> Self {
+        DepthType::Planar
+    }
+}
+
+impl Manifest {
+    /// Read a manifest file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        let mut manifest: Manifest = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| Error::Format(format!("{}: {}", path.display(), e)))?;
+
+        manifest.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        Ok(manifest)
+    }
+
+    /// Find a scene by name.
+    pub fn entry(&self, name: &str) -> Option<&SceneEntry> {
+        self.scenes.iter().find(|s| s.name == name)
+    }
+
+    /// Load a scene of the manifest.
+    pub fn load_scene(&self, entry: &SceneEntry) -> Result<Scene> {
+        let frame = ColourStereoFrame::from_dynamic(
+            &image::open(self.dir.join(&entry.left))?,
+            &image::open(self.dir.join(&entry.right))?
+        )?;
+
+        let truth = match (&entry.depth, &entry.camera) {
+            (Some(depth), Some(camera)) => {
+                let depth = read_depth_exr(self.dir.join(depth))?;
+
+                if (depth.width(), depth.height()) != (frame.width(), frame.height()) {
+                    return Err(Error::Format(format!(
+                        "depth pass of {} does not match the image size", entry.name
+                    )));
+                }
+
+                Some(GroundTruth::new(camera.disparity(&depth, entry.depth_type)))
+            },
+            (Some(_), None) => return Err(Error::Format(format!(
+                "scene {} has a depth pass but no camera", entry.name
+            ))),
+            _ => None
+        };
+
+        Ok(Scene {
+            name: entry.name.clone(),
+            frame,
+            truth
+        })
+    }
+}
+
+impl Camera {
+    /// Focal length in pixels for an image of the given width.
+    pub fn focal_pixels(&self, width: usize) -> f32 {
+        self.focal_length * width as f32 / self.sensor_width
+    }
+
+    /// Convert a depth pass to a left-referenced disparity map.
+    ///
+    /// Depths are stored in a disparity map, with pixels without a depth invalid.
+    pub fn disparity(&self, depth: &DisparityMap, depth_type: DepthType) -> DisparityMap {
+        let width = depth.width();
+        let height = depth.height();
+        let focal = self.focal_pixels(width);
+
+        // The principal point is at the image centre
+        let cx = (width as f32 - 1.0) / 2.0;
+        let cy = (height as f32 - 1.0) / 2.0;
+
+        let mut disparity = DisparityMap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                if !depth.is_valid(x, y) {
+                    continue;
+                }
+
+                let mut z = depth.get(x, y);
+
+                if depth_type == DepthType::Radial {
+                    let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                    z *= focal / (focal * focal + dx * dx + dy * dy).sqrt();
+                }
+
+                if z > 0.0 && z < BACKGROUND_DEPTH {
+                    disparity.put(x, y, focal * self.baseline / z);
+                }
+            }
+        }
+
+        disparity.update_range();
+
+        disparity
+    }
+}
+
+impl Scene {
+    /// Compute the scene with an algorithm, returning the disparity map and its result.
+    pub fn run<A: DisparityAlgorithm + ?Sized>(
+        &self,
+        algorithm: &mut A,
+        region: Region
+    ) -> Result<(DisparityMap, SceneResult)> {
+        SceneResult::compute(&self.name, algorithm, &self.frame, self.truth.as_ref(), region)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Run an algorithm over every scene of a manifest, in manifest order.
+pub fn run<A: DisparityAlgorithm + ?Sized, P: AsRef<Path>>(
+    algorithm: &mut A,
+    manifest: P,
+    region: Region
+) -> Result<Report> {
+    let manifest = Manifest::load(manifest)?;
+    let mut scenes = Vec::new();
+
+    for entry in &manifest.scenes {
+        let (_, result) = manifest.load_scene(entry)?.run(algorithm, region)?;
+        scenes.push(result);
+    }
+
+    Ok(Report { region, scenes })
+}
+
+/// Read a depth pass from an OpenEXR file into a disparity map holding the depths.
+///
+/// The depth is taken from a channel named `Z`, or ending in `.Z` as in multilayer files, or
+/// failing that from the only channel of the file. Non-finite depths are read as invalid.
+pub fn read_depth_exr<P: AsRef<Path>>(path: P) -> Result<DisparityMap> {
+    let path = path.as_ref();
+
+    let image = Image::read_from_file(path, read_options::high())
+        .map_err(|e| Error::Format(format!("{}: {}", path.display(), e)))?;
+
+    let is_depth = |name: &str| name == "Z" || name.ends_with(".Z");
+
+    let (layer, channel) = image.layers
+        .iter()
+        .flat_map(|l| l.channels.iter().map(move |c| (l, c)))
+        .find(|(_, c)| is_depth(&c.name.to_string()))
+        .or_else(|| match image.layers.first() {
+            Some(l) if l.channels.len() == 1 => Some((l, &l.channels[0])),
+            _ => None
+        })
+        .ok_or_else(|| Error::Format(format!(
+            "{} has no depth channel", path.display()
+        )))?;
+
+    let samples: Vec<f32> = match &channel.content {
+        Samples::F16(s) => s.iter().map(|v| v.to_f32()).collect(),
+        Samples::F32(s) => s.clone(),
+        Samples::U32(s) => s.iter().map(|&v| v as f32).collect()
+    };
+
+    let width = layer.size.width();
+    let height = layer.size.height();
+
+    if samples.len() != width * height {
+        return Err(Error::Format(format!(
+            "depth channel of {} is subsampled", path.display()
+        )));
+    }
+
+    let mut depth = DisparityMap::new(width, height);
+
+    for (i, &z) in samples.iter().enumerate() {
+        if z.is_finite() {
+            depth.put(i % width, i / width, z);
+        }
+    }
+
+    Ok(depth)
+}
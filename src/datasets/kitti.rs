@@ -0,0 +1,600 @@
+//! # KITTI stereo frames
+//!
+//! This module reads the stereo benchmarks of the
+//! ("KITTI Vision Benchmark Suite")[http://www.cvlibs.net/datasets/kitti/eval_stereo.php] and
+//! evaluates disparity maps with KITTI's outlier measures.
+//!
+//! Both layouts are supported, and detected from the directories present:
+//!
+//! - KITTI 2015, with colour images in `image_2` and `image_3`, ground truth in `disp_occ_0` and
+//!   `disp_noc_0`, and calibration in `calib_cam_to_cam`.
+//! - KITTI 2012, with colour images in `colored_0` and `colored_1`, ground truth in `disp_occ`
+//!   and `disp_noc`, and calibration in `calib`.
+//!
+//! Only the reference frames, named `NNNNNN_10.png`, are read. Ground truth is a 16-bit PNG
+//! holding the disparity multiplied by 256, with zero where unknown, and is absent from the
+//! testing split. The occluded map gives the disparity, and the pixels of the non-occluded map
+//! give the mask for [`Region::NonOccluded`] evaluations.
+//!
+//! KITTI counts a pixel as an outlier when its error is more than 3 pixels and more than 5% of
+//! the true disparity, and reports the percentage of outliers as D1. KITTI 2012 instead reports
+//! the percentage of pixels with an error above fixed thresholds of 2 to 5 pixels. As with the
+//! [`eval`](crate::eval) measures, pixels without an estimate count as outliers.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, ImageBuffer, Luma};
+
+use crate::colour::ColourStereoFrame;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::eval::{GroundTruth, Region};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Scale of the disparities stored in ground truth PNGs.
+const DISPARITY_SCALE: f32 = 256.0;
+
+/// Absolute error above which a pixel may be a D1 outlier, in pixels.
+const D1_ABS_THRESHOLD: f32 = 3.0;
+
+/// Relative error above which a pixel may be a D1 outlier.
+const D1_REL_THRESHOLD: f32 = 0.05;
+
+/// Error thresholds of the KITTI 2012 outlier measures, in pixels.
+pub const OUTLIER_THRESHOLDS: [f32; 4] = [2.0, 3.0, 4.0, 5.0];
+
+/// Suffix of the reference frame images.
+const FRAME_SUFFIX: &str = "_10.png";
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A KITTI stereo dataset split, such as the `training` or `testing` directory.
+pub struct Dataset {
+    root: PathBuf,
+    layout: Layout
+}
+
+/// Rectified calibration of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// Focal length in pixels.
+    pub focal: f32,
+
+    /// Camera baseline in metres.
+    pub baseline: f32
+}
+
+/// A single frame of a dataset.
+pub struct Frame {
+    /// Frame number, as used in the file names.
+    pub id: String,
+
+    pub frame: ColourStereoFrame,
+    pub calib: Calibration,
+
+    /// Ground truth, if the split includes it.
+    pub truth: Option<GroundTruth>
+}
+
+/// KITTI error measures of a disparity map against ground truth.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    /// Number of pixels evaluated.
+    pub evaluated: usize,
+
+    /// Fraction of the evaluated pixels with a valid disparity estimate.
+    pub coverage: f32,
+
+    /// Percentage of evaluated pixels which are D1 outliers.
+    pub d1: f32,
+
+    /// Percentage of evaluated pixels with an error above each of [`OUTLIER_THRESHOLDS`].
+    pub outliers: [f32; 4],
+
+    /// Mean absolute error over the evaluated pixels with a valid estimate.
+    pub avg_error: f32
+}
+
+/// Result of running an algorithm on a single frame.
+#[derive(Debug, Clone)]
+pub struct FrameResult {
+    pub id: String,
+
+    /// Error measures, if the frame has ground truth.
+    pub metrics: Option<Metrics>,
+
+    /// Time taken to compute the disparity map.
+    pub runtime: Duration
+}
+
+/// Results of running an algorithm over a dataset.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub region: Region,
+    pub frames: Vec<FrameResult>
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Directory layout of a dataset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Kitti2012,
+    Kitti2015
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Layout {
+    fn left_dir(self) -> &'static str {
+        match self {
+            Layout::Kitti2012 => "colored_0",
+            Layout::Kitti2015 => "image_2"
+        }
+    }
+
+    fn right_dir(self) -> &'static str {
+        match self {
+            Layout::Kitti2012 => "colored_1",
+            Layout::Kitti2015 => "image_3"
+        }
+    }
+
+    fn occ_dir(self) -> &'static str {
+        match self {
+            Layout::Kitti2012 => "disp_occ",
+            Layout::Kitti2015 => "disp_occ_0"
+        }
+    }
+
+    fn noc_dir(self) -> &'static str {
+        match self {
+            Layout::Kitti2012 => "disp_noc",
+            Layout::Kitti2015 => "disp_noc_0"
+        }
+    }
+
+    fn calib_dir(self) -> &'static str {
+        match self {
+            Layout::Kitti2012 => "calib",
+            Layout::Kitti2015 => "calib_cam_to_cam"
+        }
+    }
+
+    /// Keys of the left and right colour camera projection matrices in the calibration file.
+    fn projection_keys(self) -> (&'static str, &'static str) {
+        match self {
+            Layout::Kitti2012 => ("P2", "P3"),
+            Layout::Kitti2015 => ("P_rect_02", "P_rect_03")
+        }
+    }
+}
+
+impl Dataset {
+    /// Open a dataset split, detecting its layout.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+
+        let layout = [Layout::Kitti2015, Layout::Kitti2012]
+            .iter()
+            .copied()
+            .find(|l| root.join(l.left_dir()).is_dir())
+            .ok_or_else(|| Error::Format(format!(
+                "{} is not a KITTI stereo split", root.display()
+            )))?;
+
+        Ok(Self { root, layout })
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Ids of the frames in the dataset, sorted.
+    pub fn frame_ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+
+        for entry in fs::read_dir(self.root.join(self.layout.left_dir()))? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+
+            if let Some(id) = name.strip_suffix(FRAME_SUFFIX) {
+                ids.push(id.to_string());
+            }
+        }
+
+        ids.sort();
+
+        Ok(ids)
+    }
+
+    /// Load a frame by id.
+    pub fn load(&self, id: &str) -> Result<Frame> {
+        let image_name = format!("{}{}", id, FRAME_SUFFIX);
+
+        let frame = ColourStereoFrame::from_dynamic(
+            &image::open(self.root.join(self.layout.left_dir()).join(&image_name))?,
+            &image::open(self.root.join(self.layout.right_dir()).join(&image_name))?
+        )?;
+
+        let calib_path = self.root.join(self.layout.calib_dir()).join(format!("{}.txt", id));
+        let calib = Calibration::parse(&fs::read_to_string(calib_path)?, self.layout)?;
+
+        let occ_path = self.root.join(self.layout.occ_dir()).join(&image_name);
+        let noc_path = self.root.join(self.layout.noc_dir()).join(&image_name);
+
+        let truth = match occ_path.exists() {
+            true => Some(load_truth(&occ_path, &noc_path)?),
+            false => None
+        };
+
+        if let Some(t) = &truth {
+            if (t.width(), t.height()) != (frame.width(), frame.height()) {
+                return Err(Error::Format(format!(
+                    "ground truth of frame {} does not match the image size", id
+                )));
+            }
+        }
+
+        Ok(Frame {
+            id: id.to_string(),
+            frame,
+            calib,
+            truth
+        })
+    }
+}
+
+impl Calibration {
+    /// Parse a calibration file of the given layout.
+    ///
+    /// The baseline is recovered from the translation terms of the left and right colour camera
+    /// projection matrices.
+    pub fn parse(text: &str, layout: Layout) -> Result<Self> {
+        let (left_key, right_key) = layout.projection_keys();
+
+        let left = parse_projection(text, left_key)?;
+        let right = parse_projection(text, right_key)?;
+
+        if left[0] == 0.0 || right[0] == 0.0 {
+            return Err(Error::Format("calibration has a zero focal length".into()));
+        }
+
+        Ok(Self {
+            focal: left[0],
+            baseline: left[3] / left[0] - right[3] / right[0]
+        })
+    }
+
+    /// Depth in metres of a point with the given disparity.
+    pub fn depth(&self, disparity: f32) -> f32 {
+        self.focal * self.baseline / disparity
+    }
+}
+
+impl Frame {
+    /// Compute the frame with an algorithm, returning the disparity map and its result.
+    pub fn run<A: DisparityAlgorithm + ?Sized>(
+        &self,
+        algorithm: &mut A,
+        region: Region
+    ) -> Result<(DisparityMap, FrameResult)> {
+        let start = Instant::now();
+        let map = algorithm.compute_colour(&self.frame)?;
+        let runtime = start.elapsed();
+
+        let metrics = match &self.truth {
+            Some(t) => Some(Metrics::compute(&map, t, region)?),
+            None => None
+        };
+
+        let result = FrameResult {
+            id: self.id.clone(),
+            metrics,
+            runtime
+        };
+
+        Ok((map, result))
+    }
+}
+
+impl Metrics {
+    /// Evaluate a disparity map over the given region of the ground truth.
+    pub fn compute(
+        estimate: &DisparityMap,
+        truth: &GroundTruth,
+        region: Region
+    ) -> Result<Self> {
+        if (estimate.width(), estimate.height()) != (truth.width(), truth.height()) {
+            return Err(Error::InvalidParams(format!(
+                "disparity map is {:?} pixels but the ground truth is {:?} pixels",
+                (estimate.width(), estimate.height()),
+                (truth.width(), truth.height())
+            )));
+        }
+
+        let mut evaluated = 0usize;
+        let mut estimated = 0usize;
+        let mut d1 = 0usize;
+        let mut outliers = [0usize; 4];
+        let mut abs_error = 0.0f64;
+
+        for y in 0..truth.height() {
+            for x in 0..truth.width() {
+                if !truth.in_region(x, y, region) {
+                    continue;
+                }
+
+                evaluated += 1;
+
+                if !estimate.is_valid(x, y) {
+                    d1 += 1;
+                    for o in outliers.iter_mut() {
+                        *o += 1;
+                    }
+                    continue;
+                }
+
+                let true_disp = truth.disparity.get(x, y);
+                let error = (estimate.get(x, y) - true_disp).abs();
+
+                estimated += 1;
+                abs_error += error as f64;
+
+                if error > D1_ABS_THRESHOLD && error > D1_REL_THRESHOLD * true_disp.abs() {
+                    d1 += 1;
+                }
+
+                for (o, &t) in outliers.iter_mut().zip(OUTLIER_THRESHOLDS.iter()) {
+                    if error > t {
+                        *o += 1;
+                    }
+                }
+            }
+        }
+
+        if evaluated == 0 {
+            return Ok(Self::default());
+        }
+
+        let percent = |count: usize| 100.0 * count as f32 / evaluated as f32;
+
+        let mut outlier_percents = [0.0; 4];
+        for (p, &count) in outlier_percents.iter_mut().zip(outliers.iter()) {
+            *p = percent(count);
+        }
+
+        Ok(Self {
+            evaluated,
+            coverage: estimated as f32 / evaluated as f32,
+            d1: percent(d1),
+            outliers: outlier_percents,
+            avg_error: match estimated {
+                0 => 0.0,
+                n => (abs_error / n as f64) as f32
+            }
+        })
+    }
+}
+
+impl Report {
+    /// Error measures over every evaluated pixel of the frames with ground truth, as reported
+    /// by the KITTI evaluation, which weights each frame by its number of evaluated pixels.
+    pub fn overall(&self) -> Option<Metrics> {
+        let metrics: Vec<&Metrics> = self.frames
+            .iter()
+            .filter_map(|f| f.metrics.as_ref())
+            .collect();
+
+        let evaluated: usize = metrics.iter().map(|m| m.evaluated).sum();
+        if evaluated == 0 {
+            return None;
+        }
+
+        let mut overall = Metrics {
+            evaluated,
+            ..Default::default()
+        };
+        let mut estimated = 0.0f32;
+
+        for m in &metrics {
+            let w = m.evaluated as f32 / evaluated as f32;
+
+            overall.coverage += w * m.coverage;
+            overall.d1 += w * m.d1;
+            overall.avg_error += m.avg_error * m.coverage * m.evaluated as f32;
+            estimated += m.coverage * m.evaluated as f32;
+
+            for (o, mo) in overall.outliers.iter_mut().zip(m.outliers.iter()) {
+                *o += w * mo;
+            }
+        }
+
+        if estimated > 0.0 {
+            overall.avg_error /= estimated;
+        }
+
+        Some(overall)
+    }
+
+    /// Total time spent computing disparity maps.
+    pub fn total_runtime(&self) -> Duration {
+        self.frames.iter().map(|f| f.runtime).sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<10} {:>8}", "frame", "D1")?;
+        for t in OUTLIER_THRESHOLDS.iter() {
+            write!(f, " {:>8}", format!("out{}", t))?;
+        }
+        writeln!(f, " {:>8} {:>8} {:>10}", "avgerr", "coverage", "time (s)")?;
+
+        for frame in &self.frames {
+            write_row(f, &frame.id, frame.metrics.as_ref(), Some(frame.runtime))?;
+        }
+
+        write_row(f, "all", self.overall().as_ref(), None)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Run an algorithm over every frame of a dataset split, in id order.
+pub fn run<A: DisparityAlgorithm + ?Sized, P: AsRef<Path>>(
+    algorithm: &mut A,
+    root: P,
+    region: Region
+) -> Result<Report> {
+    let dataset = Dataset::open(root)?;
+    let mut frames = Vec::new();
+
+    for id in dataset.frame_ids()? {
+        let (_, result) = dataset.load(&id)?.run(algorithm, region)?;
+        frames.push(result);
+    }
+
+    Ok(Report { region, frames })
+}
+
+/// Read a KITTI 16-bit disparity PNG, with zero pixels read as invalid.
+pub fn read_disparity<P: AsRef<Path>>(path: P) -> Result<DisparityMap> {
+    let path = path.as_ref();
+
+    let png = match image::open(path)? {
+        DynamicImage::ImageLuma16(png) => png,
+        _ => return Err(Error::Format(format!(
+            "{} is not a 16-bit greyscale disparity image", path.display()
+        )))
+    };
+
+    let mut map = DisparityMap::new(png.width() as usize, png.height() as usize);
+
+    for (x, y, pixel) in png.enumerate_pixels() {
+        if pixel[0] > 0 {
+            map.put(x as usize, y as usize, pixel[0] as f32 / DISPARITY_SCALE);
+        }
+    }
+
+    map.update_range();
+
+    Ok(map)
+}
+
+/// Write a disparity map as a KITTI 16-bit disparity PNG, as expected by the benchmark
+/// submission.
+///
+/// Invalid pixels are written as zero, and disparities are clamped to the representable range.
+pub fn write_disparity<P: AsRef<Path>>(map: &DisparityMap, path: P) -> Result<()> {
+    let png = ImageBuffer::from_fn(map.width() as u32, map.height() as u32, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+
+        let val = match map.is_valid(x, y) {
+            true => (map.get(x, y) * DISPARITY_SCALE).round().max(1.0).min(u16::MAX as f32),
+            false => 0.0
+        };
+
+        Luma([val as u16])
+    });
+
+    png.save(path)?;
+
+    Ok(())
+}
+
+/// Load the occluded ground truth, masked by the non-occluded ground truth if present.
+fn load_truth(occ_path: &Path, noc_path: &Path) -> Result<GroundTruth> {
+    let mut truth = GroundTruth::new(read_disparity(occ_path)?);
+
+    if noc_path.exists() {
+        let noc = read_disparity(noc_path)?;
+
+        if (noc.width(), noc.height()) != (truth.width(), truth.height()) {
+            return Err(Error::Format(format!(
+                "{} does not match the occluded ground truth size", noc_path.display()
+            )));
+        }
+
+        let mut mask = Vec::with_capacity(noc.width() * noc.height());
+        for y in 0..noc.height() {
+            for x in 0..noc.width() {
+                mask.push(noc.is_valid(x, y));
+            }
+        }
+
+        truth.non_occluded = Some(mask);
+    }
+
+    Ok(truth)
+}
+
+/// Parse the 3x4 projection matrix with the given key, as its 12 row-major values.
+fn parse_projection(text: &str, key: &str) -> Result<[f32; 12]> {
+    let line = text
+        .lines()
+        .find(|l| l.split(':').next().map(str::trim) == Some(key))
+        .ok_or_else(|| Error::Format(format!("calibration is missing {}", key)))?;
+
+    let vals = line[line.find(':').unwrap_or(0) + 1..]
+        .split_whitespace()
+        .map(|s| s.parse::<f32>())
+        .collect::<std::result::Result<Vec<f32>, _>>()
+        .map_err(|_| Error::Format(format!("invalid calibration matrix {}", key)))?;
+
+    if vals.len() != 12 {
+        return Err(Error::Format(format!("calibration matrix {} is not 3x4", key)));
+    }
+
+    let mut matrix = [0.0; 12];
+    matrix.copy_from_slice(&vals);
+
+    Ok(matrix)
+}
+
+/// Write a row of the report table.
+fn write_row(
+    f: &mut fmt::Formatter,
+    id: &str,
+    metrics: Option<&Metrics>,
+    runtime: Option<Duration>
+) -> fmt::Result {
+    write!(f, "{:<10}", id)?;
+
+    match metrics {
+        Some(m) => {
+            write!(f, " {:>8.2}", m.d1)?;
+            for o in m.outliers.iter() {
+                write!(f, " {:>8.2}", o)?;
+            }
+            write!(f, " {:>8.3} {:>8.3}", m.avg_error, m.coverage)?;
+        },
+        None => {
+            for _ in 0..OUTLIER_THRESHOLDS.len() + 3 {
+                write!(f, " {:>8}", "-")?;
+            }
+        }
+    }
+
+    match runtime {
+        Some(r) => writeln!(f, " {:>10.3}", r.as_secs_f32()),
+        None => writeln!(f)
+    }
+}
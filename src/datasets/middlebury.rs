@@ -0,0 +1,283 @@
+//! # Middlebury stereo scenes
+//!
+//! This module reads scenes in the layout of the
+//! ("Middlebury Stereo Evaluation")[https://vision.middlebury.edu/stereo/eval3/] version 3 and
+//! the 2014 datasets, and runs disparity algorithms over a directory of them.
+//!
+//! Each scene is a directory holding:
+//!
+//! - `im0.png` and `im1.png`, the rectified left and right images.
+//! - `calib.txt`, the camera calibration and disparity range, as `key=value` lines.
+//! - `disp0.pfm`, the left-referenced ground truth, with infinity where unknown. Optional, as the
+//!   test scenes do not include it.
+//! - `mask0nocc.png`, the occlusion mask, 255 where the pixel is visible in both images. Optional.
+//!
+//! The benchmark [`run`] computes every scene of a directory and reports the error measures of
+//! the [`eval`](crate::eval) module for each scene, along with their mean over all scenes.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::colour::ColourStereoFrame;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::eval::{GroundTruth, Region, Report, SceneResult};
+use crate::io::read_pfm;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Value of the non-occluded pixels in the occlusion mask.
+const MASK_NON_OCCLUDED: u8 = 255;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Calibration of a Middlebury scene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    /// Focal length in pixels.
+    pub focal: f32,
+
+    /// Difference in the x coordinate of the principal points, `cx1 - cx0`, in pixels.
+    pub doffs: f32,
+
+    /// Camera baseline in millimetres.
+    pub baseline: f32,
+
+    pub width: usize,
+    pub height: usize,
+
+    /// Conservative bound on the number of disparity levels.
+    pub ndisp: usize,
+
+    /// Tight bounds on the ground truth disparities, if given.
+    pub vmin: Option<f32>,
+    pub vmax: Option<f32>
+}
+
+/// A Middlebury scene.
+pub struct Scene {
+    /// Name of the scene, taken from its directory.
+    pub name: String,
+
+    pub frame: ColourStereoFrame,
+    pub calib: Calibration,
+
+    /// Ground truth, if the scene includes it.
+    pub truth: Option<GroundTruth>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Calibration {
+    /// Read the calibration from a `calib.txt` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse the contents of a `calib.txt` file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut cam0 = None;
+        let mut cam1 = None;
+        let mut doffs = None;
+        let mut baseline = None;
+        let mut width = None;
+        let mut height = None;
+        let mut ndisp = None;
+        let mut vmin = None;
+        let mut vmax = None;
+
+        for line in text.lines() {
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => continue
+            };
+
+            match key {
+                "cam0" => cam0 = Some(parse_matrix(key, value)?),
+                "cam1" => cam1 = Some(parse_matrix(key, value)?),
+                "doffs" => doffs = Some(parse_value(key, value)?),
+                "baseline" => baseline = Some(parse_value(key, value)?),
+                "width" => width = Some(parse_value(key, value)?),
+                "height" => height = Some(parse_value(key, value)?),
+                "ndisp" => ndisp = Some(parse_value(key, value)?),
+                "vmin" => vmin = Some(parse_value(key, value)?),
+                "vmax" => vmax = Some(parse_value(key, value)?),
+                _ => ()
+            }
+        }
+
+        let cam0 = require("cam0", cam0)?;
+
+        // Older datasets leave out doffs, which can be recovered from the principal points
+        let doffs = match (doffs, cam1) {
+            (Some(d), _) => d,
+            (None, Some(cam1)) => cam1.1 - cam0.1,
+            (None, None) => 0.0
+        };
+
+        Ok(Self {
+            focal: cam0.0,
+            doffs,
+            baseline: require("baseline", baseline)?,
+            width: require("width", width)?,
+            height: require("height", height)?,
+            ndisp: require("ndisp", ndisp)?,
+            vmin,
+            vmax
+        })
+    }
+
+    /// Depth in millimetres of a point with the given disparity.
+    pub fn depth(&self, disparity: f32) -> f32 {
+        self.baseline * self.focal / (disparity + self.doffs)
+    }
+}
+
+impl Scene {
+    /// Load a scene from its directory.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let frame = ColourStereoFrame::from_dynamic(
+            &image::open(dir.join("im0.png"))?,
+            &image::open(dir.join("im1.png"))?
+        )?;
+
+        let calib = Calibration::load(dir.join("calib.txt"))?;
+
+        let disp_path = dir.join("disp0.pfm");
+        let truth = match disp_path.exists() {
+            true => Some(load_truth(&disp_path, &dir.join("mask0nocc.png"))?),
+            false => None
+        };
+
+        if let Some(t) = &truth {
+            if (t.width(), t.height()) != (frame.width(), frame.height()) {
+                return Err(Error::Format(format!(
+                    "ground truth of {} is {:?} pixels but the images are {:?} pixels",
+                    name,
+                    (t.width(), t.height()),
+                    (frame.width(), frame.height())
+                )));
+            }
+        }
+
+        Ok(Self {
+            name,
+            frame,
+            calib,
+            truth
+        })
+    }
+
+    /// Compute the scene with an algorithm, returning the disparity map and its result.
+    pub fn run<A: DisparityAlgorithm + ?Sized>(
+        &self,
+        algorithm: &mut A,
+        region: Region
+    ) -> Result<(DisparityMap, SceneResult)> {
+        SceneResult::compute(&self.name, algorithm, &self.frame, self.truth.as_ref(), region)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Run an algorithm over every scene in a directory, in name order.
+///
+/// Scenes are the subdirectories containing an `im0.png`, other entries are ignored.
+pub fn run<A: DisparityAlgorithm + ?Sized, P: AsRef<Path>>(
+    algorithm: &mut A,
+    root: P,
+    region: Region
+) -> Result<Report> {
+    let mut scenes = Vec::new();
+
+    for dir in scene_dirs(root)? {
+        let scene = Scene::load(&dir)?;
+        let (_, result) = scene.run(algorithm, region)?;
+
+        scenes.push(result);
+    }
+
+    Ok(Report { region, scenes })
+}
+
+/// Find the scene directories in a directory, sorted by name.
+pub fn scene_dirs<P: AsRef<Path>>(root: P) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+
+        if path.is_dir() && path.join("im0.png").exists() {
+            dirs.push(path);
+        }
+    }
+
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+/// Load the ground truth disparity and, if present, the occlusion mask.
+fn load_truth(disp_path: &Path, mask_path: &Path) -> Result<GroundTruth> {
+    let mut truth = GroundTruth::new(read_pfm(disp_path)?);
+
+    if mask_path.exists() {
+        let mask = image::open(mask_path)?.to_luma8();
+
+        if mask.dimensions() != (truth.width() as u32, truth.height() as u32) {
+            return Err(Error::Format(format!(
+                "occlusion mask {} does not match the ground truth size", mask_path.display()
+            )));
+        }
+
+        truth.non_occluded = Some(mask.pixels().map(|p| p[0] == MASK_NON_OCCLUDED).collect());
+    }
+
+    Ok(truth)
+}
+
+/// Parse a single calibration value.
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| Error::Format(format!(
+        "invalid calibration value {}={}", key, value
+    )))
+}
+
+/// Parse a calibration matrix of the form `[f 0 cx; 0 f cy; 0 0 1]`, returning `(f, cx)`.
+fn parse_matrix(key: &str, value: &str) -> Result<(f32, f32)> {
+    let vals = value
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(|c: char| c == ';' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| parse_value(key, s))
+        .collect::<Result<Vec<f32>>>()?;
+
+    match vals.len() {
+        9 => Ok((vals[0], vals[2])),
+        _ => Err(Error::Format(format!("calibration matrix {} is not 3x3", key)))
+    }
+}
+
+/// Unwrap a required calibration value.
+fn require<T>(key: &str, value: Option<T>) -> Result<T> {
+    value.ok_or_else(|| Error::Format(format!("calibration is missing {}", key)))
+}
@@ -0,0 +1,15 @@
+//! # Stereo datasets
+//!
+//! This module provides loaders for public stereo benchmarks and our own renders, giving frames
+//! along with their ground truth so that algorithms can be evaluated with the
+//! [`eval`](crate::eval) module:
+//!
+//! - [`middlebury`], the ("Middlebury Stereo Evaluation")[https://vision.middlebury.edu/stereo/eval3/]
+//!   scenes.
+//! - [`kitti`], the ("KITTI")[http://www.cvlibs.net/datasets/kitti/eval_stereo.php] 2012 and 2015
+//!   stereo benchmarks.
+//! - [`blender`], scenes rendered in Blender and listed in a manifest, such as `res/renders`.
+
+pub mod blender;
+pub mod kitti;
+pub mod middlebury;
@@ -8,19 +8,57 @@
 
 use cv_camstream::{GrayFloatImage, StereoFrame};
 use image::GrayImage;
+use serde::Deserialize;
+use crate::colour::ColourStereoFrame;
 use crate::error::*;
+use crate::prior::DisparityPrior;
+use crate::tiling::{self, Margins, Rect};
 
 // -----------------------------------------------------------------------------------------------
 // DATA STRUCTURES
 // -----------------------------------------------------------------------------------------------
 
 /// A generic floating point disparity map.
+///
+/// Every pixel carries a validity flag alongside its value. Pixels start out invalid and become
+/// valid when a value is put into them, so any region an algorithm does not fill is explicitly
+/// marked as having no disparity estimate.
 pub struct DisparityMap {
     data: GrayFloatImage,
+    validity: Vec<Validity>,
     pub max_disp: Option<f32>,
     pub min_disp: Option<f32>
 }
 
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Validity of a single pixel in a disparity map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Validity {
+    /// The pixel holds a disparity estimate.
+    Valid,
+
+    /// No disparity could be estimated for the pixel, for example because it lies in the border
+    /// of the image.
+    Invalid,
+
+    /// The pixel is visible in this image but occluded in the other, so has no match.
+    Occluded
+}
+
+/// The camera a disparity map is referenced to.
+///
+/// A left-referenced map gives, for the left pixel `x`, the disparity `d` of its match at `x - d`
+/// in the right image. A right-referenced map gives, for the right pixel `x`, the disparity `d`
+/// of its match at `x + d` in the left image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Reference {
+    Left,
+    Right
+}
+
 // -----------------------------------------------------------------------------------------------
 // TRAITS
 // -----------------------------------------------------------------------------------------------
@@ -28,6 +66,77 @@ pub struct DisparityMap {
 pub trait DisparityAlgorithm {
     /// Compute the disparity map of the given stereo frame.
     fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap>;
+
+    /// Compute the disparity map of the given stereo frame, restricting the search at each pixel
+    /// to the range given by the prior.
+    ///
+    /// Algorithms which cannot make use of a prior return `Error::PriorUnsupported`.
+    fn compute_with_prior(
+        &mut self, 
+        _frame: &StereoFrame, 
+        _prior: &DisparityPrior
+    ) -> Result<DisparityMap> {
+        Err(Error::PriorUnsupported)
+    }
+
+    /// Compute the disparity map of the given stereo frame referenced to the right camera.
+    ///
+    /// The default mirrors both images and swaps them, so that the right image becomes the left
+    /// of a mirrored frame, computes that frame, and mirrors the result back.
+    fn compute_right(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        Ok(self.compute(&mirror_frame(frame))?.mirrored())
+    }
+
+    /// Compute the disparity map of the given stereo frame referenced to the given camera.
+    fn compute_referenced(
+        &mut self,
+        frame: &StereoFrame,
+        reference: Reference
+    ) -> Result<DisparityMap> {
+        match reference {
+            Reference::Left => self.compute(frame),
+            Reference::Right => self.compute_right(frame)
+        }
+    }
+
+    /// Compute the disparity map of the given colour stereo frame.
+    ///
+    /// The default matches on the frame's greyscale images, algorithms which can make use of
+    /// colour override this.
+    fn compute_colour(&mut self, frame: &ColourStereoFrame) -> Result<DisparityMap> {
+        self.compute(&frame.grey)
+    }
+
+    /// The margins the algorithm needs around a region in order to compute every pixel inside
+    /// it.
+    ///
+    /// The default is no margin, which is only correct for purely per-pixel algorithms.
+    fn margins(&self) -> Margins {
+        Margins::default()
+    }
+
+    /// Compute the disparity map of the given region of the stereo frame.
+    ///
+    /// The returned map is the size of the whole frame, with only the pixels inside the region
+    /// filled. The default implementation crops the frame to the region expanded by the
+    /// algorithm's margins and computes the cropped frame.
+    fn compute_roi(&mut self, frame: &StereoFrame, rect: Rect) -> Result<DisparityMap> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let rect = rect.clamp(width, height);
+        if rect.is_empty() {
+            return Err(Error::EmptyRegion(rect));
+        }
+
+        let crop = rect.expand(self.margins(), width, height);
+        let crop_map = self.compute(&tiling::crop_frame(frame, crop))?;
+
+        let mut map = DisparityMap::new(width, height);
+        map.paste(&crop_map, (crop.x, crop.y), rect);
+
+        Ok(map)
+    }
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -38,16 +147,147 @@ impl DisparityMap {
     pub fn new(width: usize, height: usize) -> Self {
         DisparityMap {
             data: GrayFloatImage::new(width, height),
+            validity: vec![Validity::Invalid; width * height],
             min_disp: None,
             max_disp: None
         }
     }
 
+    /// Width of the map in pixels.
+    pub fn width(&self) -> usize {
+        self.data.width() as usize
+    }
+
+    /// Height of the map in pixels.
+    pub fn height(&self) -> usize {
+        self.data.height() as usize
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.data.get(x, y)
+    }
+
+    /// Put a disparity value into the map, marking the pixel as valid.
     pub fn put(&mut self, x: usize, y: usize, val: f32) {
-        self.data.put(x, y, val)
+        self.data.put(x, y, val);
+        self.set_validity(x, y, Validity::Valid);
+    }
+
+    /// Get the validity of the given pixel.
+    pub fn validity(&self, x: usize, y: usize) -> Validity {
+        self.validity[y * self.width() + x]
+    }
+
+    /// Set the validity of the given pixel without changing its value.
+    pub fn set_validity(&mut self, x: usize, y: usize, validity: Validity) {
+        let width = self.width();
+        self.validity[y * width + x] = validity;
+    }
+
+    pub fn is_valid(&self, x: usize, y: usize) -> bool {
+        self.validity(x, y) == Validity::Valid
+    }
+
+    /// Mark the given pixel as invalid and reset its value to zero.
+    pub fn invalidate(&mut self, x: usize, y: usize) {
+        self.data.put(x, y, 0.0);
+        self.set_validity(x, y, Validity::Invalid);
+    }
+
+    /// Mark the given pixel as occluded and reset its value to zero.
+    pub fn occlude(&mut self, x: usize, y: usize) {
+        self.data.put(x, y, 0.0);
+        self.set_validity(x, y, Validity::Occluded);
+    }
+
+    /// Fraction of pixels in the map which are valid.
+    pub fn density(&self) -> f32 {
+        let valid = self.validity.iter().filter(|&&v| v == Validity::Valid).count();
+
+        valid as f32 / self.validity.len().max(1) as f32
+    }
+
+    /// Copy the pixels of `rect` from another map into this one.
+    ///
+    /// `offset` is the position of the source map's origin within this map, and `rect` is given
+    /// in this map's coordinates. Validity is copied along with the values, and the disparity
+    /// range of this map is widened to cover the copied valid pixels.
+    pub fn paste(&mut self, src: &DisparityMap, offset: (usize, usize), rect: Rect) {
+        let mut min_disp = self.min_disp.unwrap_or(f32::INFINITY);
+        let mut max_disp = self.max_disp.unwrap_or(f32::NEG_INFINITY);
+
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let (sx, sy) = (x - offset.0, y - offset.1);
+
+                if !src.is_valid(sx, sy) {
+                    self.invalidate(x, y);
+                    self.set_validity(x, y, src.validity(sx, sy));
+                    continue;
+                }
+
+                let val = src.get(sx, sy);
+
+                self.put(x, y, val);
+
+                min_disp = min_disp.min(val);
+                max_disp = max_disp.max(val);
+            }
+        }
+
+        if min_disp <= max_disp {
+            self.min_disp = Some(min_disp);
+            self.max_disp = Some(max_disp);
+        }
+    }
+
+    /// Mirror the map horizontally, keeping the validity of each pixel.
+    pub fn mirrored(&self) -> DisparityMap {
+        let width = self.width();
+        let mut mirrored = DisparityMap::new(width, self.height());
+
+        for y in 0..self.height() {
+            for x in 0..width {
+                let mx = width - 1 - x;
+
+                mirrored.data.put(mx, y, self.get(x, y));
+                mirrored.set_validity(mx, y, self.validity(x, y));
+            }
+        }
+
+        mirrored.min_disp = self.min_disp;
+        mirrored.max_disp = self.max_disp;
+
+        mirrored
+    }
+
+    /// Recalculate the minimum and maximum disparity from the valid pixels in the map.
+    pub fn update_range(&mut self) {
+        let mut min_disp = f32::INFINITY;
+        let mut max_disp = f32::NEG_INFINITY;
+
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if self.is_valid(x, y) {
+                    min_disp = min_disp.min(self.get(x, y));
+                    max_disp = max_disp.max(self.get(x, y));
+                }
+            }
+        }
+
+        if min_disp <= max_disp {
+            self.min_disp = Some(min_disp);
+            self.max_disp = Some(max_disp);
+        }
+        else {
+            self.min_disp = None;
+            self.max_disp = None;
+        }
     }
 
     /// Converts the image into a dynamic Luma8 image.
+    ///
+    /// Pixels without a valid disparity are drawn as zero.
     pub fn to_luma(&self) -> GrayImage {
 
         let mut new = image::GrayImage::new(
@@ -57,6 +297,10 @@ impl DisparityMap {
 
         for y in 0..new.height() {
             for x in 0..new.width() {
+                if !self.is_valid(x as usize, y as usize) {
+                    continue;
+                }
+
                 let mut val = self.data.get(x as usize, y as usize);
 
                 if val < 0.0 {
@@ -91,6 +335,10 @@ impl DisparityMap {
 
         for y in 0..new.height() {
             for x in 0..new.width() {
+                if !self.is_valid(x as usize, y as usize) {
+                    continue;
+                }
+
                 let mut val = self.data.get(x as usize, y as usize) * mult;
 
                 if val < 0.0 {
@@ -106,4 +354,43 @@ impl DisparityMap {
 
         new
     }
-}
\ No newline at end of file
+}
+
+impl Default for Reference {
+    fn default() -> Self {
+        Reference::Left
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Mirror both images of a stereo frame horizontally and swap them.
+///
+/// The left-referenced disparity of the mirrored frame is the mirror image of the
+/// right-referenced disparity of the original frame.
+fn mirror_frame(frame: &StereoFrame) -> StereoFrame {
+    StereoFrame {
+        left: mirror_image(&frame.right),
+        left_timestamp: frame.right_timestamp,
+        right: mirror_image(&frame.left),
+        right_timestamp: frame.left_timestamp
+    }
+}
+
+/// Mirror an image horizontally.
+fn mirror_image(image: &GrayFloatImage) -> GrayFloatImage {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+
+    let mut mirrored = GrayFloatImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            mirrored.put(width - 1 - x, y, image.get(x, y));
+        }
+    }
+
+    mirrored
+}
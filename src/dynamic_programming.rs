@@ -0,0 +1,274 @@
+//! # Scanline dynamic programming disparity computation
+//!
+//! This module provides a scanline dynamic programming matcher in the style of
+//! ("A Maximum Likelihood Stereo Algorithm")[https://doi.org/10.1006/cviu.1996.0019] by Cox et
+//! al.
+//!
+//! Each row is matched independently by finding the cheapest monotonic path through the grid of
+//! (left pixel, right pixel) pairs. A step along the path either matches a pair of pixels, at the
+//! window matching cost, or skips a pixel in one image as occluded, at a fixed occlusion cost.
+//! The monotonic path enforces the ordering constraint, and the left pixels which are skipped are
+//! labelled as occluded in the output map. Disparities are integers.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::StereoFrame;
+use serde::Deserialize;
+
+use crate::colour::ColourStereoFrame;
+use crate::cost::{CostFunction, MatchingCost};
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::prior::DisparityPrior;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct DynamicProgramming {
+    params: Params
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Side length of the square window the matching cost is averaged over, which should be
+    /// odd. A size of 1 matches single pixels.
+    pub window_size: usize,
+
+    /// Cost of labelling a pixel as occluded, in the same units as the matching cost.
+    pub occlusion_cost: f32,
+
+    /// Per-pixel matching cost which is averaged over the window.
+    #[serde(default)]
+    pub cost: CostFunction
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Step taken into a node of the dynamic programming grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Step {
+    /// The left and right pixels match.
+    Match,
+
+    /// The left pixel is occluded in the right image.
+    OccludedLeft,
+
+    /// The right pixel is occluded in the left image.
+    OccludedRight
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl DynamicProgramming {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        Self { params }
+    }
+
+    /// Mean matching cost over the window around `(x, y)` at disparity `d`.
+    fn window_cost(
+        &self,
+        cost: &dyn MatchingCost,
+        frame: &StereoFrame,
+        x: usize,
+        y: usize,
+        d: usize
+    ) -> f32 {
+        let width = frame.width() as isize;
+        let height = frame.height() as isize;
+        let radius = (self.params.window_size / 2) as isize;
+
+        let mut total = 0.0f32;
+        let mut count = 0usize;
+
+        for j in -radius..radius + 1 {
+            for i in -radius..radius + 1 {
+                let qx = x as isize + i;
+                let qy = y as isize + j;
+
+                if qx < d as isize || qx >= width || qy < 0 || qy >= height {
+                    continue;
+                }
+
+                total += cost.cost(qx as usize, qy as usize, d);
+                count += 1;
+            }
+        }
+
+        total / count.max(1) as f32
+    }
+
+    /// Compute the disparity map for the given frame, with an optional search range prior.
+    ///
+    /// If the colour frame is given, the greyscale frame must be its greyscale frame, and the
+    /// matching cost uses colour.
+    fn compute_impl(
+        &mut self,
+        frame: &StereoFrame,
+        prior: Option<&DisparityPrior>,
+        colour: Option<&ColourStereoFrame>
+    ) -> Result<DisparityMap> {
+        if self.params.max_disparity <= self.params.min_disparity {
+            return Err(Error::InvalidParams(
+                "max_disparity must be greater than min_disparity".into()
+            ));
+        }
+
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let cost = match colour {
+            Some(c) => self.params.cost.build_colour(c)?,
+            None => self.params.cost.build(frame)?
+        };
+        let occ = self.params.occlusion_cost;
+
+        // Nodes of the grid are indexed by the number of left pixels consumed, i, and the
+        // disparity k between the left and right pixels consumed. Disparities below the minimum
+        // can still be reached through occlusions.
+        let num_k = self.params.max_disparity;
+
+        let mut match_costs = vec![f32::INFINITY; width * num_k];
+        let mut table = vec![f32::INFINITY; (width + 1) * num_k];
+        let mut steps = vec![Step::Match; (width + 1) * num_k];
+
+        let mut disp_map = DisparityMap::new(width, height);
+
+        for y in 0..height {
+
+            // ---- MATCHING COSTS ----
+
+            for x in 0..width {
+                let mut search = match prior {
+                    Some(p) => p.search_range(
+                        x, y,
+                        self.params.min_disparity..self.params.max_disparity
+                    ),
+                    None => self.params.min_disparity..self.params.max_disparity
+                };
+                search.end = search.end.min(x + 1);
+
+                for k in 0..num_k {
+                    match_costs[x * num_k + k] = match search.contains(&k) {
+                        true => self.window_cost(&*cost, frame, x, y, k),
+                        false => f32::INFINITY
+                    };
+                }
+            }
+
+            // ---- FORWARD PASS ----
+
+            for t in table.iter_mut() {
+                *t = f32::INFINITY;
+            }
+            table[0] = 0.0;
+
+            for i in 1..width + 1 {
+                let max_k = num_k.min(i + 1);
+
+                // Right occlusions come from the next disparity at the same i, so iterate
+                // disparities downwards
+                for k in (0..max_k).rev() {
+                    let mut best = (f32::INFINITY, Step::Match);
+
+                    if k < i {
+                        let c = table[(i - 1) * num_k + k] + match_costs[(i - 1) * num_k + k];
+                        if c < best.0 {
+                            best = (c, Step::Match);
+                        }
+                    }
+
+                    if k > 0 {
+                        let c = table[(i - 1) * num_k + k - 1] + occ;
+                        if c < best.0 {
+                            best = (c, Step::OccludedLeft);
+                        }
+                    }
+
+                    if k + 1 < max_k {
+                        let c = table[i * num_k + k + 1] + occ;
+                        if c < best.0 {
+                            best = (c, Step::OccludedRight);
+                        }
+                    }
+
+                    table[i * num_k + k] = best.0;
+                    steps[i * num_k + k] = best.1;
+                }
+            }
+
+            // ---- BACKTRACKING ----
+
+            // Both scanlines are fully consumed at the end of the path
+            let (mut i, mut k) = (width, 0);
+
+            while i > 0 {
+                match steps[i * num_k + k] {
+                    Step::Match => {
+                        disp_map.put(i - 1, y, k as f32);
+                        i -= 1;
+                    },
+                    Step::OccludedLeft => {
+                        disp_map.occlude(i - 1, y);
+                        i -= 1;
+                        k -= 1;
+                    },
+                    Step::OccludedRight => {
+                        k += 1;
+                    }
+                }
+            }
+        }
+
+        disp_map.update_range();
+
+        Ok(disp_map)
+    }
+}
+
+impl DisparityAlgorithm for DynamicProgramming {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        self.compute_impl(frame, None, None)
+    }
+
+    fn compute_with_prior(
+        &mut self,
+        frame: &StereoFrame,
+        prior: &DisparityPrior
+    ) -> Result<DisparityMap> {
+        prior.check_size(frame.width() as usize, frame.height() as usize)?;
+
+        self.compute_impl(frame, Some(prior), None)
+    }
+
+    /// Compute the disparity map for the given colour frame, with the matching cost averaged
+    /// over the channels.
+    fn compute_colour(&mut self, frame: &ColourStereoFrame) -> Result<DisparityMap> {
+        self.compute_impl(&frame.grey, None, Some(frame))
+    }
+
+    /// Margins covering the matching window. Each row is optimised as a whole, so tiled results
+    /// can differ near the tile edges.
+    fn margins(&self) -> Margins {
+        let radius = self.params.window_size / 2;
+
+        Margins {
+            left: radius + self.params.max_disparity,
+            right: radius,
+            top: radius,
+            bottom: radius
+        }
+    }
+}
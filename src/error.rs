@@ -2,6 +2,12 @@
 //! 
 //! This module provides a standardised error enum and result type for this crate.
 
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use crate::tiling::Rect;
+
 // -----------------------------------------------------------------------------------------------
 // TYPES
 // -----------------------------------------------------------------------------------------------
@@ -16,5 +22,32 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Error was thrown during debugging operations")]
-    Debug
+    Debug,
+
+    #[error("This algorithm does not support disparity search priors")]
+    PriorUnsupported,
+
+    #[error("Disparity prior is {prior:?} pixels but the frame is {frame:?} pixels")]
+    PriorSizeMismatch {
+        prior: (usize, usize),
+        frame: (usize, usize)
+    },
+
+    #[error("Region of interest {0:?} does not overlap the frame")]
+    EmptyRegion(Rect),
+
+    #[error("Invalid parameters: {0}")]
+    InvalidParams(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Image error: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Malformed file: {0}")]
+    Format(String)
 }
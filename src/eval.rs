@@ -0,0 +1,362 @@
+//! # Evaluation against ground truth
+//!
+//! This module scores a computed disparity map against a ground truth map, using the error
+//! measures of the ("Middlebury Stereo Evaluation")[https://vision.middlebury.edu/stereo/eval3/]:
+//!
+//! - `bad_N`, the percentage of evaluated pixels whose disparity is more than N pixels from the
+//!   truth, for N of 0.5, 1, 2 and 4.
+//! - The mean and RMS absolute error.
+//!
+//! Pixels are evaluated where the ground truth is valid, restricted to the non-occluded pixels
+//! when a [`Region::NonOccluded`] evaluation is asked for. Pixels the algorithm left without a
+//! valid disparity count as bad at every threshold, so that sparse maps cannot score well by
+//! only keeping their easy pixels, but are left out of the mean and RMS errors, which are
+//! reported along with the coverage.
+//!
+//! Dataset runners collect the metrics and runtime of each scene into a [`Report`].
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::colour::ColourStereoFrame;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Error thresholds of the `bad_N` measures, in pixels.
+pub const BAD_THRESHOLDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A ground truth disparity map, with an optional mask of the pixels which are visible in both
+/// images.
+pub struct GroundTruth {
+    pub disparity: DisparityMap,
+
+    /// Row-major mask, true where the pixel is not occluded in the other image.
+    pub non_occluded: Option<Vec<bool>>
+}
+
+/// Error measures of a disparity map against ground truth.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    /// Number of pixels evaluated.
+    pub evaluated: usize,
+
+    /// Fraction of the evaluated pixels with a valid disparity estimate.
+    pub coverage: f32,
+
+    /// Percentage of evaluated pixels with an error above each of [`BAD_THRESHOLDS`].
+    pub bad: [f32; 4],
+
+    /// Mean absolute error over the evaluated pixels with a valid estimate.
+    pub avg_error: f32,
+
+    /// Root mean square error over the evaluated pixels with a valid estimate.
+    pub rms_error: f32
+}
+
+/// Running totals from which [`Metrics`] are computed, which can be added to over several maps.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accumulator {
+    evaluated: usize,
+    estimated: usize,
+    bad: [usize; 4],
+    abs_error: f64,
+    sq_error: f64
+}
+
+/// Result of running an algorithm on a single scene.
+#[derive(Debug, Clone)]
+pub struct SceneResult {
+    pub name: String,
+
+    /// Error measures, if the scene has ground truth.
+    pub metrics: Option<Metrics>,
+
+    /// Time taken to compute the disparity map.
+    pub runtime: Duration
+}
+
+/// Results of running an algorithm over a set of scenes, which displays as a table.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub region: Region,
+    pub scenes: Vec<SceneResult>
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The pixels an evaluation covers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Region {
+    /// Every pixel with a valid ground truth disparity.
+    All,
+
+    /// Only the pixels which are also visible in the other image. Equivalent to `All` if the
+    /// ground truth has no occlusion mask.
+    NonOccluded
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl GroundTruth {
+    /// Create a ground truth without an occlusion mask.
+    pub fn new(disparity: DisparityMap) -> Self {
+        Self {
+            disparity,
+            non_occluded: None
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.disparity.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.disparity.height()
+    }
+
+    /// Whether the given pixel is part of the given region.
+    pub fn in_region(&self, x: usize, y: usize, region: Region) -> bool {
+        if !self.disparity.is_valid(x, y) {
+            return false;
+        }
+
+        match (region, &self.non_occluded) {
+            (Region::NonOccluded, Some(mask)) => mask[y * self.width() + x],
+            _ => true
+        }
+    }
+}
+
+impl Metrics {
+    /// Evaluate a disparity map over the given region of the ground truth.
+    pub fn compute(
+        estimate: &DisparityMap,
+        truth: &GroundTruth,
+        region: Region
+    ) -> Result<Self> {
+        let mut acc = Accumulator::default();
+        acc.add(estimate, truth, region)?;
+
+        Ok(acc.metrics())
+    }
+}
+
+impl Accumulator {
+    /// Add the pixels of a disparity map over the given region of the ground truth.
+    pub fn add(
+        &mut self,
+        estimate: &DisparityMap,
+        truth: &GroundTruth,
+        region: Region
+    ) -> Result<()> {
+        check_size(estimate, truth)?;
+
+        for y in 0..truth.height() {
+            for x in 0..truth.width() {
+                if !truth.in_region(x, y, region) {
+                    continue;
+                }
+
+                self.evaluated += 1;
+
+                if !estimate.is_valid(x, y) {
+                    for b in self.bad.iter_mut() {
+                        *b += 1;
+                    }
+                    continue;
+                }
+
+                let error = (estimate.get(x, y) - truth.disparity.get(x, y)).abs();
+
+                self.estimated += 1;
+                self.abs_error += error as f64;
+                self.sq_error += (error * error) as f64;
+
+                for (b, &t) in self.bad.iter_mut().zip(BAD_THRESHOLDS.iter()) {
+                    if error > t {
+                        *b += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Metrics of all the pixels added so far.
+    pub fn metrics(&self) -> Metrics {
+        if self.evaluated == 0 {
+            return Metrics::default();
+        }
+
+        let mut bad = [0.0; 4];
+        for (b, &count) in bad.iter_mut().zip(self.bad.iter()) {
+            *b = 100.0 * count as f32 / self.evaluated as f32;
+        }
+
+        let (avg_error, rms_error) = match self.estimated {
+            0 => (0.0, 0.0),
+            n => (
+                (self.abs_error / n as f64) as f32,
+                (self.sq_error / n as f64).sqrt() as f32
+            )
+        };
+
+        Metrics {
+            evaluated: self.evaluated,
+            coverage: self.estimated as f32 / self.evaluated as f32,
+            bad,
+            avg_error,
+            rms_error
+        }
+    }
+}
+
+impl SceneResult {
+    /// Compute a scene with an algorithm and evaluate it against the scene's ground truth, if it
+    /// has any, returning the disparity map and the result.
+    ///
+    /// The colour frame is given to the algorithm, so that algorithms which support colour
+    /// make use of it.
+    pub fn compute<A: DisparityAlgorithm + ?Sized>(
+        name: &str,
+        algorithm: &mut A,
+        frame: &ColourStereoFrame,
+        truth: Option<&GroundTruth>,
+        region: Region
+    ) -> Result<(DisparityMap, Self)> {
+        let start = Instant::now();
+        let map = algorithm.compute_colour(frame)?;
+        let runtime = start.elapsed();
+
+        let metrics = match truth {
+            Some(t) => Some(Metrics::compute(&map, t, region)?),
+            None => None
+        };
+
+        let result = Self {
+            name: name.to_string(),
+            metrics,
+            runtime
+        };
+
+        Ok((map, result))
+    }
+}
+
+impl Report {
+    /// Mean of the error measures over the scenes with ground truth, as reported by the
+    /// Middlebury evaluation, which weights every scene equally regardless of its size.
+    pub fn mean(&self) -> Option<Metrics> {
+        let metrics: Vec<&Metrics> = self.scenes
+            .iter()
+            .filter_map(|s| s.metrics.as_ref())
+            .collect();
+
+        if metrics.is_empty() {
+            return None;
+        }
+
+        let n = metrics.len() as f32;
+        let mut mean = Metrics::default();
+
+        for m in &metrics {
+            mean.evaluated += m.evaluated;
+            mean.coverage += m.coverage / n;
+            mean.avg_error += m.avg_error / n;
+            mean.rms_error += m.rms_error / n;
+
+            for (b, mb) in mean.bad.iter_mut().zip(m.bad.iter()) {
+                *b += mb / n;
+            }
+        }
+
+        Some(mean)
+    }
+
+    /// Total time spent computing disparity maps.
+    pub fn total_runtime(&self) -> Duration {
+        self.scenes.iter().map(|s| s.runtime).sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<16}", "scene")?;
+        for t in BAD_THRESHOLDS.iter() {
+            write!(f, " {:>8}", format!("bad{}", t))?;
+        }
+        writeln!(f, " {:>8} {:>8} {:>8} {:>10}", "avgerr", "rms", "coverage", "time (s)")?;
+
+        for scene in &self.scenes {
+            write_row(f, &scene.name, scene.metrics.as_ref(), Some(scene.runtime))?;
+        }
+
+        write_row(f, "mean", self.mean().as_ref(), None)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Check the estimate and ground truth have the same size.
+fn check_size(estimate: &DisparityMap, truth: &GroundTruth) -> Result<()> {
+    let truth_size = (truth.width(), truth.height());
+
+    match (estimate.width(), estimate.height()) == truth_size {
+        true => Ok(()),
+        false => Err(Error::InvalidParams(format!(
+            "disparity map is {:?} pixels but the ground truth is {:?} pixels",
+            (estimate.width(), estimate.height()),
+            truth_size
+        )))
+    }
+}
+
+/// Write a row of the report table.
+fn write_row(
+    f: &mut fmt::Formatter,
+    name: &str,
+    metrics: Option<&Metrics>,
+    runtime: Option<Duration>
+) -> fmt::Result {
+    write!(f, "{:<16}", name)?;
+
+    match metrics {
+        Some(m) => {
+            for b in m.bad.iter() {
+                write!(f, " {:>8.2}", b)?;
+            }
+            write!(f, " {:>8.3} {:>8.3} {:>8.3}", m.avg_error, m.rms_error, m.coverage)?;
+        },
+        None => {
+            for _ in 0..BAD_THRESHOLDS.len() + 3 {
+                write!(f, " {:>8}", "-")?;
+            }
+        }
+    }
+
+    match runtime {
+        Some(r) => writeln!(f, " {:>10.3}", r.as_secs_f32()),
+        None => writeln!(f)
+    }
+}
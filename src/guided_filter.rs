@@ -0,0 +1,189 @@
+//! # Guided filter
+//!
+//! This module provides the edge-preserving guided filter from
+//! ("Guided Image Filtering")[https://doi.org/10.1109/TPAMI.2012.213] by He et al.
+//!
+//! The output is modelled as a local linear transform of a guidance image, fitted to the input
+//! over a square window around each pixel. Edges in the guide are therefore kept in the output,
+//! while the input is smoothed elsewhere. Every step is a box filter, so the cost per pixel is
+//! independent of the window size.
+//!
+//! The statistics of the guide are computed once, so the same filter can be applied to many
+//! inputs, for example each disparity slice of a cost volume.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::GrayFloatImage;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A guided filter with precomputed guide statistics.
+pub struct GuidedFilter {
+    width: usize,
+    height: usize,
+    radius: usize,
+    epsilon: f32,
+
+    /// Guide intensities in row-major order.
+    guide: Vec<f32>,
+
+    /// Mean of the guide over the window around each pixel.
+    mean_guide: Vec<f32>,
+
+    /// Variance of the guide over the window around each pixel.
+    var_guide: Vec<f32>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl GuidedFilter {
+    /// Create a new filter from the guide image.
+    ///
+    /// The window is `2 * radius + 1` pixels square, and `epsilon` is the regularisation which
+    /// sets how large an intensity variance counts as an edge, in squared intensity units.
+    pub fn new(guide: &GrayFloatImage, radius: usize, epsilon: f32) -> Self {
+        let width = guide.width() as usize;
+        let height = guide.height() as usize;
+
+        let mut values = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                values.push(guide.get(x, y));
+            }
+        }
+
+        let squares: Vec<f32> = values.iter().map(|v| v * v).collect();
+
+        let mean_guide = box_mean(&values, width, height, radius);
+        let var_guide = box_mean(&squares, width, height, radius)
+            .iter()
+            .zip(mean_guide.iter())
+            .map(|(sq, m)| (sq - m * m).max(0.0))
+            .collect();
+
+        Self {
+            width,
+            height,
+            radius,
+            epsilon,
+            guide: values,
+            mean_guide,
+            var_guide
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Filter a row-major input with the same dimensions as the guide.
+    pub fn filter(&self, input: &[f32]) -> Vec<f32> {
+        let (w, h, r) = (self.width, self.height, self.radius);
+
+        let products: Vec<f32> = input
+            .iter()
+            .zip(self.guide.iter())
+            .map(|(p, i)| p * i)
+            .collect();
+
+        let mean_input = box_mean(input, w, h, r);
+        let mean_product = box_mean(&products, w, h, r);
+
+        // Coefficients of the linear model in each window
+        let mut a = Vec::with_capacity(w * h);
+        let mut b = Vec::with_capacity(w * h);
+
+        let guide_stats = self.mean_guide.iter().zip(self.var_guide.iter());
+        let input_stats = mean_input.iter().zip(mean_product.iter());
+
+        for ((&mean_i, &var_i), (&mean_p, &mean_ip)) in guide_stats.zip(input_stats) {
+            let cov = mean_ip - mean_i * mean_p;
+            let a_i = cov / (var_i + self.epsilon);
+
+            a.push(a_i);
+            b.push(mean_p - a_i * mean_i);
+        }
+
+        // Average the models of every window covering each pixel
+        let mean_a = box_mean(&a, w, h, r);
+        let mean_b = box_mean(&b, w, h, r);
+
+        mean_a
+            .iter()
+            .zip(mean_b.iter())
+            .zip(self.guide.iter())
+            .map(|((a, b), i)| a * i + b)
+            .collect()
+    }
+
+    /// Filter an image with the same dimensions as the guide.
+    pub fn filter_image(&self, input: &GrayFloatImage) -> GrayFloatImage {
+        let mut values = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                values.push(input.get(x, y));
+            }
+        }
+
+        let filtered = self.filter(&values);
+
+        let mut output = GrayFloatImage::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                output.put(x, y, filtered[y * self.width + x]);
+            }
+        }
+
+        output
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Mean of a row-major image over the square window around each pixel, clipped to the image.
+fn box_mean(data: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    // Integral image with a leading row and column of zeros, accumulated in f64 so that large
+    // images do not lose precision
+    let stride = width + 1;
+    let mut integral = vec![0.0f64; stride * (height + 1)];
+
+    for y in 0..height {
+        let mut row_sum = 0.0f64;
+
+        for x in 0..width {
+            row_sum += data[y * width + x] as f64;
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row_sum;
+        }
+    }
+
+    let mut means = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+
+        for x in 0..width {
+            let x0 = x.saturating_sub(radius);
+            let x1 = (x + radius + 1).min(width);
+
+            let sum = integral[y1 * stride + x1] - integral[y0 * stride + x1]
+                - integral[y1 * stride + x0] + integral[y0 * stride + x0];
+
+            means.push((sum / ((x1 - x0) * (y1 - y0)) as f64) as f32);
+        }
+    }
+
+    means
+}
@@ -0,0 +1,259 @@
+//! # Disparity map files
+//!
+//! This module reads and writes disparity maps as Portable Float Maps (PFM), the format used for
+//! the ground truth of the
+//! ("Middlebury Stereo Evaluation")[https://vision.middlebury.edu/stereo/eval3/] and by most
+//! stereo tooling.
+//!
+//! A PFM file holds a short text header giving the size and byte order followed by raw 32-bit
+//! floats, stored bottom row first. Pixels without a valid disparity are stored as infinity,
+//! following the Middlebury convention, so validity survives a round trip but the distinction
+//! between invalid and occluded pixels does not.
+//!
+//! Disparity maps can also be reprojected into 3D with a [`StereoGeometry`] and written as ASCII
+//! PLY point clouds.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use cv_camstream::GrayFloatImage;
+
+use crate::disparity::DisparityMap;
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Geometry of a rectified stereo camera, used to reproject disparities into 3D.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoGeometry {
+    /// Focal length in pixels.
+    pub focal: f32,
+
+    /// Distance between the cameras, in the units of the reprojected points.
+    pub baseline: f32,
+
+    /// Principal point of the left camera in pixels.
+    pub principal_point: (f32, f32),
+
+    /// Difference in the x coordinate of the principal points, `cx_right - cx_left`, in pixels.
+    pub doffs: f32
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl StereoGeometry {
+    /// Reproject a left image pixel with the given disparity into a point in the left camera
+    /// frame, with x right, y down and z forward.
+    ///
+    /// Returns `None` if the point would be at or behind infinity.
+    pub fn reproject(&self, x: usize, y: usize, disparity: f32) -> Option<[f32; 3]> {
+        let d = disparity + self.doffs;
+
+        if d <= 0.0 {
+            return None;
+        }
+
+        let z = self.focal * self.baseline / d;
+
+        Some([
+            (x as f32 - self.principal_point.0) * z / self.focal,
+            (y as f32 - self.principal_point.1) * z / self.focal,
+            z
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Read a greyscale PFM file as a disparity map.
+pub fn read_pfm<P: AsRef<Path>>(path: P) -> Result<DisparityMap> {
+    read_pfm_from(&mut BufReader::new(File::open(path)?))
+}
+
+/// Read a greyscale PFM from the given reader as a disparity map.
+///
+/// Infinite and NaN values are read as invalid pixels.
+pub fn read_pfm_from<R: BufRead>(reader: &mut R) -> Result<DisparityMap> {
+    let magic = read_token(reader)?;
+    if magic != "Pf" {
+        return Err(Error::Format(format!(
+            "expected a greyscale PFM header \"Pf\", found {:?}", magic
+        )));
+    }
+
+    let width: usize = parse_token(reader, "width")?;
+    let height: usize = parse_token(reader, "height")?;
+    let scale: f32 = parse_token(reader, "scale")?;
+
+    let little_endian = scale < 0.0;
+
+    let mut bytes = vec![0u8; width * height * 4];
+    reader.read_exact(&mut bytes)?;
+
+    let mut map = DisparityMap::new(width, height);
+
+    for (i, chunk) in bytes.chunks_exact(4).enumerate() {
+        let raw = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        let val = match little_endian {
+            true => f32::from_le_bytes(raw),
+            false => f32::from_be_bytes(raw)
+        };
+
+        // Rows are stored bottom first
+        let x = i % width;
+        let y = height - 1 - i / width;
+
+        if val.is_finite() {
+            map.put(x, y, val);
+        }
+    }
+
+    map.update_range();
+
+    Ok(map)
+}
+
+/// Write a disparity map as a little endian greyscale PFM file.
+pub fn write_pfm<P: AsRef<Path>>(map: &DisparityMap, path: P) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    write_pfm_to(map, &mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write a disparity map as a little endian greyscale PFM to the given writer.
+///
+/// Pixels without a valid disparity are written as infinity.
+pub fn write_pfm_to<W: Write>(map: &DisparityMap, writer: &mut W) -> Result<()> {
+    write!(writer, "Pf\n{} {}\n-1.0\n", map.width(), map.height())?;
+
+    for y in (0..map.height()).rev() {
+        for x in 0..map.width() {
+            let val = match map.is_valid(x, y) {
+                true => map.get(x, y),
+                false => f32::INFINITY
+            };
+
+            writer.write_all(&val.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reproject a disparity map into 3D and write it as an ASCII PLY point cloud file.
+pub fn write_ply<P: AsRef<Path>>(
+    map: &DisparityMap,
+    image: Option<&GrayFloatImage>,
+    geometry: &StereoGeometry,
+    path: P
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    write_ply_to(map, image, geometry, &mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reproject a disparity map into 3D and write it as an ASCII PLY point cloud to the given
+/// writer.
+///
+/// Only valid pixels which reproject in front of the camera are written. If an image is given
+/// each point is coloured with its intensity.
+pub fn write_ply_to<W: Write>(
+    map: &DisparityMap,
+    image: Option<&GrayFloatImage>,
+    geometry: &StereoGeometry,
+    writer: &mut W
+) -> Result<()> {
+    if let Some(image) = image {
+        let image_size = (image.width() as usize, image.height() as usize);
+
+        if image_size != (map.width(), map.height()) {
+            return Err(Error::InvalidParams(format!(
+                "disparity map is {:?} pixels but the image is {:?} pixels",
+                (map.width(), map.height()),
+                image_size
+            )));
+        }
+    }
+
+    let mut points = Vec::new();
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if !map.is_valid(x, y) {
+                continue;
+            }
+
+            if let Some(p) = geometry.reproject(x, y, map.get(x, y)) {
+                let intensity = image.map(|i| (i.get(x, y).max(0.0).min(1.0) * 255.0) as u8);
+                points.push((p, intensity));
+            }
+        }
+    }
+
+    writeln!(writer, "ply\nformat ascii 1.0\nelement vertex {}", points.len())?;
+    writeln!(writer, "property float x\nproperty float y\nproperty float z")?;
+    if image.is_some() {
+        writeln!(writer, "property uchar red\nproperty uchar green\nproperty uchar blue")?;
+    }
+    writeln!(writer, "end_header")?;
+
+    for (p, intensity) in points {
+        match intensity {
+            Some(i) => writeln!(writer, "{} {} {} {} {} {}", p[0], p[1], p[2], i, i, i)?,
+            None => writeln!(writer, "{} {} {}", p[0], p[1], p[2])?
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a whitespace separated header token, consuming the single whitespace byte after it.
+fn read_token<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut token = Vec::new();
+    let mut byte = [0u8];
+
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            break;
+        }
+
+        if byte[0].is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+
+            break;
+        }
+
+        token.push(byte[0]);
+    }
+
+    String::from_utf8(token)
+        .map_err(|_| Error::Format("PFM header is not valid text".into()))
+}
+
+/// Read and parse a header token, naming the field in the error if it does not parse.
+fn parse_token<R: BufRead, T: std::str::FromStr>(reader: &mut R, field: &str) -> Result<T> {
+    let token = read_token(reader)?;
+
+    token.parse().map_err(|_| Error::Format(format!(
+        "invalid PFM {} {:?}", field, token
+    )))
+}
@@ -6,15 +6,48 @@
 // MODULES
 // -----------------------------------------------------------------------------------------------
 
+pub mod ad_census;
+pub mod asw;
+pub mod batch;
+pub mod belief_propagation;
+pub mod border;
+pub mod colour;
+pub mod config;
+pub mod consistency;
+pub mod cost;
+pub mod cost_filter;
+pub mod cost_volume;
+pub mod datasets;
 mod disparity;
+pub mod dynamic_programming;
 mod error;
+pub mod eval;
+pub mod guided_filter;
+pub mod io;
 pub mod magdeburg;
 pub mod mcmanamon;
+pub mod non_local;
+pub mod patch_match;
+pub mod pipeline;
+pub mod prior;
+pub mod quality;
+pub mod refine;
+pub mod registry;
+pub mod synthetic;
+pub mod tiling;
+pub mod warp;
 
 // -----------------------------------------------------------------------------------------------
 // EXPORTS
 // -----------------------------------------------------------------------------------------------
 
+pub use crate::error::{Error, Result};
+
 pub mod prelude {
-    pub use crate::disparity::{DisparityAlgorithm, DisparityMap};
+    pub use crate::border::BorderMode;
+    pub use crate::colour::{ColourImage, ColourStereoFrame};
+    pub use crate::cost_volume::{CostVolume, CostVolumeAlgorithm};
+    pub use crate::disparity::{DisparityAlgorithm, DisparityMap, Reference, Validity};
+    pub use crate::prior::DisparityPrior;
+    pub use crate::tiling::{Margins, Rect, TiledExecutor};
 }
\ No newline at end of file
@@ -0,0 +1,257 @@
+//! # cv-disparity
+//!
+//! Command line interface for computing disparity maps from image files.
+//!
+//! ```text
+//! cv-disparity compute --left L.png --right R.png --algo mcmanamon --params params.toml \
+//!     --out disp.pfm [--preview disp.png] [--cloud out.ply --focal F --baseline B]
+//! ```
+//!
+//! The params file is the TOML form of the chosen algorithm's `Params`. Algorithms whose
+//! parameters have defaults may omit it. Alternatively `--config pipeline.toml`, or a `.json`
+//! config, gives the algorithm, its params and any pre- and post-filters in one file, see
+//! `cv_disparity::config`.
+//!
+//! Whole directories of pairs are computed with the `batch` subcommand, which writes the maps to
+//! a mirrored tree and a CSV or JSON summary of each frame:
+//!
+//! ```text
+//! cv-disparity batch --input frames --output disp --algo mcmanamon --params params.toml \
+//!     [--left-pattern {}_left.png] [--right-pattern {}_right.png] [--summary summary.csv]
+//! ```
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::path::Path;
+use std::time::Instant;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use cv_disparity::{
+    prelude::*,
+    batch,
+    config::Config,
+    datasets::kitti,
+    io::{self, StereoGeometry},
+    pipeline::Pipeline,
+    registry::ALGORITHMS,
+    Error
+};
+
+// -----------------------------------------------------------------------------------------------
+// MAIN
+// -----------------------------------------------------------------------------------------------
+
+fn main() {
+    let matches = App::new("cv-disparity")
+        .about("Compute disparity maps from stereo image pairs")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(compute_command())
+        .subcommand(batch_command())
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("compute", Some(args)) => compute(args),
+        ("batch", Some(args)) => run_batch(args),
+        _ => unreachable!()
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+fn compute_command() -> App<'static, 'static> {
+    SubCommand::with_name("compute")
+        .about("Compute the disparity map of a single stereo pair")
+        .arg(value_arg("left", "Left image").required(true))
+        .arg(value_arg("right", "Right image").required(true))
+        .args(&algorithm_args())
+        .arg(value_arg("out", "Output disparity map, either .pfm or a KITTI style 16-bit .png")
+            .required(true))
+        .arg(value_arg("preview", "Normalised 8-bit preview image of the disparity map"))
+        .arg(value_arg("cloud", "ASCII PLY point cloud of the reprojected disparity map")
+            .requires_all(&["focal", "baseline"]))
+        .arg(value_arg("focal", "Focal length in pixels, for the point cloud"))
+        .arg(value_arg("baseline", "Distance between the cameras, for the point cloud"))
+        .arg(value_arg("cx", "Principal point x in pixels, defaults to the image centre"))
+        .arg(value_arg("cy", "Principal point y in pixels, defaults to the image centre"))
+        .arg(value_arg("doffs", "Difference in principal point x between the cameras")
+            .default_value("0"))
+}
+
+fn batch_command() -> App<'static, 'static> {
+    SubCommand::with_name("batch")
+        .about("Compute the disparity maps of every stereo pair in a directory tree")
+        .arg(value_arg("input", "Directory to search for stereo pairs").required(true))
+        .arg(value_arg("output", "Directory to write the mirrored tree of maps to")
+            .required(true))
+        .args(&algorithm_args())
+        .arg(value_arg("left-pattern", "Left image file names, with {} for the frame name")
+            .default_value("{}_left.png"))
+        .arg(value_arg("right-pattern", "Right image file names, with {} for the frame name")
+            .default_value("{}_right.png"))
+        .arg(value_arg("format", "Format of the output maps")
+            .possible_values(&["pfm", "png"])
+            .default_value("pfm"))
+        .arg(Arg::with_name("preview")
+            .long("preview")
+            .help("Also write a normalised 8-bit preview of each map"))
+        .arg(Arg::with_name("no-recursive")
+            .long("no-recursive")
+            .help("Only search the top level of the input directory"))
+        .arg(value_arg("summary", "Summary file, JSON if it ends in .json and CSV otherwise, \
+            printed as CSV if not given"))
+}
+
+/// Arguments choosing the algorithm, either by a pipeline config or by name and parameters.
+fn algorithm_args() -> [Arg<'static, 'static>; 3] {
+    [
+        value_arg("config", "TOML or .json pipeline config with the algorithm, params and filters")
+            .conflicts_with_all(&["algo", "params"]),
+        value_arg("algo", "Algorithm to use")
+            .possible_values(&ALGORITHMS)
+            .required_unless("config"),
+        value_arg("params", "TOML file of the algorithm's parameters")
+    ]
+}
+
+fn value_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(name)
+        .takes_value(true)
+        .help(help)
+}
+
+fn compute(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let left = image::open(args.value_of("left").unwrap())?;
+    let right = image::open(args.value_of("right").unwrap())?;
+    let frame = ColourStereoFrame::from_dynamic(&left, &right)?;
+
+    let mut pipeline = build_pipeline(args)?;
+
+    let start = Instant::now();
+    let map = pipeline.compute_colour(&frame)?;
+    eprintln!(
+        "computed {}x{} disparity map in {:.3} s, {:.1}% valid",
+        map.width(), map.height(), start.elapsed().as_secs_f32(), map.density() * 100.0
+    );
+
+    write_map(&map, Path::new(args.value_of("out").unwrap()))?;
+
+    if let Some(path) = args.value_of("preview") {
+        map.to_luma_normalised().save(path)?;
+    }
+
+    if let Some(path) = args.value_of("cloud") {
+        let geometry = StereoGeometry {
+            focal: parse_value(args, "focal")?,
+            baseline: parse_value(args, "baseline")?,
+            principal_point: (
+                parse_or(args, "cx", (map.width() as f32 - 1.0) / 2.0)?,
+                parse_or(args, "cy", (map.height() as f32 - 1.0) / 2.0)?
+            ),
+            doffs: parse_value(args, "doffs")?
+        };
+
+        io::write_ply(&map, Some(&frame.grey.left), &geometry, path)?;
+    }
+
+    Ok(())
+}
+
+fn run_batch(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = build_pipeline(args)?;
+
+    let params = batch::Params {
+        left_pattern: args.value_of("left-pattern").unwrap().into(),
+        right_pattern: args.value_of("right-pattern").unwrap().into(),
+        recursive: !args.is_present("no-recursive"),
+        format: match args.value_of("format").unwrap() {
+            "png" => batch::OutputFormat::Png,
+            _ => batch::OutputFormat::Pfm
+        },
+        preview: args.is_present("preview")
+    };
+
+    let summary = batch::run(
+        &mut pipeline,
+        args.value_of("input").unwrap(),
+        args.value_of("output").unwrap(),
+        &params
+    )?;
+
+    for frame in summary.failures() {
+        eprintln!("{} failed: {}", frame.name, frame.error.as_deref().unwrap_or_default());
+    }
+    eprintln!(
+        "computed {} of {} frames in {:.3} s",
+        summary.frames.len() - summary.failures().count(),
+        summary.frames.len(),
+        summary.total_runtime()
+    );
+
+    match args.value_of("summary") {
+        Some(path) => summary.save(path)?,
+        None => summary.write_csv_to(&mut std::io::stdout().lock())?
+    }
+
+    Ok(())
+}
+
+/// Build the pipeline from the config file, or from the algorithm and params file if no config
+/// is given.
+fn build_pipeline(args: &ArgMatches) -> Result<Pipeline, Box<dyn std::error::Error>> {
+    let config = match args.value_of("config") {
+        Some(path) => Config::load(path)?,
+        None => {
+            let params = match args.value_of("params") {
+                Some(path) => Some(std::fs::read_to_string(path)?.parse::<toml::Value>()?),
+                None => None
+            };
+
+            Config::new(args.value_of("algo").unwrap(), params)
+        }
+    };
+
+    Ok(config.build()?)
+}
+
+/// Write the disparity map in the format given by the path's extension.
+fn write_map(map: &DisparityMap, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("pfm") => io::write_pfm(map, path)?,
+        Some("png") => kitti::write_disparity(map, path)?,
+        _ => return Err(Error::InvalidParams(format!(
+            "unsupported output format {}, expected .pfm or .png", path.display()
+        )).into())
+    }
+
+    Ok(())
+}
+
+fn parse_value(args: &ArgMatches, name: &str) -> Result<f32, Box<dyn std::error::Error>> {
+    let value = args.value_of(name).unwrap();
+
+    value.parse().map_err(|_| {
+        Error::InvalidParams(format!("--{} must be a number, found {:?}", name, value)).into()
+    })
+}
+
+fn parse_or(
+    args: &ArgMatches,
+    name: &str,
+    default: f32
+) -> Result<f32, Box<dyn std::error::Error>> {
+    match args.is_present(name) {
+        true => parse_value(args, name),
+        false => Ok(default)
+    }
+}
@@ -10,8 +10,13 @@
 use cv_camstream::StereoFrame;
 use serde::Deserialize;
 
+use crate::border::BorderMode;
+use crate::cost::subpixel_minimum;
+use crate::cost_volume::{CostVolume, CostVolumeAlgorithm};
 use crate::disparity::{DisparityAlgorithm, DisparityMap};
 use crate::error::*;
+use crate::prior::DisparityPrior;
+use crate::tiling::Margins;
 
 #[cfg(feature = "statistics")]
 use plotters::prelude::*;
@@ -23,7 +28,8 @@ use plotters::prelude::*;
 pub struct McManamon {
     params: Params,
     corr_window_x_range: std::ops::Range<isize>,
-    corr_window_y_range: std::ops::Range<isize>
+    corr_window_y_range: std::ops::Range<isize>,
+    drift: Option<CriterionDrift>
 }
 
 #[derive(Deserialize, Debug)]
@@ -31,7 +37,36 @@ pub struct Params {
     pub min_disparity: usize,
     pub max_disparity: usize,
     pub dyn_disparity_threshold: usize,
-    pub correlation_window_size: (usize, usize)
+    pub correlation_window_size: (usize, usize),
+
+    /// How pixels whose correlation window leaves the image are handled.
+    #[serde(default)]
+    pub border_mode: BorderMode,
+
+    /// Whether to check every incrementally updated criterion against a full evaluation of its
+    /// window, recording the drift between them. This roughly doubles the correlation time, so is
+    /// meant for debugging and testing.
+    #[serde(default)]
+    pub verify_criterion: bool
+}
+
+/// Drift between the incrementally updated criteria and full evaluations of the same windows,
+/// recorded when `verify_criterion` is set.
+#[derive(Clone, Debug, Default)]
+pub struct CriterionDrift {
+    /// Number of incrementally updated criteria checked.
+    pub checked: usize,
+
+    /// Largest absolute difference from the full criterion.
+    pub max_abs: f32,
+
+    /// Largest difference relative to the full criterion.
+    pub max_rel: f32,
+
+    /// Position and disparity `(x, y, d)` of the largest absolute difference.
+    pub worst: Option<(usize, usize, usize)>,
+
+    total_abs: f64
 }
 
 /// Criterion tripple with total, left column and right column values.
@@ -66,10 +101,17 @@ impl McManamon {
         Self { 
             params,
             corr_window_x_range,
-            corr_window_y_range
+            corr_window_y_range,
+            drift: None
         }
     }
 
+    /// Drift between the incrementally updated and full criteria in the last computation, if
+    /// `verify_criterion` is set.
+    pub fn criterion_drift(&self) -> Option<&CriterionDrift> {
+        self.drift.as_ref()
+    }
+
     /// Calculate the correlation criterion for the given position and disparity.
     fn get_criterion(&self, frame: &StereoFrame, x: usize, y: usize, d: usize) -> CritTripple {
         let mut middle = 0.0f32;
@@ -108,6 +150,11 @@ impl McManamon {
 
     /// Calculate the correlation criterion tripple for the given position and disparity using the
     /// optimised method.
+    ///
+    /// The window is the window of the pixel to the left with its left column removed and its
+    /// right column added. The right column is the right column of the window below, which rows
+    /// are processed bottom up so has already been computed, moved up a row, so only its bottom
+    /// pixel needs removing and a new top pixel adding.
     fn get_criterion_fast(
         &self, 
         frame: &StereoFrame, 
@@ -157,11 +204,57 @@ impl McManamon {
         }
 
     }
-}
 
-impl DisparityAlgorithm for McManamon {
-    /// Compute the disparity map for the given frame.
-    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+    /// Calculate the correlation criterion for a window which may overlap the image border,
+    /// sampling outside pixels according to the border mode.
+    ///
+    /// Returns `None` if no part of the window could be sampled.
+    fn get_criterion_border(
+        &self, 
+        frame: &StereoFrame, 
+        x: usize, 
+        y: usize, 
+        d: usize
+    ) -> Option<f32> {
+        let mode = self.params.border_mode;
+
+        let mut total = 0.0f32;
+        let mut count = 0usize;
+
+        for j in self.corr_window_y_range.clone() {
+            for i in self.corr_window_x_range.clone() {
+                let xi = x as isize + i;
+                let yj = y as isize + j;
+
+                let left = mode.sample(&frame.left, xi, yj);
+                let right = mode.sample(&frame.right, xi - d as isize, yj);
+
+                if let (Some(l), Some(r)) = (left, right) {
+                    total += (l - r).abs();
+                    count += 1;
+                }
+            }
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        // Scale partial windows up to the full window size
+        let full = self.corr_window_x_range.len() * self.corr_window_y_range.len();
+
+        Some(total * full as f32 / count as f32)
+    }
+
+    /// Compute the disparity map for the given frame, with an optional search range prior.
+    ///
+    /// If a cost volume is given every criterion evaluated is recorded in it.
+    fn compute_impl(
+        &mut self, 
+        frame: &StereoFrame, 
+        prior: Option<&DisparityPrior>,
+        mut volume: Option<&mut CostVolume>
+    ) -> Result<DisparityMap> {
         // println!("Computing disparity with following parameters: {:#?}", self.params);
         // println!("x_range: {:?}, y_range: {:?}", self.corr_window_x_range, self.corr_window_y_range);
 
@@ -193,10 +286,17 @@ impl DisparityAlgorithm for McManamon {
         let mut min_disp = self.params.max_disparity as f32;
         let mut max_disp = self.params.min_disparity as f32;
 
-        // Vector for holding criterion values in the row below.
-        // Indexed as below_crits[x][d].unwrap()
+        // Right column criteria of the row below, and of this row for use by the row above.
+        // Indexed as below_right_col_crits[x][d].unwrap()
         let mut below_right_col_crits: Vec<Vec<Option<f32>>> = 
             vec![vec![None; self.params.max_disparity]; frame.width() as usize];
+        let mut right_col_crits = below_right_col_crits.clone();
+
+        // Drift of the fast criteria from the slow, if verifying
+        let mut drift = match self.params.verify_criterion {
+            true => Some(CriterionDrift::default()),
+            false => None
+        };
 
         // Iterate through rows backwards
         for y in (
@@ -220,37 +320,61 @@ impl DisparityAlgorithm for McManamon {
             let mut left_crits: Vec<Option<CritTripple>> = 
                 vec![None; self.params.max_disparity]; 
 
+            // The criteria of the last row become the row below. The buffer for this row is
+            // cleared entirely, since the range of x searched changes from row to row, and a
+            // value left from an older row would otherwise be taken as the row below.
+            std::mem::swap(&mut below_right_col_crits, &mut right_col_crits);
+            for c in right_col_crits.iter_mut().flatten() {
+                *c = None;
+            }
+
             for x in 
                 self.params.correlation_window_size.0 + max_dyn_disp
                 ..
                 (frame.width() as usize - self.params.correlation_window_size.0) 
             {
 
-                // Make copy of the crit array below this one and clear the original
-                let below_right_cols_copy = below_right_col_crits[x].clone();
-                for c in &mut below_right_col_crits[x] {
-                    *c = None;
-                }
-
                 // Make copy of the left crit values array and clear the original
                 let left_crits_copy = left_crits.clone();
                 for c in &mut left_crits {
                     *c = None;
                 }
                 
+                // Disparities to search at this pixel, restricted by the prior if there is one
+                let search = match prior {
+                    Some(p) => {
+                        let range = p.search_range(
+                            x, y, 
+                            self.params.min_disparity..self.params.max_disparity
+                        );
+
+                        if range.start.max(min_dyn_disp) < range.end.min(max_dyn_disp) {
+                            range.start.max(min_dyn_disp)..range.end.min(max_dyn_disp)
+                        }
+                        else {
+                            // Keep the window inside the right image
+                            let limit = x + 1 - self.params.correlation_window_size.0;
+                            range.start..range.end.min(limit)
+                        }
+                    },
+                    None => min_dyn_disp..max_dyn_disp
+                };
+
+                if search.start >= search.end {
+                    continue;
+                }
+
                 // Vector of criterions
-                let mut crits: Vec<f32> = Vec::with_capacity(
-                    max_dyn_disp - min_dyn_disp
-                );
+                let mut crits: Vec<f32> = Vec::with_capacity(search.end - search.start);
 
                 // Calculate criterion for each disparity
-                for d in min_dyn_disp..max_dyn_disp {
+                for d in search.clone() {
                     let crit_tripple: CritTripple;
 
                     // If bottom row or first pixel in row use slow method
                     if left_crits_copy[d].is_none()
                         ||
-                        below_right_cols_copy[d].is_none()
+                        below_right_col_crits[x][d].is_none()
                     {
                         crit_tripple = self.get_criterion(frame, x, y, d);
 
@@ -265,9 +389,14 @@ impl DisparityAlgorithm for McManamon {
                             frame,
                             x, y, d,
                             left_crits_copy[d].unwrap(),
-                            below_right_cols_copy[d].unwrap()
+                            below_right_col_crits[x][d].unwrap()
                         );
 
+                        if let Some(drift) = drift.as_mut() {
+                            let full = self.get_criterion(frame, x, y, d);
+                            drift.record((x, y, d), crit_tripple.total, full.total);
+                        }
+
                         #[cfg(feature = "statistics")]
                         {
                             num_crit_assessments.1 += 1;
@@ -277,48 +406,19 @@ impl DisparityAlgorithm for McManamon {
                     // Set left tripple
                     left_crits[d] = Some(crit_tripple);
                     
-                    // Set below value
-                    below_right_col_crits[x][d] = Some(crit_tripple.right_col);
+                    // Set below value for the row above
+                    right_col_crits[x][d] = Some(crit_tripple.right_col);
 
                     // Set total crit accumulator
                     crits.push(crit_tripple.total);
-                }
-
-                // Find index of minimum value
-                let min_index = crits
-                    .iter()
-                    .enumerate()
-                    .fold(0, |min_idx, (idx, &val)| {
-                        if val < crits[min_idx] {
-                            idx
-                        }
-                        else {
-                            min_idx
-                        }
-                    });
-
-                // Sub pixel interpolation
-                let disp_val: f32;
 
-                // If on the outer edge of the criterion
-                if min_index == 0 || min_index == crits.len() - 1 || crits.len() < 3 {
-                    disp_val = (min_dyn_disp + min_index) as f32;
+                    if let Some(v) = volume.as_mut() {
+                        v.set(x, y, d, crit_tripple.total);
+                    }
                 }
-                // Otherwise
-                else {
 
-                    // Get left and right values of the criterion
-                    let c_left = crits[min_index - 1];
-                    let c_right = crits[min_index + 1];
-
-                    // If left is higher than right
-                    let denom = match c_left > c_right {
-                        true => 2.0 * (c_left - crits[min_index]),
-                        false => 2.0 * (c_right - crits[min_index])
-                    };
-                   
-                    disp_val = (min_dyn_disp + min_index) as f32 + ((c_left - c_right) / denom);
-                }
+                // Find the minimum with sub pixel interpolation
+                let disp_val = search.start as f32 + subpixel_minimum(&crits);
 
                 // Set disparity value
                 disp_map.put(x, y, disp_val);
@@ -369,6 +469,59 @@ impl DisparityAlgorithm for McManamon {
             // println!("Adjusted disparity range: {}..{}", min_disp, max_disp);
         }
 
+        // ---- BORDER ----
+
+        // Estimate the pixels the correlation pass could not reach, if the border mode allows it.
+        // Otherwise they are left invalid.
+        if self.params.border_mode != BorderMode::Invalid {
+            for y in 0..frame.height() as usize {
+                for x in 0..frame.width() as usize {
+                    if disp_map.is_valid(x, y) {
+                        continue;
+                    }
+
+                    // There is no dynamic range to use at the border, so search the full range
+                    let mut search = match prior {
+                        Some(p) => p.search_range(
+                            x, y, 
+                            self.params.min_disparity..self.params.max_disparity
+                        ),
+                        None => self.params.min_disparity..self.params.max_disparity
+                    };
+
+                    // A shrunk window must keep its centre pixel inside the right image
+                    if self.params.border_mode == BorderMode::ShrinkWindow {
+                        search.end = search.end.min(x + 1);
+                    }
+
+                    let crits: Option<Vec<f32>> = search
+                        .clone()
+                        .map(|d| self.get_criterion_border(frame, x, y, d))
+                        .collect();
+
+                    let crits = match crits {
+                        Some(c) if !c.is_empty() => c,
+                        _ => continue
+                    };
+
+                    if let Some(v) = volume.as_mut() {
+                        for (d, &c) in search.clone().zip(crits.iter()) {
+                            v.set(x, y, d, c);
+                        }
+                    }
+
+                    let disp_val = search.start as f32 + subpixel_minimum(&crits);
+
+                    disp_map.put(x, y, disp_val);
+
+                    min_disp = min_disp.min(disp_val);
+                    max_disp = max_disp.max(disp_val);
+                }
+            }
+        }
+
+        self.drift = drift;
+
         // Set disparity stats in the map
         disp_map.min_disp = Some(min_disp);
         disp_map.max_disp = Some(max_disp);
@@ -435,3 +588,82 @@ impl DisparityAlgorithm for McManamon {
         Ok(disp_map)
     }
 }
+
+impl CriterionDrift {
+    /// Mean absolute difference from the full criterion.
+    pub fn mean_abs(&self) -> f32 {
+        match self.checked {
+            0 => 0.0,
+            n => (self.total_abs / n as f64) as f32
+        }
+    }
+
+    /// Record the difference between a fast criterion and the full criterion of its window.
+    fn record(&mut self, position: (usize, usize, usize), fast: f32, full: f32) {
+        let abs = (fast - full).abs();
+
+        self.checked += 1;
+        self.total_abs += abs as f64;
+
+        if abs > self.max_abs || self.worst.is_none() {
+            self.max_abs = abs;
+            self.worst = Some(position);
+        }
+
+        if full > 0.0 {
+            self.max_rel = self.max_rel.max(abs / full);
+        }
+    }
+}
+
+impl DisparityAlgorithm for McManamon {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        self.compute_impl(frame, None, None)
+    }
+
+    /// Compute the disparity map for the given frame, intersecting the dynamic disparity range
+    /// with the range given by the prior at each pixel.
+    ///
+    /// If the dynamic range and the prior do not overlap the prior is used on its own, so that a
+    /// bad estimate in the row below cannot exclude the range the prior expects.
+    fn compute_with_prior(
+        &mut self, 
+        frame: &StereoFrame, 
+        prior: &DisparityPrior
+    ) -> Result<DisparityMap> {
+        prior.check_size(frame.width() as usize, frame.height() as usize)?;
+
+        self.compute_impl(frame, Some(prior), None)
+    }
+
+    /// McManamon skips a full correlation window at every edge, plus the maximum disparity on the
+    /// left.
+    fn margins(&self) -> Margins {
+        Margins {
+            left: self.params.correlation_window_size.0 + self.params.max_disparity,
+            right: self.params.correlation_window_size.0,
+            top: self.params.correlation_window_size.1,
+            bottom: self.params.correlation_window_size.1
+        }
+    }
+}
+
+impl CostVolumeAlgorithm for McManamon {
+    /// The criteria evaluated while computing the disparity map, over the range
+    /// `min_disparity..max_disparity`.
+    ///
+    /// Only the dynamic disparity range of each row is searched, so criteria outside it are left
+    /// unevaluated, as are pixels the correlation window cannot reach.
+    fn cost_volume(&mut self, frame: &StereoFrame) -> Result<CostVolume> {
+        let mut volume = CostVolume::new(
+            frame.width() as usize,
+            frame.height() as usize,
+            self.params.min_disparity..self.params.max_disparity
+        );
+
+        self.compute_impl(frame, None, Some(&mut volume))?;
+
+        Ok(volume)
+    }
+}
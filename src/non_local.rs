@@ -0,0 +1,398 @@
+//! # Non-local cost aggregation disparity computation
+//!
+//! This module provides the non-local cost aggregation method from
+//! ("A Non-Local Cost Aggregation Method for Stereo Matching")[https://doi.org/10.1109/CVPR.2012.6247827]
+//! by Yang.
+//!
+//! The left image is treated as a 4-connected graph with edges weighted by intensity difference,
+//! and its minimum spanning tree is found. Every pixel then supports every other pixel, weighted
+//! by the similarity `exp(-D / sigma)` where `D` is the distance between them along the tree.
+//! Costs are aggregated over the whole tree with one pass from the leaves to the root and one
+//! back, so the aggregation is linear in the number of pixels and disparities. Pixels in large
+//! weakly textured regions are supported by the whole region rather than a fixed window.
+//!
+//! The optional refinement step computes a right-referenced map on the right image's tree, keeps
+//! only the left pixels which pass a left-right check, and aggregates a new cost volume which
+//! pulls every pixel towards the stable disparities around it along the tree.
+//!
+//! With colour input the tree edges are weighted by the largest difference over the channels,
+//! as in the paper, and the matching cost is averaged over the channels.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::collections::VecDeque;
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use serde::Deserialize;
+
+use crate::colour::{ColourImage, ColourStereoFrame};
+use crate::consistency::left_right_check;
+use crate::cost::{CostFunction, MatchingCost};
+use crate::cost_volume::{CostVolume, CostVolumeAlgorithm};
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct NonLocal {
+    params: Params
+}
+
+/// Non-local aggregation parameters.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Tree distance at which the support similarity falls to 1/e, in the same units as the
+    /// image intensities.
+    pub sigma: f32,
+
+    /// Per-pixel matching cost which is aggregated over the tree.
+    pub cost: CostFunction,
+
+    /// Whether to run the left-right check refinement step.
+    pub refine: bool,
+
+    /// Largest disparity difference allowed by the left-right check.
+    pub lr_threshold: f32
+}
+
+/// Minimum spanning tree over the pixels of an image.
+struct Tree {
+    /// Pixels in breadth first order from the root, so every parent comes before its children.
+    order: Vec<usize>,
+
+    /// Parent of each pixel. The root is its own parent.
+    parent: Vec<usize>,
+
+    /// Similarity between each pixel and its parent.
+    similarity: Vec<f32>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            max_disparity: 64,
+            sigma: 0.1,
+            cost: CostFunction::TruncatedAbsDiff(7.0 / 255.0),
+            refine: true,
+            lr_threshold: 1.0
+        }
+    }
+}
+
+impl Tree {
+    /// Build the minimum spanning tree of a greyscale image.
+    fn new(image: &GrayFloatImage, sigma: f32) -> Self {
+        Self::build(
+            (image.width() as usize, image.height() as usize),
+            sigma,
+            |p, q| (image.get(p.0, p.1) - image.get(q.0, q.1)).abs()
+        )
+    }
+
+    /// Build the minimum spanning tree of a colour image, using the largest channel difference.
+    fn from_colour(image: &ColourImage, sigma: f32) -> Self {
+        Self::build((image.width(), image.height()), sigma, |p, q| {
+            let a = image.get(p.0, p.1);
+            let b = image.get(q.0, q.1);
+
+            a.iter().zip(b.iter()).map(|(u, v)| (u - v).abs()).fold(0.0, f32::max)
+        })
+    }
+
+    /// Build the minimum spanning tree of a 4-connected pixel graph, with edges weighted by the
+    /// distance between neighbouring pixels.
+    fn build<F>((width, height): (usize, usize), sigma: f32, distance: F) -> Self
+    where
+        F: Fn((usize, usize), (usize, usize)) -> f32
+    {
+        let num_pixels = width * height;
+
+        // ---- EDGES ----
+
+        let mut edges: Vec<(f32, usize, usize)> = Vec::with_capacity(2 * num_pixels);
+
+        for y in 0..height {
+            for x in 0..width {
+                let p = y * width + x;
+
+                if x + 1 < width {
+                    edges.push((distance((x, y), (x + 1, y)), p, p + 1));
+                }
+                if y + 1 < height {
+                    edges.push((distance((x, y), (x, y + 1)), p, p + width));
+                }
+            }
+        }
+
+        edges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        // ---- KRUSKAL ----
+
+        let mut sets: Vec<usize> = (0..num_pixels).collect();
+        let mut neighbours: Vec<Vec<(usize, f32)>> = vec![Vec::new(); num_pixels];
+
+        for (w, a, b) in edges {
+            let root_a = find(&mut sets, a);
+            let root_b = find(&mut sets, b);
+
+            if root_a == root_b {
+                continue;
+            }
+
+            sets[root_a] = root_b;
+
+            neighbours[a].push((b, w));
+            neighbours[b].push((a, w));
+        }
+
+        // ---- ORDERING ----
+
+        let mut order = Vec::with_capacity(num_pixels);
+        let mut parent = vec![usize::MAX; num_pixels];
+        let mut similarity = vec![0.0f32; num_pixels];
+
+        let mut queue = VecDeque::new();
+
+        if num_pixels > 0 {
+            parent[0] = 0;
+            queue.push_back(0);
+        }
+
+        while let Some(p) = queue.pop_front() {
+            order.push(p);
+
+            for &(q, w) in &neighbours[p] {
+                if parent[q] != usize::MAX {
+                    continue;
+                }
+
+                parent[q] = p;
+                similarity[q] = (-w / sigma).exp();
+                queue.push_back(q);
+            }
+        }
+
+        Self {
+            order,
+            parent,
+            similarity
+        }
+    }
+
+    /// Aggregate every disparity of the volume over the tree, in place.
+    fn aggregate(&self, volume: &mut CostVolume) {
+        let n = volume.num_disparities();
+        let data = volume.as_mut_slice();
+
+        // Leaves to root, adding each pixel's subtree into its parent
+        for &v in self.order.iter().skip(1).rev() {
+            let (p, s) = (self.parent[v], self.similarity[v]);
+
+            for k in 0..n {
+                data[p * n + k] += s * data[v * n + k];
+            }
+        }
+
+        // Root to leaves, adding the rest of the tree from each pixel's parent
+        for &v in self.order.iter().skip(1) {
+            let (p, s) = (self.parent[v], self.similarity[v]);
+
+            for k in 0..n {
+                data[v * n + k] = s * data[p * n + k] + (1.0 - s * s) * data[v * n + k];
+            }
+        }
+    }
+}
+
+impl NonLocal {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        Self { params }
+    }
+
+    /// Build the raw matching cost volume referenced to either the left or the right image.
+    ///
+    /// Disparities which would place the match outside the other image take the cost of the
+    /// largest disparity which does not, so that they add no evidence of their own.
+    fn matching_volume(
+        &self,
+        cost: &dyn MatchingCost,
+        width: usize,
+        height: usize,
+        right_reference: bool
+    ) -> CostVolume {
+        let mut volume = CostVolume::filled(
+            width, height,
+            self.params.min_disparity..self.params.max_disparity,
+            0.0
+        );
+
+        for y in 0..height {
+            for x in 0..width {
+                let costs = volume.costs_mut(x, y);
+                let mut last = None;
+
+                for (k, c) in costs.iter_mut().enumerate() {
+                    let d = k + self.params.min_disparity;
+
+                    let in_range = match right_reference {
+                        true => x + d < width,
+                        false => d <= x
+                    };
+
+                    if in_range {
+                        let val = match right_reference {
+                            true => cost.cost(x + d, y, d),
+                            false => cost.cost(x, y, d)
+                        };
+
+                        *c = val;
+                        last = Some(val);
+                    }
+                    else if let Some(val) = last {
+                        *c = val;
+                    }
+                }
+            }
+        }
+
+        volume
+    }
+
+    /// Compute the aggregated left-referenced cost volume, and the refined volume if enabled.
+    ///
+    /// If the colour frame is given, the greyscale frame must be its greyscale frame.
+    fn aggregated_volume(
+        &self,
+        frame: &StereoFrame,
+        colour: Option<&ColourStereoFrame>
+    ) -> Result<CostVolume> {
+        if self.params.max_disparity <= self.params.min_disparity {
+            return Err(Error::InvalidParams(
+                "max_disparity must be greater than min_disparity".into()
+            ));
+        }
+
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let cost = match colour {
+            Some(c) => self.params.cost.build_colour(c)?,
+            None => self.params.cost.build(frame)?
+        };
+
+        let tree = |grey: &GrayFloatImage, colour: Option<&ColourImage>| match colour {
+            Some(c) => Tree::from_colour(c, self.params.sigma),
+            None => Tree::new(grey, self.params.sigma)
+        };
+
+        // ---- AGGREGATION ----
+
+        let left_tree = tree(&frame.left, colour.map(|c| &c.left));
+
+        let mut volume = self.matching_volume(&*cost, width, height, false);
+        left_tree.aggregate(&mut volume);
+
+        if !self.params.refine {
+            return Ok(volume);
+        }
+
+        // ---- REFINEMENT ----
+
+        let right_tree = tree(&frame.right, colour.map(|c| &c.right));
+
+        let mut right_volume = self.matching_volume(&*cost, width, height, true);
+        right_tree.aggregate(&mut right_volume);
+
+        let mut stable = volume.wta();
+        left_right_check(&mut stable, &right_volume.wta(), self.params.lr_threshold);
+
+        // Stable pixels cost the distance from their disparity, unstable pixels are free so take
+        // their disparity from the stable pixels supporting them
+        let mut refined = CostVolume::filled(width, height, volume.disparities(), 0.0);
+
+        for y in 0..height {
+            for x in 0..width {
+                if !stable.is_valid(x, y) {
+                    continue;
+                }
+
+                let disp = stable.get(x, y);
+
+                for (d, c) in volume.disparities().zip(refined.costs_mut(x, y).iter_mut()) {
+                    *c = (d as f32 - disp).abs();
+                }
+            }
+        }
+
+        left_tree.aggregate(&mut refined);
+
+        Ok(refined)
+    }
+}
+
+impl DisparityAlgorithm for NonLocal {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        Ok(self.aggregated_volume(frame, None)?.wta())
+    }
+
+    /// Compute the disparity map for the given colour frame, using colour in both the trees and
+    /// the matching cost.
+    fn compute_colour(&mut self, frame: &ColourStereoFrame) -> Result<DisparityMap> {
+        Ok(self.aggregated_volume(&frame.grey, Some(frame))?.wta())
+    }
+
+    /// Aggregation is over the whole image, so no finite margin makes tiled results match whole
+    /// frame results. The margin only covers the disparity range.
+    fn margins(&self) -> Margins {
+        Margins {
+            left: self.params.max_disparity,
+            right: 0,
+            top: 0,
+            bottom: 0
+        }
+    }
+}
+
+impl CostVolumeAlgorithm for NonLocal {
+    /// The aggregated cost volume, or the refined volume if refinement is enabled.
+    fn cost_volume(&mut self, frame: &StereoFrame) -> Result<CostVolume> {
+        self.aggregated_volume(frame, None)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Find the representative of a pixel's set, compressing the path to it.
+fn find(sets: &mut [usize], p: usize) -> usize {
+    let mut root = p;
+    while sets[root] != root {
+        root = sets[root];
+    }
+
+    let mut q = p;
+    while sets[q] != root {
+        let next = sets[q];
+        sets[q] = root;
+        q = next;
+    }
+
+    root
+}
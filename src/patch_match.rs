@@ -0,0 +1,553 @@
+//! # PatchMatch Stereo disparity computation
+//!
+//! This module provides an implementation of the algorithm from
+//! ("PatchMatch Stereo - Stereo Matching with Slanted Support Windows")[https://doi.org/10.5244/C.25.14]
+//! by Bleyer et al.
+//!
+//! Rather than assuming every pixel in a window shares the centre pixel's disparity, each pixel
+//! is given its own slanted plane in disparity space and the window is matched along that plane.
+//! This removes the fronto-parallel bias of fixed windows on sloped terrain and gives sub pixel
+//! disparities directly from the planes. Planes start out random and are improved over a number
+//! of iterations by:
+//!
+//! - spatial propagation, trying the planes of the previously visited neighbours,
+//! - view propagation, trying the plane of the matching pixel in the other view, and
+//! - plane refinement, trying random perturbations of decreasing size.
+//!
+//! View propagation only tries the pixel the current plane matches in the other view, rather
+//! than every pixel which maps onto the current one.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::cost::gradient_x;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+pub struct PatchMatch {
+    params: Params
+}
+
+/// PatchMatch Stereo parameters.
+///
+/// Intensity thresholds are in the same units as the image intensities. The defaults are the
+/// values from the paper rescaled to intensities in [0, 1].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    pub min_disparity: usize,
+    pub max_disparity: usize,
+
+    /// Side length of the square support window, which should be odd.
+    pub window_size: usize,
+
+    /// Intensity difference at which a window pixel's weight falls to 1/e.
+    pub gamma: f32,
+
+    /// Balance between the intensity (0) and gradient (1) terms of the matching cost.
+    pub alpha: f32,
+
+    /// Truncation of the intensity term of the matching cost.
+    pub tau_colour: f32,
+
+    /// Truncation of the gradient term of the matching cost.
+    pub tau_gradient: f32,
+
+    /// Number of propagation iterations.
+    pub iterations: usize,
+
+    /// Seed for the random number generator. If `None` a new seed is drawn from the operating
+    /// system for every frame, so results are not reproducible.
+    pub seed: Option<u64>,
+
+    /// Maximum difference between the left and right disparities of a pixel before it is marked
+    /// invalid. If `None` no consistency check is made.
+    pub lr_check_threshold: Option<f32>,
+
+    /// Fill pixels which fail the consistency check with the lower disparity of the planes of
+    /// their nearest valid neighbours to the left and right.
+    pub fill_invalid: bool
+}
+
+/// A plane in disparity space, `d = a * x + b * y + c`.
+#[derive(Copy, Clone, Debug)]
+struct Plane {
+    a: f32,
+    b: f32,
+    c: f32
+}
+
+/// One of the two views of the frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum View {
+    Left,
+    Right
+}
+
+/// Images and gradients of the frame being computed.
+struct Images<'a> {
+    width: usize,
+    height: usize,
+    left: &'a GrayFloatImage,
+    right: &'a GrayFloatImage,
+    left_grad: Vec<f32>,
+    right_grad: Vec<f32>
+}
+
+/// Current plane and matching cost of every pixel in both views.
+struct State {
+    planes: [Vec<Plane>; 2],
+    costs: [Vec<f32>; 2]
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            min_disparity: 0,
+            max_disparity: 64,
+            window_size: 35,
+            gamma: 10.0 / 255.0,
+            alpha: 0.9,
+            tau_colour: 10.0 / 255.0,
+            tau_gradient: 2.0 / 255.0,
+            iterations: 3,
+            seed: None,
+            lr_check_threshold: Some(1.0),
+            fill_invalid: true
+        }
+    }
+}
+
+impl Plane {
+    /// Build the plane passing through disparity `d` at `(x, y)` with the given normal.
+    fn from_point_normal(x: f32, y: f32, d: f32, n: [f32; 3]) -> Self {
+        Self {
+            a: -n[0] / n[2],
+            b: -n[1] / n[2],
+            c: (n[0] * x + n[1] * y + n[2] * d) / n[2]
+        }
+    }
+
+    /// Unit normal of the plane, pointing towards increasing disparity.
+    fn normal(&self) -> [f32; 3] {
+        let len = (self.a * self.a + self.b * self.b + 1.0).sqrt();
+
+        [-self.a / len, -self.b / len, 1.0 / len]
+    }
+
+    fn disparity(&self, x: f32, y: f32) -> f32 {
+        self.a * x + self.b * y + self.c
+    }
+
+    /// Express the plane in the coordinates of the other view, where the plane's own view
+    /// matches `x` to `x + sign * d` in the other.
+    ///
+    /// Returns `None` for planes which are almost parallel to the other view's lines of sight.
+    fn in_other_view(&self, sign: f32) -> Option<Self> {
+        let denom = 1.0 + self.a * sign;
+
+        if denom.abs() < 1e-3 {
+            return None;
+        }
+
+        Some(Self {
+            a: self.a / denom,
+            b: self.b / denom,
+            c: self.c / denom
+        })
+    }
+}
+
+impl View {
+    fn index(self) -> usize {
+        match self {
+            View::Left => 0,
+            View::Right => 1
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            View::Left => View::Right,
+            View::Right => View::Left
+        }
+    }
+
+    /// Direction of the match in the other view, which is at `x + sign * d`.
+    fn sign(self) -> f32 {
+        match self {
+            View::Left => -1.0,
+            View::Right => 1.0
+        }
+    }
+}
+
+impl<'a> Images<'a> {
+    fn new(frame: &'a StereoFrame) -> Self {
+        Self {
+            width: frame.width() as usize,
+            height: frame.height() as usize,
+            left: &frame.left,
+            right: &frame.right,
+            left_grad: gradient_x(&frame.left),
+            right_grad: gradient_x(&frame.right)
+        }
+    }
+
+    /// Get the (own, other) images and gradients of the given view.
+    fn view(&self, view: View) -> (&GrayFloatImage, &[f32], &GrayFloatImage, &[f32]) {
+        match view {
+            View::Left => (self.left, &self.left_grad, self.right, &self.right_grad),
+            View::Right => (self.right, &self.right_grad, self.left, &self.left_grad)
+        }
+    }
+}
+
+impl PatchMatch {
+    /// Create a new instance of the algorithm with the given parameters.
+    pub fn new(params: Params) -> Self {
+        Self { params }
+    }
+
+    /// Aggregated matching cost of the window around `(x, y)` in the given view, matched along
+    /// the given plane.
+    fn plane_cost(&self, images: &Images, view: View, x: usize, y: usize, plane: &Plane) -> f32 {
+        let (own, own_grad, other, other_grad) = images.view(view);
+        let radius = (self.params.window_size / 2) as isize;
+        let sign = view.sign();
+
+        let max_cost = (1.0 - self.params.alpha) * self.params.tau_colour
+            + self.params.alpha * self.params.tau_gradient;
+        let min_disp = self.params.min_disparity as f32;
+        let max_disp = self.params.max_disparity as f32;
+
+        let centre = own.get(x, y);
+        let mut total = 0.0f32;
+
+        for j in -radius..radius + 1 {
+            let qy = y as isize + j;
+            if qy < 0 || qy >= images.height as isize {
+                continue;
+            }
+            let qy = qy as usize;
+
+            for i in -radius..radius + 1 {
+                let qx = x as isize + i;
+                if qx < 0 || qx >= images.width as isize {
+                    continue;
+                }
+                let qx = qx as usize;
+
+                let own_val = own.get(qx, qy);
+                let weight = (-(centre - own_val).abs() / self.params.gamma).exp();
+
+                let d = plane.disparity(qx as f32, qy as f32);
+                let xo = qx as f32 + sign * d;
+
+                // Matches outside the disparity range or the other image get the maximum cost
+                if d < min_disp || d > max_disp || xo < 0.0 || xo > (images.width - 1) as f32 {
+                    total += weight * max_cost;
+                    continue;
+                }
+
+                // Linearly interpolate the other image at the sub pixel match
+                let x0 = xo.floor() as usize;
+                let x1 = (x0 + 1).min(images.width - 1);
+                let t = xo - x0 as f32;
+
+                let other_val = (1.0 - t) * other.get(x0, qy) + t * other.get(x1, qy);
+                let other_g = (1.0 - t) * other_grad[qy * images.width + x0]
+                    + t * other_grad[qy * images.width + x1];
+
+                let colour = (own_val - other_val).abs().min(self.params.tau_colour);
+                let gradient = (own_grad[qy * images.width + qx] - other_g)
+                    .abs()
+                    .min(self.params.tau_gradient);
+
+                total += weight
+                    * ((1.0 - self.params.alpha) * colour + self.params.alpha * gradient);
+            }
+        }
+
+        total
+    }
+
+    /// Draw a random plane through a random disparity at `(x, y)`.
+    fn random_plane<R: Rng>(&self, rng: &mut R, x: usize, y: usize) -> Plane {
+        let d = rng.gen_range(
+            self.params.min_disparity as f32,
+            self.params.max_disparity as f32
+        );
+
+        let normal = normalise([
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(0.1, 1.0)
+        ]);
+
+        Plane::from_point_normal(x as f32, y as f32, d, normal)
+    }
+
+    /// Replace the plane at `(x, y)` with the candidate if it has a lower cost.
+    fn try_plane(
+        &self,
+        images: &Images,
+        state: &mut State,
+        view: View,
+        x: usize,
+        y: usize,
+        candidate: Plane
+    ) {
+        let idx = y * images.width + x;
+        let cost = self.plane_cost(images, view, x, y, &candidate);
+
+        if cost < state.costs[view.index()][idx] {
+            state.planes[view.index()][idx] = candidate;
+            state.costs[view.index()][idx] = cost;
+        }
+    }
+
+    /// Run spatial propagation, view propagation and plane refinement at one pixel.
+    fn improve<R: Rng>(
+        &self,
+        images: &Images,
+        state: &mut State,
+        rng: &mut R,
+        view: View,
+        (x, y): (usize, usize),
+        forward: bool
+    ) {
+        let width = images.width;
+        let v = view.index();
+
+        // ---- SPATIAL PROPAGATION ----
+
+        let neighbours = match forward {
+            true => [
+                if x > 0 { Some((x - 1, y)) } else { None },
+                if y > 0 { Some((x, y - 1)) } else { None }
+            ],
+            false => [
+                if x + 1 < width { Some((x + 1, y)) } else { None },
+                if y + 1 < images.height { Some((x, y + 1)) } else { None }
+            ]
+        };
+
+        for &(nx, ny) in neighbours.iter().flatten() {
+            let candidate = state.planes[v][ny * width + nx];
+            self.try_plane(images, state, view, x, y, candidate);
+        }
+
+        // ---- VIEW PROPAGATION ----
+
+        let d = state.planes[v][y * width + x].disparity(x as f32, y as f32);
+        let xo = (x as f32 + view.sign() * d).round();
+
+        if xo >= 0.0 && xo < width as f32 {
+            let other_plane = state.planes[view.other().index()][y * width + xo as usize];
+
+            if let Some(candidate) = other_plane.in_other_view(view.other().sign()) {
+                self.try_plane(images, state, view, x, y, candidate);
+            }
+        }
+
+        // ---- PLANE REFINEMENT ----
+
+        let mut dz_max = (self.params.max_disparity - self.params.min_disparity) as f32 / 2.0;
+        let mut dn_max = 1.0f32;
+
+        while dz_max >= 0.1 {
+            let current = state.planes[v][y * width + x];
+            let z = current.disparity(x as f32, y as f32) + rng.gen_range(-dz_max, dz_max);
+            let n = current.normal();
+
+            let normal = normalise([
+                n[0] + rng.gen_range(-dn_max, dn_max),
+                n[1] + rng.gen_range(-dn_max, dn_max),
+                n[2] + rng.gen_range(-dn_max, dn_max)
+            ]);
+
+            let in_range = z >= self.params.min_disparity as f32
+                && z <= self.params.max_disparity as f32;
+
+            // Planes close to the lines of sight give meaningless disparities
+            if in_range && normal[2] > 0.1 {
+                let candidate = Plane::from_point_normal(x as f32, y as f32, z, normal);
+                self.try_plane(images, state, view, x, y, candidate);
+            }
+
+            dz_max /= 2.0;
+            dn_max /= 2.0;
+        }
+    }
+
+    /// Disparity of the given pixel in the given view, clamped to the disparity range.
+    fn disparity(&self, state: &State, view: View, x: usize, y: usize, width: usize) -> f32 {
+        state.planes[view.index()][y * width + x]
+            .disparity(x as f32, y as f32)
+            .max(self.params.min_disparity as f32)
+            .min(self.params.max_disparity as f32)
+    }
+}
+
+impl DisparityAlgorithm for PatchMatch {
+    /// Compute the disparity map for the given frame.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        if self.params.max_disparity <= self.params.min_disparity {
+            return Err(Error::InvalidParams(
+                "max_disparity must be greater than min_disparity".into()
+            ));
+        }
+
+        let images = Images::new(frame);
+        let width = images.width;
+        let height = images.height;
+
+        let mut rng = match self.params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy()
+        };
+
+        // ---- RANDOM INITIALISATION ----
+
+        let mut state = State {
+            planes: [Vec::with_capacity(width * height), Vec::with_capacity(width * height)],
+            costs: [Vec::with_capacity(width * height), Vec::with_capacity(width * height)]
+        };
+
+        for &view in &[View::Left, View::Right] {
+            for y in 0..height {
+                for x in 0..width {
+                    let plane = self.random_plane(&mut rng, x, y);
+                    let cost = self.plane_cost(&images, view, x, y, &plane);
+
+                    state.planes[view.index()].push(plane);
+                    state.costs[view.index()].push(cost);
+                }
+            }
+        }
+
+        // ---- ITERATIONS ----
+
+        // Even iterations run from the top left, odd ones from the bottom right
+        for iter in 0..self.params.iterations {
+            let forward = iter % 2 == 0;
+
+            for &view in &[View::Left, View::Right] {
+                for i in 0..width * height {
+                    let idx = if forward { i } else { width * height - 1 - i };
+
+                    self.improve(
+                        &images, &mut state, &mut rng,
+                        view, (idx % width, idx / width),
+                        forward
+                    );
+                }
+            }
+        }
+
+        // ---- POST PROCESSING ----
+
+        let mut disp_map = DisparityMap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let d = self.disparity(&state, View::Left, x, y, width);
+
+                let consistent = match self.params.lr_check_threshold {
+                    Some(threshold) => {
+                        let xr = (x as f32 - d).round();
+
+                        xr >= 0.0
+                            && (d - self.disparity(&state, View::Right, xr as usize, y, width))
+                                .abs() <= threshold
+                    },
+                    None => true
+                };
+
+                if consistent {
+                    disp_map.put(x, y, d);
+                }
+            }
+        }
+
+        // Fill inconsistent pixels from the planes of their nearest valid neighbours, preferring
+        // the lower disparity since occluded pixels belong to the background
+        if self.params.fill_invalid {
+            let mut filled: Vec<(usize, usize, f32)> = Vec::new();
+
+            for y in 0..height {
+                for x in 0..width {
+                    if disp_map.is_valid(x, y) {
+                        continue;
+                    }
+
+                    let left = (0..x).rev().find(|&xl| disp_map.is_valid(xl, y));
+                    let right = (x + 1..width).find(|&xr| disp_map.is_valid(xr, y));
+
+                    let eval = |xn: usize| {
+                        state.planes[View::Left.index()][y * width + xn]
+                            .disparity(x as f32, y as f32)
+                            .max(self.params.min_disparity as f32)
+                            .min(self.params.max_disparity as f32)
+                    };
+
+                    let fill = match (left.map(&eval), right.map(&eval)) {
+                        (Some(l), Some(r)) => Some(l.min(r)),
+                        (l, r) => l.or(r)
+                    };
+
+                    if let Some(d) = fill {
+                        filled.push((x, y, d));
+                    }
+                }
+            }
+
+            for (x, y, d) in filled {
+                disp_map.put(x, y, d);
+            }
+        }
+
+        disp_map.update_range();
+
+        Ok(disp_map)
+    }
+
+    /// Margins covering the support window. Propagation carries planes across the whole frame,
+    /// so tiled results will differ from whole frame results.
+    fn margins(&self) -> Margins {
+        let radius = self.params.window_size / 2;
+
+        Margins {
+            left: radius + self.params.max_disparity,
+            right: radius,
+            top: radius,
+            bottom: radius
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Normalise a vector to unit length.
+fn normalise(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt().max(1e-6);
+
+    [v[0] / len, v[1] / len, v[2] / len]
+}
@@ -0,0 +1,350 @@
+//! # Filter pipelines
+//!
+//! This module wraps a disparity algorithm with chains of filters, so that a whole processing
+//! chain can be driven through the [`DisparityAlgorithm`] trait:
+//!
+//! 1. Pre-filters, implementing [`ImageFilter`], are applied in order to both images of the
+//!    frame before matching, for example to smooth out noise or remove a brightness mismatch
+//!    between the cameras.
+//! 2. The algorithm computes the left-referenced map, optionally followed by a left-right
+//!    consistency check, see [`consistency::compute_checked`]. The check is applied whichever
+//!    way the map is computed, with a prior or from a colour frame.
+//! 3. Post-filters, implementing [`DisparityFilter`], are applied in order to the map, guided by
+//!    the unfiltered left image.
+//!
+//! Pipelines are usually built from a config file, see [`crate::config`].
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use serde::Deserialize;
+
+use crate::colour::{ColourImage, ColourStereoFrame};
+use crate::consistency;
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::prior::DisparityPrior;
+use crate::refine::DisparityFilter;
+use crate::tiling::Margins;
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// A filter applied to the images of a frame before matching.
+pub trait ImageFilter {
+    /// Filter the image, returning an image of the same size.
+    fn filter(&self, image: &GrayFloatImage) -> Result<GrayFloatImage>;
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A disparity algorithm with pre- and post-filter chains.
+pub struct Pipeline {
+    algorithm: Box<dyn DisparityAlgorithm>,
+    pre: Vec<Box<dyn ImageFilter>>,
+    post: Vec<Box<dyn DisparityFilter>>,
+    lr_threshold: Option<f32>
+}
+
+/// Separable Gaussian blur, with the border extended by clamping.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Gaussian {
+    /// Standard deviation of the kernel in pixels.
+    pub sigma: f32
+}
+
+/// Linear normalisation of each image to a fixed mean and standard deviation, which removes a
+/// gain and bias mismatch between the cameras. The result is clamped to [0, 1].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Normalise {
+    pub mean: f32,
+    pub std_dev: f32
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Pipeline {
+    /// Create a pipeline running only the given algorithm.
+    pub fn new(algorithm: Box<dyn DisparityAlgorithm>) -> Self {
+        Self {
+            algorithm,
+            pre: Vec::new(),
+            post: Vec::new(),
+            lr_threshold: None
+        }
+    }
+
+    /// Add a filter to the end of the pre-filter chain.
+    pub fn pre_filter(mut self, filter: Box<dyn ImageFilter>) -> Self {
+        self.pre.push(filter);
+        self
+    }
+
+    /// Add a filter to the end of the post-filter chain.
+    pub fn post_filter(mut self, filter: Box<dyn DisparityFilter>) -> Self {
+        self.post.push(filter);
+        self
+    }
+
+    /// Remove pixels which fail a left-right consistency check with the given threshold, before
+    /// post-filtering.
+    pub fn left_right_check(mut self, threshold: f32) -> Self {
+        self.lr_threshold = Some(threshold);
+        self
+    }
+
+    /// Get a reference to the wrapped algorithm.
+    pub fn algorithm(&self) -> &dyn DisparityAlgorithm {
+        &*self.algorithm
+    }
+
+    /// Apply the pre-filters to both images of a frame, or `None` if there are none.
+    fn pre_filter_frame(&self, frame: &StereoFrame) -> Result<Option<StereoFrame>> {
+        if self.pre.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(StereoFrame {
+            left: self.pre_filter_image(&frame.left)?,
+            left_timestamp: frame.left_timestamp,
+            right: self.pre_filter_image(&frame.right)?,
+            right_timestamp: frame.right_timestamp
+        }))
+    }
+
+    fn pre_filter_image(&self, image: &GrayFloatImage) -> Result<GrayFloatImage> {
+        let mut filtered = self.pre[0].filter(image)?;
+
+        for filter in &self.pre[1..] {
+            filtered = filter.filter(&filtered)?;
+        }
+
+        Ok(filtered)
+    }
+
+    fn pre_filter_colour(&self, image: &ColourImage) -> Result<ColourImage> {
+        let channels = [
+            self.pre_filter_image(image.channel(0))?,
+            self.pre_filter_image(image.channel(1))?,
+            self.pre_filter_image(image.channel(2))?
+        ];
+
+        let mut filtered = ColourImage::new(image.width(), image.height());
+
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                filtered.put(x, y, [
+                    channels[0].get(x, y),
+                    channels[1].get(x, y),
+                    channels[2].get(x, y)
+                ]);
+            }
+        }
+
+        Ok(filtered)
+    }
+
+    /// Apply the post-filters to a map, guided by the unfiltered image it is referenced to.
+    fn post_filter_map(
+        &self,
+        mut map: DisparityMap,
+        guide: &GrayFloatImage
+    ) -> Result<DisparityMap> {
+        for filter in &self.post {
+            map = filter.filter(&map, guide)?;
+        }
+
+        map.update_range();
+
+        Ok(map)
+    }
+}
+
+impl DisparityAlgorithm for Pipeline {
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        let filtered = self.pre_filter_frame(frame)?;
+        let input = filtered.as_ref().unwrap_or(frame);
+
+        let map = match self.lr_threshold {
+            Some(t) => consistency::compute_checked(&mut *self.algorithm, input, t)?,
+            None => self.algorithm.compute(input)?
+        };
+
+        self.post_filter_map(map, &frame.left)
+    }
+
+    /// Compute with the prior.
+    ///
+    /// The prior only applies to the left-referenced map, so the right-referenced map of the
+    /// left-right check is computed without it.
+    fn compute_with_prior(
+        &mut self,
+        frame: &StereoFrame,
+        prior: &DisparityPrior
+    ) -> Result<DisparityMap> {
+        let filtered = self.pre_filter_frame(frame)?;
+        let input = filtered.as_ref().unwrap_or(frame);
+
+        let mut map = self.algorithm.compute_with_prior(input, prior)?;
+
+        if let Some(t) = self.lr_threshold {
+            let right = self.algorithm.compute_right(input)?;
+            consistency::left_right_check(&mut map, &right, t);
+        }
+
+        self.post_filter_map(map, &frame.left)
+    }
+
+    /// Compute the right-referenced map, pre- and post-filtered like the left map, with the
+    /// post-filters guided by the unfiltered right image.
+    ///
+    /// The left-right check is not applied, as it belongs to the left-referenced map. Without this
+    /// the default would run the whole pipeline on the mirrored frame, nesting a second check
+    /// inside the one in [`Pipeline::compute`].
+    fn compute_right(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        let filtered = self.pre_filter_frame(frame)?;
+        let map = self.algorithm.compute_right(filtered.as_ref().unwrap_or(frame))?;
+
+        self.post_filter_map(map, &frame.right)
+    }
+
+    /// Compute with colour, pre-filtering each colour channel as well as the greyscale images.
+    ///
+    /// The right-referenced map of the left-right check is computed from the greyscale frame, as
+    /// algorithms only support right-referenced computation in greyscale.
+    fn compute_colour(&mut self, frame: &ColourStereoFrame) -> Result<DisparityMap> {
+        let filtered = match self.pre_filter_frame(&frame.grey)? {
+            Some(grey) => Some(ColourStereoFrame::new(
+                grey,
+                self.pre_filter_colour(&frame.left)?,
+                self.pre_filter_colour(&frame.right)?
+            )?),
+            None => None
+        };
+        let input = filtered.as_ref().unwrap_or(frame);
+
+        let mut map = self.algorithm.compute_colour(input)?;
+
+        if let Some(t) = self.lr_threshold {
+            let right = self.algorithm.compute_right(&input.grey)?;
+            consistency::left_right_check(&mut map, &right, t);
+        }
+
+        self.post_filter_map(map, &frame.grey.left)
+    }
+
+    /// The margins of the algorithm, which do not include the support of the filters.
+    fn margins(&self) -> Margins {
+        self.algorithm.margins()
+    }
+}
+
+impl Default for Gaussian {
+    fn default() -> Self {
+        Self {
+            sigma: 1.0
+        }
+    }
+}
+
+impl ImageFilter for Gaussian {
+    fn filter(&self, image: &GrayFloatImage) -> Result<GrayFloatImage> {
+        if self.sigma <= 0.0 {
+            return Err(Error::InvalidParams("Gaussian sigma must be positive".into()));
+        }
+
+        let radius = (3.0 * self.sigma).ceil() as isize;
+        let mut kernel: Vec<f32> = (-radius..=radius)
+            .map(|i| (-(i * i) as f32 / (2.0 * self.sigma * self.sigma)).exp())
+            .collect();
+        let sum: f32 = kernel.iter().sum();
+        kernel.iter_mut().for_each(|k| *k /= sum);
+
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+
+        let mut rows = GrayFloatImage::new(width, height);
+        let mut output = GrayFloatImage::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let val = kernel.iter().enumerate().map(|(i, k)| {
+                    let xi = (x as isize + i as isize - radius).max(0).min(width as isize - 1);
+                    k * image.get(xi as usize, y)
+                }).sum();
+
+                rows.put(x, y, val);
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let val = kernel.iter().enumerate().map(|(i, k)| {
+                    let yi = (y as isize + i as isize - radius).max(0).min(height as isize - 1);
+                    k * rows.get(x, yi as usize)
+                }).sum();
+
+                output.put(x, y, val);
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl Default for Normalise {
+    fn default() -> Self {
+        Self {
+            mean: 0.5,
+            std_dev: 0.2
+        }
+    }
+}
+
+impl ImageFilter for Normalise {
+    fn filter(&self, image: &GrayFloatImage) -> Result<GrayFloatImage> {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        let count = (width * height).max(1) as f64;
+
+        let mut sum = 0.0f64;
+        let mut sum_sq = 0.0f64;
+
+        for y in 0..height {
+            for x in 0..width {
+                let v = image.get(x, y) as f64;
+                sum += v;
+                sum_sq += v * v;
+            }
+        }
+
+        let mean = sum / count;
+        let std_dev = (sum_sq / count - mean * mean).max(0.0).sqrt();
+
+        // A flat image has nothing to stretch, so is only shifted to the target mean
+        let scale = match std_dev > 1e-6 {
+            true => self.std_dev as f64 / std_dev,
+            false => 0.0
+        };
+
+        let mut output = GrayFloatImage::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let v = (image.get(x, y) as f64 - mean) * scale + self.mean as f64;
+                output.put(x, y, (v as f32).max(0.0).min(1.0));
+            }
+        }
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,193 @@
+//! # Disparity search range priors
+//!
+//! This module provides a per-pixel (or per-tile) prior on the disparity search range. A prior
+//! can be built from the disparity map of a previous frame, from a pair of min/max images (for
+//! example rendered from a terrain model), or filled in by hand, and is then used by algorithms
+//! to restrict the disparities they search at each pixel.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::ops::Range;
+
+use cv_camstream::GrayFloatImage;
+
+use crate::disparity::DisparityMap;
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A prior on the minimum and maximum disparity of each pixel in an image.
+///
+/// The prior is stored on a grid of square tiles of `tile_size` pixels, with a tile size of 1
+/// giving a true per-pixel prior. Bounds are inclusive, and an upper bound of `f32::INFINITY`
+/// leaves the search range limited only by the algorithm's own parameters.
+#[derive(Clone, Debug)]
+pub struct DisparityPrior {
+    width: usize,
+    height: usize,
+    tile_size: usize,
+    grid_width: usize,
+    grid_height: usize,
+    min: Vec<f32>,
+    max: Vec<f32>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl DisparityPrior {
+    /// Create a new prior for an image of the given size, with every tile set to the same range.
+    ///
+    /// A `tile_size` of zero is treated as one.
+    pub fn new(width: usize, height: usize, tile_size: usize, min: f32, max: f32) -> Self {
+        let tile_size = tile_size.max(1);
+        let grid_width = (width + tile_size - 1) / tile_size;
+        let grid_height = (height + tile_size - 1) / tile_size;
+
+        Self {
+            width,
+            height,
+            tile_size,
+            grid_width,
+            grid_height,
+            min: vec![min; grid_width * grid_height],
+            max: vec![max; grid_width * grid_height]
+        }
+    }
+
+    /// Create a new prior which places no restriction on the search range.
+    pub fn unbounded(width: usize, height: usize, tile_size: usize) -> Self {
+        Self::new(width, height, tile_size, 0.0, f32::INFINITY)
+    }
+
+    /// Build a per-pixel prior from a pair of minimum and maximum disparity images.
+    pub fn from_images(min: &GrayFloatImage, max: &GrayFloatImage) -> Result<Self> {
+        let width = min.width() as usize;
+        let height = min.height() as usize;
+
+        if max.width() as usize != width || max.height() as usize != height {
+            return Err(Error::InvalidParams(format!(
+                "prior min image is {:?} pixels but the max image is {:?} pixels",
+                (width, height),
+                (max.width() as usize, max.height() as usize)
+            )));
+        }
+
+        let mut prior = Self::unbounded(width, height, 1);
+
+        for y in 0..height {
+            for x in 0..width {
+                prior.set_tile(x, y, min.get(x, y), max.get(x, y));
+            }
+        }
+
+        Ok(prior)
+    }
+
+    /// Build a prior from the disparity map of a previous frame.
+    ///
+    /// Each tile is given the range of valid disparities observed within it, widened by `margin`
+    /// on both sides. Tiles with no valid disparities are left unbounded.
+    pub fn from_map(map: &DisparityMap, tile_size: usize, margin: f32) -> Self {
+        let mut prior = Self::unbounded(map.width(), map.height(), tile_size);
+
+        for ty in 0..prior.grid_height {
+            for tx in 0..prior.grid_width {
+                let mut min = f32::INFINITY;
+                let mut max = f32::NEG_INFINITY;
+
+                for y in prior.tile_rows(ty) {
+                    for x in prior.tile_cols(tx) {
+                        if !map.is_valid(x, y) {
+                            continue;
+                        }
+
+                        let val = map.get(x, y);
+                        min = min.min(val);
+                        max = max.max(val);
+                    }
+                }
+
+                if min <= max {
+                    prior.set_tile(tx, ty, (min - margin).max(0.0), max + margin);
+                }
+            }
+        }
+
+        prior
+    }
+
+    /// Width of the image the prior covers.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the image the prior covers.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Size of each tile in pixels.
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+
+    /// Number of tiles in the (x, y) directions.
+    pub fn grid_size(&self) -> (usize, usize) {
+        (self.grid_width, self.grid_height)
+    }
+
+    /// Set the range of the tile at the given tile coordinates.
+    pub fn set_tile(&mut self, tx: usize, ty: usize, min: f32, max: f32) {
+        let idx = ty * self.grid_width + tx;
+        self.min[idx] = min;
+        self.max[idx] = max;
+    }
+
+    /// Get the (min, max) range of the tile containing the given pixel.
+    pub fn get(&self, x: usize, y: usize) -> (f32, f32) {
+        let idx = (y / self.tile_size) * self.grid_width + x / self.tile_size;
+        (self.min[idx], self.max[idx])
+    }
+
+    /// Get the integer disparity search range for the given pixel, clamped to `limits`.
+    ///
+    /// The returned range is half-open, matching the `[min, max)` convention used by the
+    /// algorithms, and may be empty if the prior does not overlap the limits.
+    pub fn search_range(&self, x: usize, y: usize, limits: Range<usize>) -> Range<usize> {
+        let (min, max) = self.get(x, y);
+
+        // Float to int casts saturate, so an infinite maximum becomes usize::MAX
+        let start = (min.floor().max(0.0) as usize).max(limits.start);
+        let end = (max.ceil().max(0.0) as usize).saturating_add(1).min(limits.end);
+
+        start..end.max(start)
+    }
+
+    /// Check that the prior covers an image of the given size.
+    pub fn check_size(&self, width: usize, height: usize) -> Result<()> {
+        if self.width != width || self.height != height {
+            return Err(Error::PriorSizeMismatch {
+                prior: (self.width, self.height),
+                frame: (width, height)
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Pixel rows covered by the given tile row.
+    fn tile_rows(&self, ty: usize) -> Range<usize> {
+        (ty * self.tile_size)..((ty + 1) * self.tile_size).min(self.height)
+    }
+
+    /// Pixel columns covered by the given tile column.
+    fn tile_cols(&self, tx: usize) -> Range<usize> {
+        (tx * self.tile_size)..((tx + 1) * self.tile_size).min(self.width)
+    }
+}
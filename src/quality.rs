@@ -0,0 +1,254 @@
+//! # Unsupervised quality metrics
+//!
+//! This module scores a disparity map using only the stereo frame it was computed from, so that
+//! parameter sets can be compared on real captures where no ground truth is available:
+//!
+//! - Photometric error, the mean absolute difference between the left image and the right image
+//!   warped into the left view by the map.
+//! - Left-right consistency rate, the fraction of valid pixels which agree with a right-referenced
+//!   map, when one is given.
+//! - Density, the fraction of pixels with a valid disparity.
+//! - Smoothness, the mean absolute disparity difference between neighbouring valid pixels.
+//! - Edge alignment, the fraction of disparity edges which lie on or next to an intensity edge,
+//!   since depth discontinuities in real scenes almost always coincide with image edges.
+//!
+//! None of these is meaningful alone - an empty map is perfectly smooth - so they are reported
+//! together in a [`QualityReport`].
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use serde::Deserialize;
+
+use crate::consistency::consistency_rate;
+use crate::disparity::{DisparityAlgorithm, DisparityMap, Reference};
+use crate::error::*;
+use crate::warp::backward_warp;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Thresholds used when computing a [`QualityReport`].
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct QualityParams {
+    /// Largest disparity difference for a pixel to pass the left-right check.
+    pub lr_threshold: f32,
+
+    /// Disparity difference between neighbouring pixels above which they form a disparity edge.
+    pub disparity_edge_threshold: f32,
+
+    /// Intensity gradient magnitude above which a pixel is an image edge.
+    pub image_edge_threshold: f32
+}
+
+/// Quality scores of a left-referenced disparity map.
+#[derive(Debug, Clone, Copy)]
+pub struct QualityReport {
+    /// Mean absolute photometric error over the pixels which could be reprojected, lower is
+    /// better.
+    pub photometric_error: f32,
+
+    /// Fraction of the pixels which could be reprojected.
+    pub reprojected: f32,
+
+    /// Fraction of valid pixels passing the left-right check, if a right-referenced map was
+    /// given. Higher is better.
+    pub lr_consistency: Option<f32>,
+
+    /// Fraction of pixels with a valid disparity, higher is better.
+    pub density: f32,
+
+    /// Mean absolute disparity difference between neighbouring valid pixels, lower is better.
+    pub smoothness: f32,
+
+    /// Fraction of disparity edges within one pixel of an image edge, higher is better. One if
+    /// the map has no disparity edges.
+    pub edge_alignment: f32
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for QualityParams {
+    fn default() -> Self {
+        Self {
+            lr_threshold: 1.0,
+            disparity_edge_threshold: 1.0,
+            image_edge_threshold: 0.05
+        }
+    }
+}
+
+impl QualityReport {
+    /// Score a left-referenced disparity map of the given frame, optionally checking it against a
+    /// right-referenced map of the same frame.
+    pub fn compute(
+        frame: &StereoFrame,
+        left: &DisparityMap,
+        right: Option<&DisparityMap>,
+        params: &QualityParams
+    ) -> Result<Self> {
+        let warped = backward_warp(&frame.right, left, Reference::Left)?;
+
+        let mut error_sum = 0.0f64;
+        let mut error_count = 0usize;
+
+        for y in 0..warped.height() {
+            for x in 0..warped.width() {
+                if warped.is_valid(x, y) {
+                    error_sum += (frame.left.get(x, y) - warped.image.get(x, y)).abs() as f64;
+                    error_count += 1;
+                }
+            }
+        }
+
+        let lr_consistency = match right {
+            Some(r) => {
+                if (r.width(), r.height()) != (left.width(), left.height()) {
+                    return Err(Error::InvalidParams(format!(
+                        "right disparity map is {:?} pixels but the left is {:?} pixels",
+                        (r.width(), r.height()),
+                        (left.width(), left.height())
+                    )));
+                }
+
+                Some(consistency_rate(left, r, params.lr_threshold))
+            },
+            None => None
+        };
+
+        Ok(Self {
+            photometric_error: mean(error_sum, error_count),
+            reprojected: warped.coverage(),
+            lr_consistency,
+            density: left.density(),
+            smoothness: smoothness(left),
+            edge_alignment: edge_alignment(&frame.left, left, params)
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Compute the left and right-referenced maps of a frame with the given algorithm and score the
+/// left map.
+pub fn evaluate<A: DisparityAlgorithm + ?Sized>(
+    algorithm: &mut A,
+    frame: &StereoFrame,
+    params: &QualityParams
+) -> Result<(DisparityMap, QualityReport)> {
+    let left = algorithm.compute(frame)?;
+    let right = algorithm.compute_right(frame)?;
+
+    let report = QualityReport::compute(frame, &left, Some(&right), params)?;
+
+    Ok((left, report))
+}
+
+/// Mean absolute disparity difference between horizontally and vertically neighbouring pixels
+/// which are both valid.
+pub fn smoothness(map: &DisparityMap) -> f32 {
+    let mut sum = 0.0f64;
+    let mut count = 0usize;
+
+    for_each_neighbour(map, |a, b| {
+        sum += (a - b).abs() as f64;
+        count += 1;
+    });
+
+    mean(sum, count)
+}
+
+/// Fraction of the disparity edges of a map which are within one pixel of an intensity edge of
+/// its reference image, with both kinds of edge found using the thresholds in the params. One if
+/// the map has no disparity edges.
+pub fn edge_alignment(image: &GrayFloatImage, map: &DisparityMap, params: &QualityParams) -> f32 {
+    let width = map.width();
+    let height = map.height();
+
+    // Mark image edges with a central difference gradient
+    let mut image_edges = vec![false; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let gx = image.get((x + 1).min(width - 1), y) - image.get(x.saturating_sub(1), y);
+            let gy = image.get(x, (y + 1).min(height - 1)) - image.get(x, y.saturating_sub(1));
+
+            image_edges[y * width + x] =
+                0.5 * (gx * gx + gy * gy).sqrt() > params.image_edge_threshold;
+        }
+    }
+
+    // An image edge anywhere in the 3x3 neighbourhood counts as aligned
+    let near_edge = |x: usize, y: usize| {
+        (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+            (x.saturating_sub(1)..(x + 2).min(width)).any(|nx| image_edges[ny * width + nx])
+        })
+    };
+
+    let mut edges = 0usize;
+    let mut aligned = 0usize;
+
+    for y in 0..height {
+        for x in 0..width {
+            if !map.is_valid(x, y) {
+                continue;
+            }
+
+            let d = map.get(x, y);
+
+            let is_edge = [(x + 1, y), (x, y + 1)].iter().any(|&(nx, ny)| {
+                nx < width && ny < height && map.is_valid(nx, ny)
+                    && (map.get(nx, ny) - d).abs() > params.disparity_edge_threshold
+            });
+
+            if is_edge {
+                edges += 1;
+
+                if near_edge(x, y) {
+                    aligned += 1;
+                }
+            }
+        }
+    }
+
+    match edges {
+        0 => 1.0,
+        _ => aligned as f32 / edges as f32
+    }
+}
+
+/// Call `f` with the disparities of every pair of horizontally or vertically neighbouring pixels
+/// which are both valid.
+fn for_each_neighbour<F: FnMut(f32, f32)>(map: &DisparityMap, mut f: F) {
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if !map.is_valid(x, y) {
+                continue;
+            }
+
+            if x + 1 < map.width() && map.is_valid(x + 1, y) {
+                f(map.get(x, y), map.get(x + 1, y));
+            }
+
+            if y + 1 < map.height() && map.is_valid(x, y + 1) {
+                f(map.get(x, y), map.get(x, y + 1));
+            }
+        }
+    }
+}
+
+/// Mean of a sum over a count, or zero if the count is zero.
+fn mean(sum: f64, count: usize) -> f32 {
+    match count {
+        0 => 0.0,
+        _ => (sum / count as f64) as f32
+    }
+}
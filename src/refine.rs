@@ -0,0 +1,486 @@
+//! # Disparity refinement filters
+//!
+//! This module provides edge-aware filters which refine a computed disparity map, using the left
+//! image as a guide so that smoothing stops at intensity edges:
+//!
+//! - [`JointBilateral`], a joint bilateral filter.
+//! - [`WeightedMedian`], a weighted median filter with bilateral weights, which removes outliers
+//!   without blurring disparity edges.
+//! - [`FastGlobalSmoother`], the weighted least squares smoother from
+//!   ("Fast Global Image Smoothing Based on Weighted Least Squares")[https://doi.org/10.1109/TIP.2014.2366600]
+//!   by Min et al.
+//! - [`Guided`], the guided filter from [`crate::guided_filter`] applied to the map.
+//!
+//! Invalid and occluded pixels never contribute to the output. By default they are also left as
+//! they are, but each filter can instead fill them from the valid pixels supporting them.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::cmp::Ordering;
+
+use cv_camstream::GrayFloatImage;
+use serde::Deserialize;
+
+use crate::disparity::DisparityMap;
+use crate::error::*;
+use crate::guided_filter::GuidedFilter;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Smallest filtered confidence the guided filter divides by.
+const MIN_CONFIDENCE: f32 = 0.1;
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// A filter which refines a disparity map using its reference image as a guide.
+pub trait DisparityFilter {
+    /// Filter the map, which must be the same size as the guide.
+    fn filter(&self, map: &DisparityMap, guide: &GrayFloatImage) -> Result<DisparityMap>;
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Joint bilateral filter.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct JointBilateral {
+    /// Radius of the square filter window.
+    pub radius: usize,
+
+    /// Standard deviation of the spatial weight, in pixels.
+    pub sigma_space: f32,
+
+    /// Standard deviation of the guide intensity weight.
+    pub sigma_range: f32,
+
+    /// Whether to fill invalid pixels which have valid pixels in their window.
+    pub fill_invalid: bool
+}
+
+/// Weighted median filter with joint bilateral weights.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WeightedMedian {
+    /// Radius of the square filter window.
+    pub radius: usize,
+
+    /// Standard deviation of the spatial weight, in pixels.
+    pub sigma_space: f32,
+
+    /// Standard deviation of the guide intensity weight.
+    pub sigma_range: f32,
+
+    /// Whether to fill invalid pixels which have valid pixels in their window.
+    pub fill_invalid: bool
+}
+
+/// Fast global smoother, solving a weighted least squares problem with alternating 1D passes
+/// along the rows and columns.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FastGlobalSmoother {
+    /// Overall smoothness weight.
+    pub lambda: f32,
+
+    /// Guide intensity difference at which the smoothness weight falls to 1/e.
+    pub sigma_colour: f32,
+
+    /// Number of row and column pass pairs.
+    pub iterations: usize,
+
+    /// Whether to fill invalid pixels from the valid pixels around them.
+    pub fill_invalid: bool
+}
+
+/// Guided filter, smoothing the disparities and their confidences, which are one at valid pixels
+/// and zero elsewhere, and dividing to normalise.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Guided {
+    /// Radius of the square filter window.
+    pub radius: usize,
+
+    /// Regularisation, in squared guide intensity units.
+    pub epsilon: f32,
+
+    /// Whether to fill invalid pixels from the valid pixels around them.
+    pub fill_invalid: bool
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for JointBilateral {
+    fn default() -> Self {
+        Self {
+            radius: 5,
+            sigma_space: 3.0,
+            sigma_range: 0.1,
+            fill_invalid: false
+        }
+    }
+}
+
+impl Default for WeightedMedian {
+    fn default() -> Self {
+        Self {
+            radius: 5,
+            sigma_space: 3.0,
+            sigma_range: 0.1,
+            fill_invalid: false
+        }
+    }
+}
+
+impl Default for FastGlobalSmoother {
+    fn default() -> Self {
+        Self {
+            lambda: 900.0,
+            sigma_colour: 0.03,
+            iterations: 3,
+            fill_invalid: false
+        }
+    }
+}
+
+impl Default for Guided {
+    fn default() -> Self {
+        Self {
+            radius: 4,
+            epsilon: 0.0001,
+            fill_invalid: false
+        }
+    }
+}
+
+impl DisparityFilter for JointBilateral {
+    fn filter(&self, map: &DisparityMap, guide: &GrayFloatImage) -> Result<DisparityMap> {
+        check_size(map, guide)?;
+
+        let mut output = copy_validity(map);
+
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if !map.is_valid(x, y) && !self.fill_invalid {
+                    continue;
+                }
+
+                let mut num = 0.0f32;
+                let mut den = 0.0f32;
+
+                for_each_support(
+                    map, guide, (x, y), self.radius, self.sigma_space, self.sigma_range,
+                    |d, w| {
+                        num += w * d;
+                        den += w;
+                    }
+                );
+
+                if den > 0.0 {
+                    output.put(x, y, num / den);
+                }
+            }
+        }
+
+        output.update_range();
+
+        Ok(output)
+    }
+}
+
+impl DisparityFilter for WeightedMedian {
+    fn filter(&self, map: &DisparityMap, guide: &GrayFloatImage) -> Result<DisparityMap> {
+        check_size(map, guide)?;
+
+        let mut output = copy_validity(map);
+        let mut samples: Vec<(f32, f32)> = Vec::new();
+
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if !map.is_valid(x, y) && !self.fill_invalid {
+                    continue;
+                }
+
+                samples.clear();
+
+                for_each_support(
+                    map, guide, (x, y), self.radius, self.sigma_space, self.sigma_range,
+                    |d, w| samples.push((d, w))
+                );
+
+                if samples.is_empty() {
+                    continue;
+                }
+
+                samples.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+                // The median is the first sample where the cumulative weight reaches half
+                let half = samples.iter().map(|s| s.1).sum::<f32>() / 2.0;
+                let mut cumulative = 0.0f32;
+
+                for &(d, w) in &samples {
+                    cumulative += w;
+
+                    if cumulative >= half {
+                        output.put(x, y, d);
+                        break;
+                    }
+                }
+            }
+        }
+
+        output.update_range();
+
+        Ok(output)
+    }
+}
+
+impl DisparityFilter for FastGlobalSmoother {
+    fn filter(&self, map: &DisparityMap, guide: &GrayFloatImage) -> Result<DisparityMap> {
+        check_size(map, guide)?;
+
+        let width = map.width();
+        let height = map.height();
+
+        // Smooth the disparities weighted by a confidence of one at valid pixels and zero
+        // elsewhere, along with the confidences themselves, and divide to normalise
+        let mut values = vec![0.0f32; width * height];
+        let mut confidence = vec![0.0f32; width * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                if map.is_valid(x, y) {
+                    values[y * width + x] = map.get(x, y);
+                    confidence[y * width + x] = 1.0;
+                }
+            }
+        }
+
+        let mut guide_vals = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                guide_vals.push(guide.get(x, y));
+            }
+        }
+
+        // The smoothness weight falls on each iteration, as in the paper
+        let iters = self.iterations.max(1) as i32;
+        let scale = 4.0f32.powi(iters) - 1.0;
+
+        for t in 0..iters {
+            let lambda_t = self.lambda * 1.5 * 4.0f32.powi(iters - t - 1) / scale;
+
+            self.smooth(&mut values, &guide_vals, width, height, lambda_t);
+            self.smooth(&mut confidence, &guide_vals, width, height, lambda_t);
+        }
+
+        let mut output = copy_validity(map);
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+
+                if (map.is_valid(x, y) || self.fill_invalid) && confidence[i] > 1e-6 {
+                    output.put(x, y, values[i] / confidence[i]);
+                }
+            }
+        }
+
+        output.update_range();
+
+        Ok(output)
+    }
+}
+
+impl DisparityFilter for Guided {
+    fn filter(&self, map: &DisparityMap, guide: &GrayFloatImage) -> Result<DisparityMap> {
+        check_size(map, guide)?;
+
+        let width = map.width();
+        let height = map.height();
+
+        let mut values = vec![0.0f32; width * height];
+        let mut confidence = vec![0.0f32; width * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                if map.is_valid(x, y) {
+                    values[y * width + x] = map.get(x, y);
+                    confidence[y * width + x] = 1.0;
+                }
+            }
+        }
+
+        let filter = GuidedFilter::new(guide, self.radius, self.epsilon);
+        let values = filter.filter(&values);
+        let confidence = filter.filter(&confidence);
+
+        let mut output = copy_validity(map);
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+
+                // The linear model can take the confidence close to zero next to strong edges,
+                // where valid pixels keep their own value rather than divide by it
+                if confidence[i] > MIN_CONFIDENCE {
+                    if map.is_valid(x, y) || self.fill_invalid {
+                        output.put(x, y, values[i] / confidence[i]);
+                    }
+                }
+                else if map.is_valid(x, y) {
+                    output.put(x, y, map.get(x, y));
+                }
+            }
+        }
+
+        output.update_range();
+
+        Ok(output)
+    }
+}
+
+impl FastGlobalSmoother {
+    /// Run one pass along every row and then every column of a row-major buffer.
+    fn smooth(&self, buf: &mut [f32], guide: &[f32], width: usize, height: usize, lambda: f32) {
+        let mut scratch = Vec::new();
+
+        for y in 0..height {
+            let idx: Vec<usize> = (0..width).map(|x| y * width + x).collect();
+            self.solve_line(buf, guide, &idx, lambda, &mut scratch);
+        }
+
+        for x in 0..width {
+            let idx: Vec<usize> = (0..height).map(|y| y * width + x).collect();
+            self.solve_line(buf, guide, &idx, lambda, &mut scratch);
+        }
+    }
+
+    /// Solve the 1D weighted least squares problem along the pixels at the given indices, in
+    /// place, with the Thomas algorithm.
+    fn solve_line(
+        &self,
+        buf: &mut [f32],
+        guide: &[f32],
+        idx: &[usize],
+        lambda: f32,
+        scratch: &mut Vec<f32>
+    ) {
+        let n = idx.len();
+        if n < 2 {
+            return;
+        }
+
+        // Smoothness weight between each pixel and the next along the line
+        let weight = |i: usize| {
+            let diff = (guide[idx[i]] - guide[idx[i + 1]]).abs();
+            lambda * (-diff / self.sigma_colour).exp()
+        };
+
+        // Forward sweep, keeping the modified upper diagonal in the scratch buffer
+        scratch.clear();
+        scratch.resize(n, 0.0);
+
+        let mut prev_w = 0.0f32;
+        let mut prev_c = 0.0f32;
+
+        for i in 0..n {
+            let next_w = if i + 1 < n { weight(i) } else { 0.0 };
+
+            let a = -prev_w;
+            let b = 1.0 + prev_w + next_w;
+            let c = -next_w;
+
+            let denom = b - a * prev_c;
+
+            scratch[i] = c / denom;
+            buf[idx[i]] = match i {
+                0 => buf[idx[i]] / denom,
+                _ => (buf[idx[i]] - a * buf[idx[i - 1]]) / denom
+            };
+
+            prev_w = next_w;
+            prev_c = scratch[i];
+        }
+
+        // Back substitution
+        for i in (0..n - 1).rev() {
+            buf[idx[i]] -= scratch[i] * buf[idx[i + 1]];
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Check the map and guide have the same size.
+fn check_size(map: &DisparityMap, guide: &GrayFloatImage) -> Result<()> {
+    let guide_size = (guide.width() as usize, guide.height() as usize);
+
+    match (map.width(), map.height()) == guide_size {
+        true => Ok(()),
+        false => Err(Error::InvalidParams(format!(
+            "disparity map is {:?} pixels but the guide is {:?} pixels",
+            (map.width(), map.height()),
+            guide_size
+        )))
+    }
+}
+
+/// Create an empty map with the same validity labels as the given map, so that pixels the filter
+/// does not write keep their label.
+fn copy_validity(map: &DisparityMap) -> DisparityMap {
+    let mut output = DisparityMap::new(map.width(), map.height());
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            output.set_validity(x, y, map.validity(x, y));
+        }
+    }
+
+    output
+}
+
+/// Call `f` with the disparity and joint bilateral weight of every valid pixel in the window
+/// around `centre`.
+fn for_each_support<F: FnMut(f32, f32)>(
+    map: &DisparityMap,
+    guide: &GrayFloatImage,
+    centre: (usize, usize),
+    radius: usize,
+    sigma_space: f32,
+    sigma_range: f32,
+    mut f: F
+) {
+    let (x, y) = centre;
+    let centre_val = guide.get(x, y);
+
+    let space_den = 2.0 * sigma_space * sigma_space;
+    let range_den = 2.0 * sigma_range * sigma_range;
+
+    for qy in y.saturating_sub(radius)..(y + radius + 1).min(map.height()) {
+        for qx in x.saturating_sub(radius)..(x + radius + 1).min(map.width()) {
+            if !map.is_valid(qx, qy) {
+                continue;
+            }
+
+            let dx = qx as f32 - x as f32;
+            let dy = qy as f32 - y as f32;
+            let di = guide.get(qx, qy) - centre_val;
+
+            let w = (-(dx * dx + dy * dy) / space_den - di * di / range_den).exp();
+
+            f(map.get(qx, qy), w);
+        }
+    }
+}
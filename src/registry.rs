@@ -0,0 +1,106 @@
+//! # Algorithm registry
+//!
+//! This module constructs disparity algorithms by name, with their parameters given as a TOML
+//! value, so that configs, tests, benches and the command line interface can all choose an
+//! algorithm without naming its type.
+//!
+//! The names are the algorithms' module names. Algorithms whose parameters have defaults may be
+//! built without parameters, and any fields missing from the given parameters take their
+//! defaults.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use serde::de::DeserializeOwned;
+
+use crate::ad_census::{self, AdCensus};
+use crate::asw::{self, AdaptiveSupportWeight};
+use crate::belief_propagation::{self, BeliefPropagation};
+use crate::cost_filter::{self, CostFilter};
+use crate::disparity::DisparityAlgorithm;
+use crate::dynamic_programming::{self, DynamicProgramming};
+use crate::error::*;
+use crate::mcmanamon::{self, McManamon};
+use crate::non_local::{self, NonLocal};
+use crate::patch_match::{self, PatchMatch};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Names of every algorithm the registry can build.
+pub const ALGORITHMS: [&str; 8] = [
+    "ad_census",
+    "asw",
+    "belief_propagation",
+    "cost_filter",
+    "dynamic_programming",
+    "mcmanamon",
+    "non_local",
+    "patch_match"
+];
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Build an algorithm by name.
+pub fn build(name: &str, params: Option<&toml::Value>) -> Result<Box<dyn DisparityAlgorithm>> {
+    let alg: Box<dyn DisparityAlgorithm> = match name {
+        "ad_census" => Box::new(AdCensus::new(
+            params_or_default::<ad_census::Params>(name, params)?
+        )),
+        "asw" => Box::new(AdaptiveSupportWeight::new(
+            required_params::<asw::Params>(name, params)?
+        )),
+        "belief_propagation" => Box::new(BeliefPropagation::new(
+            params_or_default::<belief_propagation::Params>(name, params)?
+        )),
+        "cost_filter" => Box::new(CostFilter::new(
+            params_or_default::<cost_filter::Params>(name, params)?
+        )),
+        "dynamic_programming" => Box::new(DynamicProgramming::new(
+            required_params::<dynamic_programming::Params>(name, params)?
+        )),
+        "mcmanamon" => Box::new(McManamon::new(
+            required_params::<mcmanamon::Params>(name, params)?
+        )),
+        "non_local" => Box::new(NonLocal::new(
+            params_or_default::<non_local::Params>(name, params)?
+        )),
+        "patch_match" => Box::new(PatchMatch::new(
+            params_or_default::<patch_match::Params>(name, params)?
+        )),
+        _ => return Err(Error::InvalidParams(format!(
+            "unknown algorithm {:?}, expected one of {}", name, ALGORITHMS.join(", ")
+        )))
+    };
+
+    Ok(alg)
+}
+
+/// Deserialize parameters which have no defaults, so must be given.
+fn required_params<T: DeserializeOwned>(name: &str, params: Option<&toml::Value>) -> Result<T> {
+    match params {
+        Some(params) => deserialize(name, params),
+        None => Err(Error::InvalidParams(format!("{} requires parameters", name)))
+    }
+}
+
+fn params_or_default<T: DeserializeOwned + Default>(
+    name: &str,
+    params: Option<&toml::Value>
+) -> Result<T> {
+    match params {
+        Some(params) => deserialize(name, params),
+        None => Ok(T::default())
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(name: &str, params: &toml::Value) -> Result<T> {
+    params
+        .clone()
+        .try_into()
+        .map_err(|e| Error::InvalidParams(format!("{} parameters: {}", name, e)))
+}
@@ -0,0 +1,347 @@
+//! # Synthetic stereo pairs
+//!
+//! This module generates stereo frames with exactly known disparity, so that the correctness of
+//! algorithms can be checked without cameras or renders.
+//!
+//! A [`Scene`] is a background plane with any number of rectangular layers in front of it, each
+//! with its own disparity plane `d(x, y) = disparity + dx * x + dy * y` in left image
+//! coordinates. This covers random-dot stereograms, fronto-parallel and slanted planes, steps and
+//! occluding boxes. Every surface carries its own random-dot texture, fixed to the surface so
+//! that both images see the same texture, which is interpolated along the rows so that sub-pixel
+//! disparities are matched exactly.
+//!
+//! The right image is rendered by finding, for each right pixel, the closest surface which lands
+//! on it. The left-referenced ground truth marks the pixels which are hidden in the right image,
+//! either by a closer surface or by falling outside it, as occluded.
+//!
+//! Noise and a gain and bias mismatch between the cameras can be added, with a seed so that
+//! every generated pair is reproducible.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::disparity::DisparityMap;
+use crate::error::*;
+use crate::eval::GroundTruth;
+use crate::tiling::Rect;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Parameters of the generated images.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Params {
+    pub width: usize,
+    pub height: usize,
+
+    /// Seed of the textures and noise.
+    pub seed: u64,
+
+    /// Size of the texture's dots in pixels.
+    pub dot_size: usize,
+
+    /// Standard deviation of the Gaussian noise added to both images.
+    pub noise: f32,
+
+    /// Gain and bias applied to the right image, to simulate mismatched cameras.
+    pub gain: f32,
+    pub bias: f32
+}
+
+/// A disparity plane in left image coordinates, `d(x, y) = disparity + dx * x + dy * y`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub disparity: f32,
+    pub dx: f32,
+    pub dy: f32
+}
+
+/// A rectangular surface in front of the background.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Layer {
+    /// Extent of the layer in the left image.
+    pub rect: Rect,
+
+    pub plane: Plane
+}
+
+/// A synthetic scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    /// Plane covering the whole image behind the layers.
+    pub background: Plane,
+
+    /// Layers in front of the background. Where layers overlap the one with the largest
+    /// disparity is visible.
+    pub layers: Vec<Layer>
+}
+
+/// A generated stereo pair.
+pub struct Synthetic {
+    pub frame: StereoFrame,
+
+    /// Left-referenced ground truth, with a mask of the pixels visible in the right image.
+    pub truth: GroundTruth
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            width: 128,
+            height: 96,
+            seed: 0,
+            dot_size: 1,
+            noise: 0.0,
+            gain: 1.0,
+            bias: 0.0
+        }
+    }
+}
+
+impl Plane {
+    /// A plane of constant disparity.
+    pub fn fronto_parallel(disparity: f32) -> Self {
+        Self {
+            disparity,
+            dx: 0.0,
+            dy: 0.0
+        }
+    }
+
+    /// Disparity of the plane at the given left image position.
+    pub fn at(&self, x: f32, y: f32) -> f32 {
+        self.disparity + self.dx * x + self.dy * y
+    }
+
+    /// Left image column of the point of the plane which lands on the given right image column.
+    fn solve(&self, xr: f32, y: f32) -> f32 {
+        (xr + self.disparity + self.dy * y) / (1.0 - self.dx)
+    }
+}
+
+impl Scene {
+    /// A single plane of constant disparity.
+    pub fn fronto_parallel(disparity: f32) -> Self {
+        Self::slanted(Plane::fronto_parallel(disparity))
+    }
+
+    /// A single slanted plane.
+    pub fn slanted(plane: Plane) -> Self {
+        Self {
+            background: plane,
+            layers: Vec::new()
+        }
+    }
+
+    /// A random-dot stereogram, with a square of the given disparity floating over the middle of
+    /// the background.
+    pub fn random_dot(width: usize, height: usize, background: f32, square: f32) -> Self {
+        let size = width.min(height) / 2;
+
+        Self::boxes(
+            background,
+            &[(Rect::new((width - size) / 2, (height - size) / 2, size, size), square)]
+        )
+    }
+
+    /// A step in disparity, with the columns from `column` onwards at the `right` disparity.
+    pub fn step(width: usize, height: usize, column: usize, left: f32, right: f32) -> Self {
+        Self::boxes(left, &[(Rect::new(column, 0, width.saturating_sub(column), height), right)])
+    }
+
+    /// Fronto-parallel boxes of the given disparities in front of a fronto-parallel background.
+    pub fn boxes(background: f32, boxes: &[(Rect, f32)]) -> Self {
+        Self {
+            background: Plane::fronto_parallel(background),
+            layers: boxes
+                .iter()
+                .map(|&(rect, d)| Layer {
+                    rect,
+                    plane: Plane::fronto_parallel(d)
+                })
+                .collect()
+        }
+    }
+
+    /// Generate a stereo pair of the scene.
+    pub fn generate(&self, params: &Params) -> Result<Synthetic> {
+        self.check(params)?;
+
+        let width = params.width;
+        let height = params.height;
+
+        let mut left = GrayFloatImage::new(width, height);
+        let mut right = GrayFloatImage::new(width, height);
+        let mut truth = GroundTruth::new(DisparityMap::new(width, height));
+        let mut non_occluded = vec![false; width * height];
+
+        for y in 0..height {
+            let yf = y as f32;
+
+            for x in 0..width {
+                // Left image and ground truth
+                let (surface, d) = self.surface_at(x as f32, yf);
+
+                left.put(x, y, texture(params, surface, x as f32, y));
+                truth.disparity.put(x, y, d);
+
+                let xr = x as f32 - d;
+                non_occluded[y * width + x] = xr >= 0.0
+                    && xr <= (width - 1) as f32
+                    && self.visible(xr, yf).0 == surface;
+
+                // Right image
+                let (surface, x_left) = self.visible(x as f32, yf);
+
+                right.put(x, y, texture(params, surface, x_left, y));
+            }
+        }
+
+        truth.disparity.update_range();
+        truth.non_occluded = Some(non_occluded);
+
+        let mut rng = StdRng::seed_from_u64(params.seed);
+
+        apply_noise(&mut left, params.noise, 1.0, 0.0, &mut rng);
+        apply_noise(&mut right, params.noise, params.gain, params.bias, &mut rng);
+
+        Ok(Synthetic {
+            frame: StereoFrame {
+                left,
+                left_timestamp: 0,
+                right,
+                right_timestamp: 0
+            },
+            truth
+        })
+    }
+
+    /// Check the parameters and scene can be generated.
+    fn check(&self, params: &Params) -> Result<()> {
+        if params.width == 0 || params.height == 0 {
+            return Err(Error::InvalidParams("synthetic images must not be empty".into()));
+        }
+
+        if params.dot_size == 0 {
+            return Err(Error::InvalidParams("dot_size must be at least 1".into()));
+        }
+
+        // A plane sloping by a pixel per pixel or more would fold over itself in the right image
+        match self.background.dx < 1.0 && self.layers.iter().all(|l| l.plane.dx < 1.0) {
+            true => Ok(()),
+            false => Err(Error::InvalidParams("plane dx must be less than 1".into()))
+        }
+    }
+
+    /// Plane of a surface, with zero being the background and the layers following.
+    fn plane(&self, surface: usize) -> &Plane {
+        match surface {
+            0 => &self.background,
+            i => &self.layers[i - 1].plane
+        }
+    }
+
+    /// Whether a surface covers the given left image position.
+    fn covers(&self, surface: usize, x: f32, y: f32) -> bool {
+        match surface {
+            0 => true,
+            i => {
+                let r = &self.layers[i - 1].rect;
+
+                x >= r.x as f32 && x < r.right() as f32
+                    && y >= r.y as f32 && y < r.bottom() as f32
+            }
+        }
+    }
+
+    /// Closest surface at the given left image position, and its disparity.
+    fn surface_at(&self, x: f32, y: f32) -> (usize, f32) {
+        (0..=self.layers.len())
+            .filter(|&s| self.covers(s, x, y))
+            .map(|s| (s, self.plane(s).at(x, y)))
+            .fold((0, f32::NEG_INFINITY), |best, c| if c.1 > best.1 { c } else { best })
+    }
+
+    /// Closest surface landing on the given right image position, and the left image column of
+    /// the point which lands there.
+    fn visible(&self, xr: f32, y: f32) -> (usize, f32) {
+        let mut best = (0, self.background.solve(xr, y));
+        let mut best_d = self.background.at(best.1, y);
+
+        for s in 1..=self.layers.len() {
+            let x = self.plane(s).solve(xr, y);
+            let d = self.plane(s).at(x, y);
+
+            if self.covers(s, x, y) && d > best_d {
+                best = (s, x);
+                best_d = d;
+            }
+        }
+
+        best
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Texture of a surface at the given left image position, linearly interpolated between dots
+/// along the row.
+fn texture(params: &Params, surface: usize, x: f32, y: usize) -> f32 {
+    let dot = params.dot_size as f32;
+
+    let u = x / dot - 0.5;
+    let u0 = u.floor();
+    let t = u - u0;
+
+    let row = (y / params.dot_size) as u64;
+    let value = |col: f32| {
+        let key = params.seed
+            ^ (surface as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ row.wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+            ^ (col as i64 as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+
+        (splitmix64(key) >> 40) as f32 / (1u64 << 24) as f32
+    };
+
+    value(u0) * (1.0 - t) + value(u0 + 1.0) * t
+}
+
+/// The splitmix64 hash, used to give each texture dot a random value without storing them.
+fn splitmix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Apply a gain, bias and Gaussian noise to an image, clamping it to [0, 1].
+fn apply_noise(image: &mut GrayFloatImage, sigma: f32, gain: f32, bias: f32, rng: &mut StdRng) {
+    for y in 0..image.height() as usize {
+        for x in 0..image.width() as usize {
+            let mut val = image.get(x, y) * gain + bias;
+
+            if sigma > 0.0 {
+                // Box-Muller transform
+                let u1: f32 = rng.gen_range(f32::EPSILON, 1.0);
+                let u2: f32 = rng.gen_range(0.0, 1.0);
+
+                val += sigma * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos();
+            }
+
+            image.put(x, y, val.max(0.0).min(1.0));
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! # Regions of interest and tiled computation
+//!
+//! This module provides rectangular regions of interest, the margins an algorithm needs around a
+//! region in order to compute it, and a tiled executor which splits a frame into overlapping
+//! tiles, computes each independently, and stitches the results back into a single map.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+
+use crate::disparity::{DisparityAlgorithm, DisparityMap};
+use crate::error::*;
+use crate::prior::DisparityPrior;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// An axis aligned rectangle in pixel coordinates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+/// The number of pixels an algorithm needs on each side of a region in order to compute the
+/// disparity of every pixel inside it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Margins {
+    pub left: usize,
+    pub right: usize,
+    pub top: usize,
+    pub bottom: usize
+}
+
+/// Executes an algorithm over a frame in independent tiles.
+///
+/// Each tile is expanded by the algorithm's margins before it is computed, and only the
+/// unexpanded tile is copied into the output map. For window-local algorithms, such as adaptive
+/// support weights, the tiles overlap by enough for every pixel to be computed as if the whole
+/// frame had been used. Algorithms which carry state across the image, such as McManamon's
+/// dynamic disparity range or the global optimisers (dynamic programming, belief propagation and
+/// non-local aggregation), give results near the tile edges which differ from a whole frame run.
+pub struct TiledExecutor<A: DisparityAlgorithm> {
+    inner: A,
+    tile_size: (usize, usize)
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Exclusive right hand edge of the rectangle.
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    /// Exclusive bottom edge of the rectangle.
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Clamp the rectangle so that it lies within an image of the given size.
+    pub fn clamp(&self, width: usize, height: usize) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+
+        Self {
+            x,
+            y,
+            width: self.right().min(width) - x,
+            height: self.bottom().min(height) - y
+        }
+    }
+
+    /// Expand the rectangle by the given margins, clamped to an image of the given size.
+    pub fn expand(&self, margins: Margins, width: usize, height: usize) -> Self {
+        let x = self.x.saturating_sub(margins.left);
+        let y = self.y.saturating_sub(margins.top);
+        let right = (self.right() + margins.right).min(width);
+        let bottom = (self.bottom() + margins.bottom).min(height);
+
+        Self {
+            x,
+            y,
+            width: right.saturating_sub(x),
+            height: bottom.saturating_sub(y)
+        }
+    }
+}
+
+impl<A: DisparityAlgorithm> TiledExecutor<A> {
+    /// Create a new executor which runs `inner` over tiles of the given (width, height).
+    pub fn new(inner: A, tile_size: (usize, usize)) -> Self {
+        Self {
+            inner,
+            tile_size: (tile_size.0.max(1), tile_size.1.max(1))
+        }
+    }
+
+    /// Get a reference to the wrapped algorithm.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Split the given region into tiles, row by row.
+    pub fn tiles(&self, rect: Rect) -> Vec<Rect> {
+        let mut tiles = Vec::new();
+
+        let mut y = rect.y;
+        while y < rect.bottom() {
+            let height = self.tile_size.1.min(rect.bottom() - y);
+
+            let mut x = rect.x;
+            while x < rect.right() {
+                let width = self.tile_size.0.min(rect.right() - x);
+                tiles.push(Rect::new(x, y, width, height));
+                x += width;
+            }
+
+            y += height;
+        }
+
+        tiles
+    }
+}
+
+impl<A: DisparityAlgorithm> DisparityAlgorithm for TiledExecutor<A> {
+    /// Compute the disparity map of the whole frame tile by tile.
+    fn compute(&mut self, frame: &StereoFrame) -> Result<DisparityMap> {
+        let rect = Rect::new(0, 0, frame.width() as usize, frame.height() as usize);
+
+        self.compute_roi(frame, rect)
+    }
+
+    /// Compute the disparity map of the whole frame with a prior.
+    ///
+    /// This is not tiled, the prior is passed to the wrapped algorithm with the whole frame.
+    fn compute_with_prior(
+        &mut self,
+        frame: &StereoFrame,
+        prior: &DisparityPrior
+    ) -> Result<DisparityMap> {
+        self.inner.compute_with_prior(frame, prior)
+    }
+
+    fn margins(&self) -> Margins {
+        self.inner.margins()
+    }
+
+    /// Compute the disparity map of the given region tile by tile.
+    fn compute_roi(&mut self, frame: &StereoFrame, rect: Rect) -> Result<DisparityMap> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let rect = rect.clamp(width, height);
+        if rect.is_empty() {
+            return Err(Error::EmptyRegion(rect));
+        }
+
+        let mut map = DisparityMap::new(width, height);
+
+        for tile in self.tiles(rect) {
+            let crop = tile.expand(self.inner.margins(), width, height);
+            let tile_map = self.inner.compute(&crop_frame(frame, crop))?;
+
+            map.paste(&tile_map, (crop.x, crop.y), tile);
+        }
+
+        Ok(map)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Crop both images of a stereo frame to the given region.
+///
+/// Both images are cropped identically, so disparities measured within the cropped frame are the
+/// same as in the original.
+pub fn crop_frame(frame: &StereoFrame, rect: Rect) -> StereoFrame {
+    StereoFrame {
+        left: crop_image(&frame.left, rect),
+        left_timestamp: frame.left_timestamp,
+        right: crop_image(&frame.right, rect),
+        right_timestamp: frame.right_timestamp
+    }
+}
+
+/// Crop an image to the given region.
+pub fn crop_image(image: &GrayFloatImage, rect: Rect) -> GrayFloatImage {
+    let mut cropped = GrayFloatImage::new(rect.width, rect.height);
+
+    for y in 0..rect.height {
+        for x in 0..rect.width {
+            cropped.put(x, y, image.get(rect.x + x, rect.y + y));
+        }
+    }
+
+    cropped
+}
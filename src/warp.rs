@@ -0,0 +1,264 @@
+//! # Image warping by disparity
+//!
+//! This module provides view synthesis from a disparity map, for example to warp the right image
+//! into the left view and compare it with the left image where no ground truth is available.
+//!
+//! - [`backward_warp`] samples the other view at each pixel's match with bilinear
+//!   interpolation, giving an image in the disparity map's own view.
+//! - [`forward_warp`] splats each pixel of the map's own view into the other view, keeping the
+//!   closest pixel where several land on the same place, giving an image in the other view.
+//! - [`occlusion_mask`] finds the pixels of the map's view which are hidden in the other view.
+//!
+//! The view a disparity map is referenced to is given by a [`Reference`], so the same functions
+//! warp in either direction.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_camstream::GrayFloatImage;
+
+use crate::disparity::{DisparityMap, Reference};
+use crate::error::*;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Disparity margin by which another pixel must be closer to hide a pixel.
+const OCCLUSION_TOLERANCE: f32 = 0.5;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A warped image, with a mask of the pixels which could be synthesised.
+pub struct WarpedImage {
+    pub image: GrayFloatImage,
+    mask: Vec<bool>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl WarpedImage {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            image: GrayFloatImage::new(width, height),
+            mask: vec![false; width * height]
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height() as usize
+    }
+
+    /// Whether the given pixel was synthesised. Other pixels are zero.
+    pub fn is_valid(&self, x: usize, y: usize) -> bool {
+        self.mask[y * self.width() + x]
+    }
+
+    /// Fraction of the pixels which were synthesised.
+    pub fn coverage(&self) -> f32 {
+        match self.mask.is_empty() {
+            true => 0.0,
+            false => self.mask.iter().filter(|&&m| m).count() as f32 / self.mask.len() as f32
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, val: f32) {
+        let width = self.width();
+
+        self.image.put(x, y, val);
+        self.mask[y * width + x] = true;
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Warp `source`, the image of the other view, into the view of the disparity map by sampling it
+/// at each pixel's match.
+///
+/// Pixels without a valid disparity, whose match is outside the source, or which are occluded in
+/// the other view are left out of the mask, since sampling would give the occluding surface.
+pub fn backward_warp(
+    source: &GrayFloatImage,
+    disparity: &DisparityMap,
+    reference: Reference
+) -> Result<WarpedImage> {
+    check_size(source, disparity)?;
+
+    let width = disparity.width();
+    let height = disparity.height();
+    let sign = direction(reference);
+    let occluded = occlusion_mask(disparity, reference);
+
+    let mut warped = WarpedImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            if !disparity.is_valid(x, y) || occluded[y * width + x] {
+                continue;
+            }
+
+            let sx = x as f32 + sign * disparity.get(x, y);
+
+            if let Some(val) = sample_bilinear(source, sx, y as f32) {
+                warped.put(x, y, val);
+            }
+        }
+    }
+
+    Ok(warped)
+}
+
+/// Warp `image`, the image of the disparity map's own view, into the other view by splatting
+/// each pixel to its match.
+///
+/// Where several pixels land on the same target pixel the one with the largest disparity, which
+/// is closest to the camera, is kept. Target pixels nothing lands on are disoccluded and left out
+/// of the mask.
+pub fn forward_warp(
+    image: &GrayFloatImage,
+    disparity: &DisparityMap,
+    reference: Reference
+) -> Result<WarpedImage> {
+    check_size(image, disparity)?;
+
+    let width = disparity.width();
+    let height = disparity.height();
+
+    let mut warped = WarpedImage::new(width, height);
+    let mut z_buffer = vec![f32::NEG_INFINITY; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let target = match splat_target(disparity, reference, x, y) {
+                Some(t) => t,
+                None => continue
+            };
+
+            let d = disparity.get(x, y);
+
+            if d > z_buffer[y * width + target] {
+                z_buffer[y * width + target] = d;
+                warped.put(target, y, image.get(x, y));
+            }
+        }
+    }
+
+    Ok(warped)
+}
+
+/// Find the pixels of the disparity map's view which are hidden in the other view, because a
+/// closer pixel lands on the same place.
+///
+/// Returns a row-major mask, true where occluded. Pixels without a valid disparity are not
+/// marked.
+pub fn occlusion_mask(disparity: &DisparityMap, reference: Reference) -> Vec<bool> {
+    let width = disparity.width();
+    let height = disparity.height();
+
+    // Largest disparity landing on each pixel of the other view
+    let mut z_buffer = vec![f32::NEG_INFINITY; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            if let Some(t) = splat_target(disparity, reference, x, y) {
+                let z = &mut z_buffer[y * width + t];
+                *z = z.max(disparity.get(x, y));
+            }
+        }
+    }
+
+    let mut mask = vec![false; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            if let Some(t) = splat_target(disparity, reference, x, y) {
+                mask[y * width + x] =
+                    z_buffer[y * width + t] > disparity.get(x, y) + OCCLUSION_TOLERANCE;
+            }
+        }
+    }
+
+    mask
+}
+
+/// Sample an image at a sub pixel position with bilinear interpolation.
+///
+/// Returns `None` if the position is outside the image.
+pub fn sample_bilinear(image: &GrayFloatImage, x: f32, y: f32) -> Option<f32> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    if x < 0.0 || y < 0.0 || x > (width - 1) as f32 || y > (height - 1) as f32 {
+        return None;
+    }
+
+    let x0 = x.floor() as usize;
+    let y0 = y.floor() as usize;
+    let x1 = (x0 + 1).min(width - 1);
+    let y1 = (y0 + 1).min(height - 1);
+
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+
+    let top = image.get(x0, y0) * (1.0 - fx) + image.get(x1, y0) * fx;
+    let bottom = image.get(x0, y1) * (1.0 - fx) + image.get(x1, y1) * fx;
+
+    Some(top * (1.0 - fy) + bottom * fy)
+}
+
+/// Sign of the offset from a pixel to its match in the other view.
+fn direction(reference: Reference) -> f32 {
+    match reference {
+        Reference::Left => -1.0,
+        Reference::Right => 1.0
+    }
+}
+
+/// Nearest pixel column in the other view which the given pixel lands on, if it has a valid
+/// disparity and lands inside the image.
+fn splat_target(
+    disparity: &DisparityMap,
+    reference: Reference,
+    x: usize,
+    y: usize
+) -> Option<usize> {
+    if !disparity.is_valid(x, y) {
+        return None;
+    }
+
+    let t = (x as f32 + direction(reference) * disparity.get(x, y)).round();
+
+    match t >= 0.0 && t < disparity.width() as f32 {
+        true => Some(t as usize),
+        false => None
+    }
+}
+
+/// Check an image and disparity map have the same size.
+fn check_size(image: &GrayFloatImage, disparity: &DisparityMap) -> Result<()> {
+    let image_size = (image.width() as usize, image.height() as usize);
+
+    match (disparity.width(), disparity.height()) == image_size {
+        true => Ok(()),
+        false => Err(Error::InvalidParams(format!(
+            "disparity map is {:?} pixels but the image is {:?} pixels",
+            (disparity.width(), disparity.height()),
+            image_size
+        )))
+    }
+}
@@ -0,0 +1,74 @@
+//! Test the AD-Census algorithm on synthetic pairs.
+
+use cv_disparity::{
+    prelude::*,
+    ad_census::{AdCensus, Params},
+    eval::{Metrics, Region},
+    synthetic::{self, Scene}
+};
+
+#[test]
+fn recovers_random_dot() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let map = AdCensus::new(ad_census_params()).compute(&pair.frame)?;
+
+    let metrics = Metrics::compute(&map, &pair.truth, Region::NonOccluded)?;
+    assert!(metrics.coverage > 0.9, "coverage of {}", metrics.coverage);
+    assert!(metrics.bad[1] < 10.0, "bad1 of {:.2}%", metrics.bad[1]);
+
+    Ok(())
+}
+
+#[test]
+fn census_handles_radiometric_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    // The right camera is brighter and offset, which breaks the absolute difference cost but
+    // leaves the census transform unchanged
+    let params = synthetic::Params {
+        gain: 1.5,
+        bias: -0.15,
+        seed: 3,
+        ..Default::default()
+    };
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let map = AdCensus::new(ad_census_params()).compute(&pair.frame)?;
+
+    let metrics = Metrics::compute(&map, &pair.truth, Region::NonOccluded)?;
+    assert!(metrics.coverage > 0.85, "coverage of {}", metrics.coverage);
+    assert!(metrics.bad[1] < 15.0, "bad1 of {:.2}%", metrics.bad[1]);
+
+    Ok(())
+}
+
+#[test]
+fn voting_fills_unreliable_pixels() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let scene = Scene::boxes(2.0, &[(Rect::new(40, 20, 40, 50), 9.0)]);
+    let pair = scene.generate(&params)?;
+
+    let without = AdCensus::new(Params {
+        vote_iterations: 0,
+        ..ad_census_params()
+    }).compute(&pair.frame)?;
+    let with = AdCensus::new(ad_census_params()).compute(&pair.frame)?;
+
+    // The box's occlusions fail the left-right check, and voting fills some of them from the
+    // background around them
+    assert!(without.density() < 1.0);
+    assert!(with.density() > without.density());
+
+    let before = Metrics::compute(&without, &pair.truth, Region::NonOccluded)?;
+    let after = Metrics::compute(&with, &pair.truth, Region::NonOccluded)?;
+    assert!(after.bad[1] <= before.bad[1]);
+
+    Ok(())
+}
+
+fn ad_census_params() -> Params {
+    Params {
+        max_disparity: 16,
+        ..Default::default()
+    }
+}
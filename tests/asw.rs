@@ -0,0 +1,82 @@
+//! Test the adaptive support weight algorithm.
+
+use cv_camstream::StereoFrame;
+use cv_disparity::{
+    prelude::*,
+    asw::{AdaptiveSupportWeight, Params},
+    synthetic::{self, Scene}
+};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 48;
+
+/// Column of the left image the foreground starts at.
+const STEP: usize = 48;
+
+const BACK: usize = 4;
+const FRONT: usize = 10;
+
+#[test]
+fn edges_stay_sharp() -> Result<(), Box<dyn std::error::Error>> {
+    let frame = two_tone_step()?;
+
+    let mut disp = AdaptiveSupportWeight::new(Params {
+        min_disparity: 0,
+        max_disparity: 16,
+        window_size: 15,
+        gamma_similarity: 0.05,
+        gamma_proximity: 7.5,
+        cost: Default::default()
+    });
+
+    let map = disp.compute(&frame)?;
+
+    // The background from STEP - (FRONT - BACK) up to the step is hidden in the right image, so
+    // check the visible pixels just either side of that and of the step itself
+    let back = (STEP - (FRONT - BACK) - 2)..(STEP - (FRONT - BACK));
+    let front = STEP..(STEP + 2);
+
+    for y in 8..(HEIGHT - 8) {
+        for (xs, truth) in [(back.clone(), BACK), (front.clone(), FRONT)].iter() {
+            for x in xs.clone() {
+                assert!(
+                    (map.get(x, y) - *truth as f32).abs() <= 1.0,
+                    "({}, {}) has disparity {} rather than {}", x, y, map.get(x, y), truth
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A step from a dark background to a bright foreground, with the synthetic texture of each
+/// surface squeezed into its own intensity band, so that the discontinuity is also an intensity
+/// edge.
+fn two_tone_step() -> cv_disparity::Result<StereoFrame> {
+    let params = synthetic::Params {
+        width: WIDTH,
+        height: HEIGHT,
+        ..Default::default()
+    };
+    let mut frame = Scene::step(WIDTH, HEIGHT, STEP, BACK as f32, FRONT as f32)
+        .generate(&params)?
+        .frame;
+
+    let tone = |value: f32, front: bool| match front {
+        true => 0.7 + 0.2 * value,
+        false => 0.1 + 0.2 * value
+    };
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let left = tone(frame.left.get(x, y), x >= STEP);
+            frame.left.put(x, y, left);
+
+            let right = tone(frame.right.get(x, y), x + FRONT >= STEP && x + FRONT < WIDTH);
+            frame.right.put(x, y, right);
+        }
+    }
+
+    Ok(frame)
+}
@@ -0,0 +1,115 @@
+//! Test batch processing of a directory tree of stereo pairs.
+
+use std::path::Path;
+
+use cv_disparity::{
+    prelude::*,
+    batch::{self, Params},
+    io::read_pfm,
+    mcmanamon::{self, McManamon}
+};
+use image::{GrayImage, Luma};
+
+#[test]
+fn mirrored_tree_and_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let root = std::env::temp_dir().join("cv_disparity_batch_test");
+    let input = root.join("input");
+    let output = root.join("output");
+
+    if root.exists() {
+        std::fs::remove_dir_all(&root)?;
+    }
+    std::fs::create_dir_all(input.join("scene"))?;
+
+    write_pair(&input, "a", 4)?;
+    write_pair(&input.join("scene"), "b", 6)?;
+
+    // Unmatched left image and an unrelated file, both skipped
+    pattern(0).save(input.join("c_left.png"))?;
+    std::fs::write(input.join("notes.txt"), "not an image")?;
+
+    // A right image which fails to load
+    pattern(0).save(input.join("d_left.png"))?;
+    std::fs::write(input.join("d_right.png"), "not an image")?;
+
+    let params = Params {
+        preview: true,
+        ..Default::default()
+    };
+
+    let pairs = batch::find_pairs(&input, &params)?;
+    let names: Vec<&Path> = pairs.iter().map(|p| p.name.as_path()).collect();
+    assert_eq!(names, vec![Path::new("a"), Path::new("d"), Path::new("scene/b")]);
+
+    let mut disp = McManamon::new(mcmanamon::Params {
+        min_disparity: 0,
+        max_disparity: 12,
+        dyn_disparity_threshold: 2,
+        correlation_window_size: (7, 7),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    });
+
+    let summary = batch::run(&mut disp, &input, &output, &params)?;
+
+    assert_eq!(summary.frames.len(), 3);
+    assert_eq!(summary.failures().count(), 1);
+    assert_eq!(summary.frames[1].name, "d");
+
+    let b = &summary.frames[2];
+    assert_eq!(b.name, "scene/b");
+    assert_eq!((b.width, b.height), (64, 32));
+    assert!(b.density > 0.5);
+    assert!((b.mean_disparity.unwrap() - 6.0).abs() < 0.5);
+
+    let map = read_pfm(output.join("scene/b.pfm"))?;
+    assert_eq!(map.width(), 64);
+    assert!(output.join("scene/b_preview.png").exists());
+    assert!(!output.join("d.pfm").exists());
+
+    // CSV has a header and a row per frame, JSON the same frames
+    let mut csv = Vec::new();
+    summary.write_csv_to(&mut csv)?;
+    let csv = String::from_utf8(csv)?;
+
+    assert_eq!(csv.lines().count(), 4);
+    assert!(csv.starts_with("name,width,height,runtime"));
+    assert!(csv.lines().nth(3).unwrap().starts_with("scene/b,64,32,"));
+
+    let mut json = Vec::new();
+    summary.write_json_to(&mut json)?;
+    let json: serde_json::Value = serde_json::from_slice(&json)?;
+
+    assert_eq!(json["frames"][2]["name"], "scene/b");
+    assert!(json["frames"][1]["error"].is_string());
+
+    std::fs::remove_dir_all(&root)?;
+
+    Ok(())
+}
+
+#[test]
+fn pattern_needs_placeholder() {
+    let params = Params {
+        left_pattern: "left.png".into(),
+        ..Default::default()
+    };
+
+    assert!(batch::find_pairs(std::env::temp_dir(), &params).is_err());
+}
+
+/// Write a random-dot pair with the given disparity.
+fn write_pair(dir: &Path, name: &str, disparity: u32) -> image::ImageResult<()> {
+    pattern(0).save(dir.join(format!("{}_left.png", name)))?;
+    pattern(disparity).save(dir.join(format!("{}_right.png", name)))
+}
+
+/// A random-dot image shifted left by the given number of pixels.
+fn pattern(shift: u32) -> GrayImage {
+    GrayImage::from_fn(64, 32, |x, y| {
+        let mut h = ((x + shift) as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ ((y as u64) << 32);
+        h ^= h >> 29;
+        h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        Luma([(h >> 56) as u8])
+    })
+}
@@ -0,0 +1,26 @@
+//! Test the hierarchical belief propagation algorithm.
+
+use cv_disparity::{
+    prelude::*,
+    belief_propagation::{BeliefPropagation, Params},
+    eval::{Metrics, Region},
+    synthetic::{self, Scene}
+};
+
+#[test]
+fn recovers_random_dot() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let map = BeliefPropagation::new(Params {
+        max_disparity: 16,
+        ..Default::default()
+    }).compute(&pair.frame)?;
+
+    // Every pixel is labelled, and the smoothness term carries the labels to the image border
+    let metrics = Metrics::compute(&map, &pair.truth, Region::NonOccluded)?;
+    assert_eq!(metrics.coverage, 1.0);
+    assert!(metrics.bad[1] < 15.0, "bad1 of {:.2}%", metrics.bad[1]);
+
+    Ok(())
+}
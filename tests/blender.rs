@@ -0,0 +1,48 @@
+//! Test the Blender render manifest and depth conversion.
+
+use cv_disparity::{
+    prelude::*,
+    datasets::blender::{Camera, DepthType, Manifest}
+};
+
+#[test]
+fn bundled_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = Manifest::load("res/renders/manifest.toml")?;
+
+    let names: Vec<&str> = manifest.scenes.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["simple_01", "simple_02", "simple_rocks_01"]);
+
+    let entry = manifest.entry("simple_rocks_01").ok_or("missing scene")?;
+    let scene = manifest.load_scene(entry)?;
+
+    assert!(scene.frame.width() > 0);
+    assert!(scene.truth.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn depth_to_disparity() {
+    // 100 pixels wide with a 36mm sensor and 36mm lens gives a focal length of 100 pixels
+    let camera = Camera {
+        baseline: 0.5,
+        focal_length: 36.0,
+        sensor_width: 36.0
+    };
+
+    let mut depth = DisparityMap::new(100, 1);
+    depth.put(10, 0, 10.0);
+    depth.put(20, 0, 1.0e10);
+
+    let disparity = camera.disparity(&depth, DepthType::Planar);
+
+    assert_eq!(camera.focal_pixels(100), 100.0);
+    assert_eq!(disparity.get(10, 0), 5.0);
+    assert!(!disparity.is_valid(20, 0));
+    assert!(!disparity.is_valid(30, 0));
+
+    // At the image edge the ray is longer than the planar depth, giving a larger disparity
+    let radial = camera.disparity(&depth, DepthType::Radial);
+
+    assert!(radial.get(10, 0) > 5.0);
+}
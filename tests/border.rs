@@ -0,0 +1,64 @@
+//! Test border handling modes.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, mcmanamon::{McManamon, Params}};
+use image;
+
+#[test]
+fn border_coords() {
+    assert_eq!(BorderMode::Invalid.map_coord(-1, 5), None);
+    assert_eq!(BorderMode::ShrinkWindow.map_coord(5, 5), None);
+    assert_eq!(BorderMode::Replicate.map_coord(-3, 5), Some(0));
+    assert_eq!(BorderMode::Replicate.map_coord(7, 5), Some(4));
+    assert_eq!(BorderMode::Reflect.map_coord(-2, 5), Some(2));
+    assert_eq!(BorderMode::Reflect.map_coord(6, 5), Some(2));
+    assert_eq!(BorderMode::Reflect.map_coord(3, 5), Some(3));
+}
+
+#[test]
+fn border_modes_fill_map() -> Result<(), Box<dyn std::error::Error>> {
+
+    // Load images
+    let left_img = image::open("res/renders/simple_02_left.png")?;
+    let right_img = image::open("res/renders/simple_02_right.png")?;
+
+    let frame = StereoFrame {
+        left: GrayFloatImage::from_dynamic(&left_img),
+        left_timestamp: 0,
+        right: GrayFloatImage::from_dynamic(&right_img),
+        right_timestamp: 0
+    };
+
+    for &mode in &[BorderMode::Replicate, BorderMode::Reflect, BorderMode::ShrinkWindow] {
+        let mut disp = McManamon::new(Params {
+            min_disparity: 0,
+            max_disparity: 32,
+            dyn_disparity_threshold: 10,
+            correlation_window_size: (7, 7),
+            border_mode: mode,
+            verify_criterion: false
+        });
+
+        let map = disp.compute(&frame)?;
+
+        assert_eq!(map.density(), 1.0, "{:?} left pixels unfilled", mode);
+    }
+
+    // The default mode leaves the border explicitly invalid
+    let mut disp = McManamon::new(Params {
+        min_disparity: 0,
+        max_disparity: 32,
+        dyn_disparity_threshold: 10,
+        correlation_window_size: (7, 7),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    });
+
+    let map = disp.compute(&frame)?;
+
+    assert!(!map.is_valid(0, 0));
+    assert!(!map.is_valid(map.width() - 1, map.height() - 1));
+    assert!(map.density() < 1.0);
+
+    Ok(())
+}
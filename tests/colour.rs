@@ -0,0 +1,40 @@
+//! Test colour stereo input.
+
+use cv_disparity::{prelude::*, cost::{ColourAbsDiff, MatchingCost}};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Image whose colour varies along x but whose channels sum to a constant.
+fn hue_ramp(width: u32, height: u32, shift: u32) -> DynamicImage {
+    let image = RgbImage::from_fn(width, height, |x, _| {
+        let v = (((x + shift) * 16) % 256) as u8;
+        Rgb([v, 255 - v, 128])
+    });
+
+    DynamicImage::ImageRgb8(image)
+}
+
+#[test]
+fn colour_frame_channels() -> Result<(), Box<dyn std::error::Error>> {
+    let left = hue_ramp(16, 4, 0);
+    let right = hue_ramp(16, 4, 2);
+
+    let frame = ColourStereoFrame::from_dynamic(&left, &right)?;
+
+    assert_eq!((frame.width(), frame.height()), (16, 4));
+    assert_eq!(frame.left.get(1, 0), [16.0 / 255.0, 239.0 / 255.0, 128.0 / 255.0]);
+
+    // Right pixel x matches left pixel x + 2
+    let cost = ColourAbsDiff::new(&frame, f32::INFINITY);
+    assert_eq!(cost.cost(5, 1, 2), 0.0);
+    assert!(cost.cost(5, 1, 1) > 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn colour_frame_size_mismatch() {
+    let left = hue_ramp(16, 4, 0);
+    let right = hue_ramp(12, 4, 0);
+
+    assert!(ColourStereoFrame::from_dynamic(&left, &right).is_err());
+}
@@ -0,0 +1,251 @@
+//! Test loading pipelines from config files and the algorithm registry.
+
+use cv_camstream::GrayFloatImage;
+use cv_disparity::{
+    prelude::*,
+    config::{Config, PostFilter, PreFilter},
+    eval::{Metrics, Region},
+    pipeline::{Gaussian, ImageFilter, Normalise},
+    registry::{self, ALGORITHMS},
+    synthetic::{self, Scene},
+    Error
+};
+
+const CONFIG: &str = r#"
+algorithm = "mcmanamon"
+left_right_threshold = 1.0
+
+[params]
+min_disparity = 0
+max_disparity = 16
+dyn_disparity_threshold = 2
+correlation_window_size = [7, 7]
+border_mode = "Invalid"
+
+[[pre]]
+filter = "normalise"
+
+[[post]]
+filter = "weighted_median"
+radius = 2
+"#;
+
+#[test]
+fn parse_config() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::parse(CONFIG)?;
+
+    assert_eq!(config.algorithm, "mcmanamon");
+    assert_eq!(config.left_right_threshold, Some(1.0));
+
+    match &config.pre[..] {
+        [PreFilter::Normalise(n)] => assert_eq!(n.mean, 0.5),
+        other => panic!("unexpected pre-filters {:?}", other)
+    }
+
+    match &config.post[..] {
+        [PostFilter::WeightedMedian(m)] => {
+            assert_eq!(m.radius, 2);
+            assert!(!m.fill_invalid);
+        },
+        other => panic!("unexpected post-filters {:?}", other)
+    }
+
+    config.build()?;
+
+    // Every post-filter can be named, including the guided filter
+    let guided = Config::parse("algorithm = \"ad_census\"\n[[post]]\nfilter = \"guided\"")?;
+    match &guided.post[..] {
+        [PostFilter::Guided(g)] => assert_eq!(g.radius, 4),
+        other => panic!("unexpected post-filters {:?}", other)
+    }
+
+    // The bundled config is valid too
+    Config::load("res/configs/mcmanamon.toml")?.build()?;
+
+    Ok(())
+}
+
+#[test]
+fn parse_json_config() -> Result<(), Box<dyn std::error::Error>> {
+    let json = r#"{
+        "algorithm": "mcmanamon",
+        "left_right_threshold": 1.0,
+        "params": {
+            "min_disparity": 0,
+            "max_disparity": 16,
+            "dyn_disparity_threshold": 2,
+            "correlation_window_size": [7, 7],
+            "border_mode": "Invalid"
+        },
+        "pre": [{ "filter": "normalise" }],
+        "post": [{ "filter": "weighted_median", "radius": 2 }]
+    }"#;
+
+    let config = Config::parse_json(json)?;
+    let toml = Config::parse(CONFIG)?;
+
+    assert_eq!(config.algorithm, toml.algorithm);
+    assert_eq!(config.left_right_threshold, toml.left_right_threshold);
+    assert_eq!(config.params, toml.params);
+
+    match (&config.pre[..], &config.post[..]) {
+        ([PreFilter::Normalise(_)], [PostFilter::WeightedMedian(m)]) => assert_eq!(m.radius, 2),
+        other => panic!("unexpected filters {:?}", other)
+    }
+
+    config.build()?;
+
+    // Loading picks the format from the extension
+    let path = std::env::temp_dir().join("cv_disparity_config_test.json");
+    std::fs::write(&path, json)?;
+    let loaded = Config::load(&path);
+    std::fs::remove_file(&path)?;
+    assert_eq!(loaded?.params, toml.params);
+
+    // Bad configs give the same error whatever their format
+    assert!(matches!(Config::parse_json("{ \"params\": {} }"), Err(Error::Format(_))));
+    assert!(matches!(Config::parse("params = {}"), Err(Error::Format(_))));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_configs() {
+    assert!(Config::parse("algorithm = \"magdeburg\"").unwrap().build().is_err());
+    assert!(Config::parse("algorithm = \"mcmanamon\"").unwrap().build().is_err());
+    assert!(Config::parse("params = {}").is_err());
+
+    let unknown_filter = "algorithm = \"ad_census\"\n[[post]]\nfilter = \"sharpen\"";
+    assert!(Config::parse(unknown_filter).is_err());
+}
+
+#[test]
+fn registry_defaults() -> Result<(), Box<dyn std::error::Error>> {
+    for name in ["ad_census", "belief_propagation", "cost_filter", "non_local", "patch_match"]
+        .iter()
+    {
+        assert!(ALGORITHMS.contains(name));
+        registry::build(name, None)?;
+    }
+
+    // Given fields override the defaults, the rest keep them
+    let params: toml::Value = "max_disparity = 32".parse()?;
+    registry::build("patch_match", Some(&params))?;
+
+    let wrong_type: toml::Value = "max_disparity = \"lots\"".parse()?;
+    assert!(registry::build("patch_match", Some(&wrong_type)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn image_filters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut image = GrayFloatImage::new(9, 9);
+    for y in 0..9 {
+        for x in 0..9 {
+            image.put(x, y, 0.4);
+        }
+    }
+    image.put(4, 4, 1.0);
+
+    // Blurring spreads the peak but leaves pixels out of its reach alone
+    let blurred = Gaussian { sigma: 1.0 }.filter(&image)?;
+    assert!(blurred.get(4, 4) < 1.0 && blurred.get(4, 4) > 0.4);
+    assert!(blurred.get(5, 4) > 0.4);
+    assert!((blurred.get(0, 0) - 0.4).abs() < 1e-6);
+
+    // Normalising a flat image only shifts it
+    let flat = Normalise::default().filter(&GrayFloatImage::new(4, 4))?;
+    assert_eq!(flat.get(2, 2), 0.5);
+
+    Ok(())
+}
+
+#[test]
+fn pipeline_right_map() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let mut config = Config::parse(CONFIG)?;
+    config.pre.clear();
+    config.post.clear();
+
+    // The right map only goes through the filters, so without any it matches the algorithm's own
+    // right map even though the pipeline checks its left map
+    let expected = registry::build(&config.algorithm, config.params.as_ref())?
+        .compute_right(&pair.frame)?;
+    let map = config.build()?.compute_right(&pair.frame)?;
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            assert_eq!(map.validity(x, y), expected.validity(x, y));
+            assert_eq!(map.get(x, y), expected.get(x, y));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn pipeline_checks_prior_maps() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+    let prior = DisparityPrior::new(params.width, params.height, 16, 0.0, f32::INFINITY);
+
+    let mut config = Config::parse(CONFIG)?;
+    config.pre.clear();
+    config.post.clear();
+
+    let checked = config.build()?.compute_with_prior(&pair.frame, &prior)?;
+
+    config.left_right_threshold = None;
+    let unchecked = config.build()?.compute_with_prior(&pair.frame, &prior)?;
+
+    // The check only removes pixels, and the square's occlusions always fail it
+    assert!(checked.density() < unchecked.density());
+
+    for y in 0..checked.height() {
+        for x in 0..checked.width() {
+            if checked.is_valid(x, y) {
+                assert!(unchecked.is_valid(x, y));
+                assert_eq!(checked.get(x, y), unchecked.get(x, y));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn pipeline_removes_camera_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    // The right camera squashes the contrast into [0.6, 0.9], which the absolute difference
+    // criterion cannot see through but normalising undoes
+    let params = synthetic::Params {
+        gain: 0.3,
+        bias: 0.6,
+        ..Default::default()
+    };
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let config = Config::parse(CONFIG)?;
+    let map = config.build()?.compute(&pair.frame)?;
+
+    assert_eq!((map.width(), map.height()), (params.width, params.height));
+
+    let normalised = Metrics::compute(&map, &pair.truth, Region::NonOccluded)?;
+    assert!(normalised.coverage > 0.5, "coverage of {}", normalised.coverage);
+    assert!(normalised.avg_error < 1.0, "average error of {}", normalised.avg_error);
+
+    // The same pipeline without the normalise pre-filter is thrown off by the mismatch
+    let mut unnormalised = config.clone();
+    unnormalised.pre.clear();
+    let raw_map = unnormalised.build()?.compute(&pair.frame)?;
+    let raw = Metrics::compute(&raw_map, &pair.truth, Region::NonOccluded)?;
+
+    assert!(
+        raw.bad[1] > normalised.bad[1],
+        "bad1 of {:.2}% unnormalised against {:.2}% normalised", raw.bad[1], normalised.bad[1]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Test left-right consistency checking.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, consistency::{compute_checked, left_right_check}};
+
+#[test]
+fn left_right_check_labels() {
+    let mut left = DisparityMap::new(8, 1);
+    let mut right = DisparityMap::new(8, 1);
+
+    // Consistent match between left 5 and right 3
+    left.put(5, 0, 2.0);
+    right.put(3, 0, 2.0);
+
+    // Inconsistent match between left 6 and right 4
+    left.put(6, 0, 2.0);
+    right.put(4, 0, 5.0);
+
+    // Match outside the right image
+    left.put(1, 0, 3.0);
+
+    let removed = left_right_check(&mut left, &right, 1.0);
+
+    assert_eq!(removed, 2);
+    assert_eq!(left.validity(5, 0), Validity::Valid);
+    assert_eq!(left.validity(6, 0), Validity::Invalid);
+    assert_eq!(left.validity(1, 0), Validity::Occluded);
+
+    // Removed pixels keep no trace of the rejected disparity
+    assert_eq!(left.get(6, 0), 0.0);
+    assert_eq!(left.get(1, 0), 0.0);
+}
+
+/// Per-pixel winner-take-all on absolute differences, which has no right-referenced support of
+/// its own.
+struct PixelWta;
+
+impl DisparityAlgorithm for PixelWta {
+    fn compute(&mut self, frame: &StereoFrame) -> cv_disparity::Result<DisparityMap> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+
+        let mut map = DisparityMap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let best = (0..8.min(x + 1))
+                    .map(|d| (d, (frame.left.get(x, y) - frame.right.get(x - d, y)).abs()))
+                    .fold((0, f32::INFINITY), |best, c| if c.1 < best.1 { c } else { best });
+
+                map.put(x, y, best.0 as f32);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[test]
+fn right_referenced_output() -> Result<(), Box<dyn std::error::Error>> {
+    let width = 32;
+
+    // The right image is the left shifted by 3 pixels, with a texture unique over 17 pixels
+    let mut left = GrayFloatImage::new(width, 1);
+    let mut right = GrayFloatImage::new(width, 1);
+
+    for x in 0..width {
+        left.put(x, 0, ((3 * x) % 17) as f32 / 17.0);
+        right.put(x, 0, ((3 * (x + 3)) % 17) as f32 / 17.0);
+    }
+
+    let frame = StereoFrame {
+        left,
+        left_timestamp: 0,
+        right,
+        right_timestamp: 0
+    };
+
+    let right_map = PixelWta.compute_referenced(&frame, Reference::Right)?;
+
+    for x in 0..width - 3 {
+        assert_eq!(right_map.get(x, 0), 3.0);
+    }
+
+    let checked = compute_checked(&mut PixelWta, &frame, 0.5)?;
+
+    for x in 3..width {
+        assert!(checked.is_valid(x, 0));
+    }
+
+    Ok(())
+}
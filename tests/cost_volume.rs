@@ -0,0 +1,81 @@
+//! Test cost volume inspection, export and winner-take-all.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, mcmanamon::{McManamon, Params}};
+use image;
+
+#[test]
+fn volume_curve_and_wta() {
+    let mut volume = CostVolume::new(2, 1, 4..8);
+
+    volume.set(0, 0, 5, 3.0);
+    volume.set(0, 0, 6, 1.0);
+    volume.set(0, 0, 7, 3.0);
+
+    assert_eq!(volume.num_disparities(), 4);
+    assert_eq!(volume.get(0, 0, 2), f32::INFINITY);
+    assert_eq!(volume.curve(0, 0), vec![(5, 3.0), (6, 1.0), (7, 3.0)]);
+    assert_eq!(volume.slice(6), vec![1.0, f32::INFINITY]);
+
+    let disp_map = volume.wta();
+
+    assert_eq!(disp_map.get(0, 0), 6.0);
+    assert!(!disp_map.is_valid(1, 0));
+}
+
+#[test]
+fn volume_npy_header() -> Result<(), Box<dyn std::error::Error>> {
+    let volume = CostVolume::filled(5, 3, 0..4, 1.0);
+
+    let mut bytes = Vec::new();
+    volume.write_npy(&mut bytes)?;
+
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let header = std::str::from_utf8(&bytes[10..10 + header_len])?;
+
+    assert_eq!(&bytes[..6], b"\x93NUMPY");
+    assert_eq!((10 + header_len) % 64, 0);
+    assert!(header.contains("'shape': (3, 5, 4)"));
+    assert_eq!(bytes.len(), 10 + header_len + 5 * 3 * 4 * 4);
+
+    Ok(())
+}
+
+#[test]
+fn mcmanamon_volume_matches_compute() -> Result<(), Box<dyn std::error::Error>> {
+
+    // Load images
+    let left_img = image::open("res/renders/simple_01_left.png")?;
+    let right_img = image::open("res/renders/simple_01_right.png")?;
+
+    let frame = StereoFrame {
+        left: GrayFloatImage::from_dynamic(&left_img),
+        left_timestamp: 0,
+        right: GrayFloatImage::from_dynamic(&right_img),
+        right_timestamp: 0
+    };
+
+    let mut disp = McManamon::new(Params {
+        min_disparity: 0,
+        max_disparity: 40,
+        dyn_disparity_threshold: 10,
+        correlation_window_size: (7, 7),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    });
+
+    let disp_map = disp.compute(&frame)?;
+    let from_volume = disp.cost_volume(&frame)?.wta();
+
+    for y in 0..disp_map.height() {
+        for x in 0..disp_map.width() {
+            assert_eq!(disp_map.is_valid(x, y), from_volume.is_valid(x, y));
+
+            if disp_map.is_valid(x, y) {
+                assert_eq!(disp_map.get(x, y), from_volume.get(x, y));
+            }
+        }
+    }
+
+    Ok(())
+}
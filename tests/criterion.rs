@@ -0,0 +1,92 @@
+//! Test the incrementally updated McManamon criterion against full evaluations of the window.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, mcmanamon::{McManamon, Params}};
+use proptest::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Frame of independent uniformly random images.
+fn random_frame(width: usize, height: usize, seed: u64) -> StereoFrame {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut left = GrayFloatImage::new(width, height);
+    let mut right = GrayFloatImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            left.put(x, y, rng.gen_range(0.0, 1.0));
+            right.put(x, y, rng.gen_range(0.0, 1.0));
+        }
+    }
+
+    StereoFrame {
+        left,
+        left_timestamp: 0,
+        right,
+        right_timestamp: 0
+    }
+}
+
+fn params(window: (usize, usize), max_disparity: usize, verify_criterion: bool) -> Params {
+    Params {
+        min_disparity: 0,
+        max_disparity,
+        dyn_disparity_threshold: 2,
+        correlation_window_size: window,
+        border_mode: BorderMode::Invalid,
+        verify_criterion
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn fast_criterion_matches_full(
+        seed in any::<u64>(),
+        width in 48usize..80,
+        height in 24usize..48,
+        half_window in (1usize..5, 1usize..5),
+        max_disparity in 2usize..12
+    ) {
+        let frame = random_frame(width, height, seed);
+        let window = (2 * half_window.0 + 1, 2 * half_window.1 + 1);
+
+        let mut disp = McManamon::new(params(window, max_disparity, true));
+        disp.compute(&frame).unwrap();
+
+        let drift = disp.criterion_drift().unwrap();
+        let area = (window.0 * window.1) as f32;
+
+        prop_assert!(drift.checked > 0);
+        prop_assert!(
+            drift.max_abs <= 1e-4 * area,
+            "criterion drifted by {} at {:?}", drift.max_abs, drift.worst
+        );
+    }
+
+    #[test]
+    fn verification_does_not_change_output(seed in any::<u64>()) {
+        let frame = random_frame(64, 32, seed);
+
+        let plain = McManamon::new(params((5, 5), 10, false)).compute(&frame).unwrap();
+        let verified = McManamon::new(params((5, 5), 10, true)).compute(&frame).unwrap();
+
+        for y in 0..plain.height() {
+            for x in 0..plain.width() {
+                prop_assert_eq!(plain.validity(x, y), verified.validity(x, y));
+                prop_assert_eq!(plain.get(x, y), verified.get(x, y));
+            }
+        }
+    }
+}
+
+#[test]
+fn no_drift_without_verification() -> Result<(), Box<dyn std::error::Error>> {
+    let mut disp = McManamon::new(params((5, 5), 10, false));
+    disp.compute(&random_frame(64, 32, 0))?;
+
+    assert!(disp.criterion_drift().is_none());
+
+    Ok(())
+}
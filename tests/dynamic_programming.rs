@@ -0,0 +1,66 @@
+//! Test the scanline dynamic programming algorithm.
+
+use cv_disparity::{
+    prelude::*,
+    dynamic_programming::{DynamicProgramming, Params},
+    eval::{Metrics, Region},
+    synthetic::{self, Scene}
+};
+
+#[test]
+fn labels_occlusions() -> Result<(), Box<dyn std::error::Error>> {
+    // The box hides the background columns 41 to 47 of the left image from the right camera
+    let scene = Scene::boxes(3.0, &[(Rect::new(48, 24, 32, 48), 10.0)]);
+    let pair = scene.generate(&synthetic::Params::default())?;
+
+    let map = DynamicProgramming::new(Params {
+        min_disparity: 0,
+        max_disparity: 16,
+        window_size: 3,
+        occlusion_cost: 0.2,
+        cost: Default::default()
+    }).compute(&pair.frame)?;
+
+    // Compare the occlusion labels with the truth's mask, away from the left border where every
+    // pixel is hidden
+    let mut occluded = 0;
+    let mut occluded_labelled = 0;
+    let mut visible = 0;
+    let mut visible_labelled = 0;
+
+    for y in 0..map.height() {
+        for x in 16..map.width() {
+            let labelled = map.validity(x, y) == Validity::Occluded;
+
+            match pair.truth.in_region(x, y, Region::NonOccluded) {
+                true => {
+                    visible += 1;
+                    visible_labelled += labelled as usize;
+                },
+                false => {
+                    occluded += 1;
+                    occluded_labelled += labelled as usize;
+                }
+            }
+        }
+    }
+
+    let occluded_recall = occluded_labelled as f32 / occluded as f32;
+    let false_occlusions = visible_labelled as f32 / visible as f32;
+
+    assert!(occluded > 0);
+    assert!(
+        occluded_recall > 0.75,
+        "only {:.1}% of occluded pixels are labelled", occluded_recall * 100.0
+    );
+    assert!(
+        false_occlusions < 0.03,
+        "{:.1}% of visible pixels are labelled occluded", false_occlusions * 100.0
+    );
+
+    // The visible pixels are matched to the right integer disparity
+    let metrics = Metrics::compute(&map, &pair.truth, Region::NonOccluded)?;
+    assert!(metrics.bad[1] < 10.0, "bad1 of {:.2}%", metrics.bad[1]);
+
+    Ok(())
+}
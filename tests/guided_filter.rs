@@ -0,0 +1,42 @@
+//! Test the guided filter.
+
+use cv_camstream::GrayFloatImage;
+use cv_disparity::guided_filter::GuidedFilter;
+
+/// Image with a vertical step edge halfway across.
+fn step_image(width: usize, height: usize) -> GrayFloatImage {
+    let mut image = GrayFloatImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            image.put(x, y, if x < width / 2 { 0.2 } else { 0.8 });
+        }
+    }
+
+    image
+}
+
+#[test]
+fn constant_input_unchanged() {
+    let filter = GuidedFilter::new(&step_image(32, 16), 4, 0.0001);
+
+    let output = filter.filter(&vec![0.5; 32 * 16]);
+
+    for v in output {
+        assert!((v - 0.5).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn guide_edges_preserved() {
+    let guide = step_image(32, 16);
+    let filter = GuidedFilter::new(&guide, 4, 0.0001);
+
+    let output = filter.filter_image(&guide);
+
+    // Filtering the guide with itself keeps the step sharp
+    for y in 0..16 {
+        assert!((output.get(15, y) - 0.2).abs() < 0.01);
+        assert!((output.get(16, y) - 0.8).abs() < 0.01);
+    }
+}
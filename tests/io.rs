@@ -0,0 +1,55 @@
+//! Test reading and writing disparity maps and point clouds.
+
+use cv_camstream::GrayFloatImage;
+use cv_disparity::{
+    prelude::*,
+    io::{write_ply_to, StereoGeometry}
+};
+
+#[test]
+fn reprojection() {
+    let geometry = StereoGeometry {
+        focal: 100.0,
+        baseline: 0.5,
+        principal_point: (10.0, 5.0),
+        doffs: 0.0
+    };
+
+    // 100 px * 0.5 / 10 px gives a depth of 5, and 10 px right of centre is 0.5 across
+    let p = geometry.reproject(20, 5, 10.0).unwrap();
+
+    assert!((p[0] - 0.5).abs() < 1e-6);
+    assert!(p[1].abs() < 1e-6);
+    assert!((p[2] - 5.0).abs() < 1e-6);
+
+    assert!(geometry.reproject(20, 5, 0.0).is_none());
+}
+
+#[test]
+fn ply_skips_invalid_pixels() -> Result<(), Box<dyn std::error::Error>> {
+    let geometry = StereoGeometry {
+        focal: 100.0,
+        baseline: 0.5,
+        principal_point: (1.0, 1.0),
+        doffs: 0.0
+    };
+
+    let mut map = DisparityMap::new(3, 3);
+    map.put(0, 0, 10.0);
+    map.put(2, 2, 20.0);
+    map.update_range();
+
+    let mut image = GrayFloatImage::new(3, 3);
+    image.put(2, 2, 1.0);
+
+    let mut ply = Vec::new();
+    write_ply_to(&map, Some(&image), &geometry, &mut ply)?;
+    let ply = String::from_utf8(ply)?;
+
+    assert!(ply.starts_with("ply\n"));
+    assert!(ply.contains("element vertex 2\n"));
+    assert!(ply.contains("property uchar red\n"));
+    assert_eq!(ply.lines().last(), Some("0.025 0.025 2.5 255 255 255"));
+
+    Ok(())
+}
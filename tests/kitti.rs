@@ -0,0 +1,115 @@
+//! Test the KITTI frame loader and outlier measures.
+
+use std::fs;
+
+use cv_camstream::StereoFrame;
+use cv_disparity::{
+    prelude::*,
+    datasets::kitti::{self, Calibration, Dataset, Layout},
+    eval::{GroundTruth, Region}
+};
+
+const CALIB_2015: &str = "calib_time: 09-Jan-2012 13:57:47
+P_rect_02: 7.215377e+02 0.000000e+00 6.095593e+02 4.485728e+01 0.000000e+00 7.215377e+02 \
+1.728540e+02 2.163791e-01 0.000000e+00 0.000000e+00 1.000000e+00 2.745884e-03
+P_rect_03: 7.215377e+02 0.000000e+00 6.095593e+02 -3.395242e+02 0.000000e+00 7.215377e+02 \
+1.728540e+02 2.199936e+00 0.000000e+00 0.000000e+00 1.000000e+00 2.729905e-03
+";
+
+/// Algorithm returning the same disparity everywhere.
+struct Constant(f32);
+
+impl DisparityAlgorithm for Constant {
+    fn compute(&mut self, frame: &StereoFrame) -> cv_disparity::Result<DisparityMap> {
+        let mut map = DisparityMap::new(frame.width() as usize, frame.height() as usize);
+
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                map.put(x, y, self.0);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[test]
+fn d1_outliers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut truth = DisparityMap::new(4, 1);
+    let mut estimate = DisparityMap::new(4, 1);
+
+    truth.put(0, 0, 10.0);
+    truth.put(1, 0, 100.0);
+    truth.put(2, 0, 10.0);
+    truth.put(3, 0, 10.0);
+
+    // An error of 4 is an outlier at 10 but within 5% at 100, and the last pixel is missing
+    estimate.put(0, 0, 14.0);
+    estimate.put(1, 0, 104.0);
+    estimate.put(2, 0, 10.5);
+
+    let metrics = kitti::Metrics::compute(&estimate, &GroundTruth::new(truth), Region::All)?;
+
+    assert_eq!(metrics.evaluated, 4);
+    assert_eq!(metrics.d1, 50.0);
+    assert_eq!(metrics.outliers, [75.0, 75.0, 25.0, 25.0]);
+
+    Ok(())
+}
+
+#[test]
+fn parse_calibration() -> Result<(), Box<dyn std::error::Error>> {
+    let calib = Calibration::parse(CALIB_2015, Layout::Kitti2015)?;
+
+    assert_eq!(calib.focal, 721.5377);
+    assert!((calib.baseline - 0.5327).abs() < 1e-3);
+
+    Ok(())
+}
+
+#[test]
+fn run_dataset() -> Result<(), Box<dyn std::error::Error>> {
+    let root = std::env::temp_dir().join("cv_disparity_kitti_test");
+    let _ = fs::remove_dir_all(&root);
+
+    for dir in &["image_2", "image_3", "disp_occ_0", "disp_noc_0", "calib_cam_to_cam"] {
+        fs::create_dir_all(root.join(dir))?;
+    }
+
+    image::RgbImage::new(8, 2).save(root.join("image_2/000000_10.png"))?;
+    image::RgbImage::new(8, 2).save(root.join("image_3/000000_10.png"))?;
+    fs::write(root.join("calib_cam_to_cam/000000.txt"), CALIB_2015)?;
+
+    // The right half is occluded, with a disparity of 20
+    let mut occ = DisparityMap::new(8, 2);
+    let mut noc = DisparityMap::new(8, 2);
+    for y in 0..2 {
+        for x in 0..8 {
+            occ.put(x, y, if x < 4 { 2.5 } else { 20.0 });
+
+            if x < 4 {
+                noc.put(x, y, 2.5);
+            }
+        }
+    }
+    kitti::write_disparity(&occ, root.join("disp_occ_0/000000_10.png"))?;
+    kitti::write_disparity(&noc, root.join("disp_noc_0/000000_10.png"))?;
+
+    let dataset = Dataset::open(&root)?;
+    assert_eq!(dataset.layout(), Layout::Kitti2015);
+    assert_eq!(dataset.frame_ids()?, vec!["000000".to_string()]);
+
+    let truth = dataset.load("000000")?.truth.ok_or("no ground truth")?;
+    assert_eq!(truth.disparity.get(5, 1), 20.0);
+    assert!(!truth.in_region(5, 1, Region::NonOccluded));
+
+    let noc_report = kitti::run(&mut Constant(2.5), &root, Region::NonOccluded)?;
+    assert_eq!(noc_report.overall().ok_or("no metrics")?.d1, 0.0);
+
+    let all_report = kitti::run(&mut Constant(2.5), &root, Region::All)?;
+    assert_eq!(all_report.overall().ok_or("no metrics")?.d1, 50.0);
+
+    fs::remove_dir_all(&root)?;
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Test PFM files, ground truth evaluation and the Middlebury scene loader.
+
+use std::fs;
+
+use cv_camstream::StereoFrame;
+use cv_disparity::{
+    prelude::*,
+    datasets::middlebury::{self, Calibration},
+    eval::{GroundTruth, Metrics, Region},
+    io::{read_pfm, read_pfm_from, write_pfm, write_pfm_to}
+};
+
+const CALIB: &str = "cam0=[100.5 0 20; 0 100.5 10; 0 0 1]
+cam1=[100.5 0 22; 0 100.5 10; 0 0 1]
+doffs=2
+baseline=150.0
+width=8
+height=4
+ndisp=16
+isint=0
+vmin=1
+vmax=6
+";
+
+/// Algorithm returning the same disparity everywhere.
+struct Constant(f32);
+
+impl DisparityAlgorithm for Constant {
+    fn compute(&mut self, frame: &StereoFrame) -> cv_disparity::Result<DisparityMap> {
+        let mut map = DisparityMap::new(frame.width() as usize, frame.height() as usize);
+
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                map.put(x, y, self.0);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[test]
+fn pfm_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let mut map = DisparityMap::new(3, 2);
+    map.put(0, 0, 1.5);
+    map.put(2, 1, -4.0);
+
+    let mut bytes = Vec::new();
+    write_pfm_to(&map, &mut bytes)?;
+
+    assert!(bytes.starts_with(b"Pf\n3 2\n-1.0\n"));
+
+    let read = read_pfm_from(&mut bytes.as_slice())?;
+
+    assert_eq!((read.width(), read.height()), (3, 2));
+    assert_eq!(read.get(0, 0), 1.5);
+    assert_eq!(read.get(2, 1), -4.0);
+    assert!(!read.is_valid(1, 0));
+
+    Ok(())
+}
+
+#[test]
+fn bad_pixel_metrics() -> Result<(), Box<dyn std::error::Error>> {
+    let mut truth = DisparityMap::new(4, 1);
+    let mut estimate = DisparityMap::new(4, 1);
+
+    for x in 0..4 {
+        truth.put(x, 0, 10.0);
+    }
+
+    // Errors of 0, 0.75 and 3, with the last pixel missing
+    estimate.put(0, 0, 10.0);
+    estimate.put(1, 0, 10.75);
+    estimate.put(2, 0, 7.0);
+
+    let mut truth = GroundTruth::new(truth);
+    truth.non_occluded = Some(vec![true, true, true, false]);
+
+    let all = Metrics::compute(&estimate, &truth, Region::All)?;
+
+    assert_eq!(all.evaluated, 4);
+    assert_eq!(all.coverage, 0.75);
+    assert_eq!(all.bad, [75.0, 50.0, 50.0, 25.0]);
+    assert!((all.avg_error - 1.25).abs() < 1e-6);
+
+    let non_occluded = Metrics::compute(&estimate, &truth, Region::NonOccluded)?;
+
+    assert_eq!(non_occluded.evaluated, 3);
+    assert_eq!(non_occluded.coverage, 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn parse_calibration() -> Result<(), Box<dyn std::error::Error>> {
+    let calib = Calibration::parse(CALIB)?;
+
+    assert_eq!(calib.focal, 100.5);
+    assert_eq!(calib.doffs, 2.0);
+    assert_eq!(calib.baseline, 150.0);
+    assert_eq!((calib.width, calib.height, calib.ndisp), (8, 4, 16));
+    assert_eq!(calib.vmax, Some(6.0));
+
+    Ok(())
+}
+
+#[test]
+fn run_scene_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let root = std::env::temp_dir().join("cv_disparity_middlebury_test");
+    let scene_dir = root.join("scene");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&scene_dir)?;
+
+    image::GrayImage::new(8, 4).save(scene_dir.join("im0.png"))?;
+    image::GrayImage::new(8, 4).save(scene_dir.join("im1.png"))?;
+    fs::write(scene_dir.join("calib.txt"), CALIB)?;
+
+    let mut truth = DisparityMap::new(8, 4);
+    for y in 0..4 {
+        for x in 0..8 {
+            truth.put(x, y, if x < 4 { 3.0 } else { 5.0 });
+        }
+    }
+    write_pfm(&truth, scene_dir.join("disp0.pfm"))?;
+
+    assert_eq!(read_pfm(scene_dir.join("disp0.pfm"))?.get(6, 2), 5.0);
+
+    let report = middlebury::run(&mut Constant(3.0), &root, Region::All)?;
+
+    assert_eq!(report.scenes.len(), 1);
+    assert_eq!(report.scenes[0].name, "scene");
+
+    let mean = report.mean().ok_or("no metrics")?;
+    assert_eq!(mean.bad[1], 50.0);
+    assert_eq!(mean.avg_error, 1.0);
+
+    fs::remove_dir_all(&root)?;
+
+    Ok(())
+}
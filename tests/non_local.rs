@@ -0,0 +1,119 @@
+//! Test the non-local cost aggregation algorithm.
+
+use cv_camstream::StereoFrame;
+use cv_disparity::{
+    prelude::*,
+    consistency,
+    eval::{Metrics, Region},
+    non_local::{NonLocal, Params},
+    synthetic::{self, Scene}
+};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 48;
+
+/// Column of the left image the foreground starts at.
+const STEP: usize = 48;
+
+const BACK: usize = 4;
+const FRONT: usize = 10;
+
+#[test]
+fn recovers_random_dot() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let map = NonLocal::new(Params {
+        max_disparity: 16,
+        refine: false,
+        ..Default::default()
+    }).compute(&pair.frame)?;
+
+    let metrics = Metrics::compute(&map, &pair.truth, Region::NonOccluded)?;
+    assert_eq!(metrics.coverage, 1.0);
+    assert!(metrics.bad[1] < 10.0, "bad1 of {:.2}%", metrics.bad[1]);
+
+    Ok(())
+}
+
+#[test]
+fn refinement_fills_inconsistent_pixels() -> Result<(), Box<dyn std::error::Error>> {
+    let frame = two_tone_step()?;
+    let params = Params {
+        max_disparity: 16,
+        ..Default::default()
+    };
+
+    // Find the pixels which fail the left-right check without refinement, which include the
+    // background hidden by the step
+    let mut unrefined = NonLocal::new(Params {
+        refine: false,
+        ..params.clone()
+    });
+    let mut checked = unrefined.compute(&frame)?;
+    let right = unrefined.compute_right(&frame)?;
+    consistency::left_right_check(&mut checked, &right, params.lr_threshold);
+
+    let refined = NonLocal::new(params).compute(&frame)?;
+
+    let mut failed = 0;
+    let mut filled = 0;
+
+    for y in 8..(HEIGHT - 8) {
+        for x in 16..WIDTH {
+            if checked.is_valid(x, y) {
+                continue;
+            }
+
+            failed += 1;
+
+            let truth = match x < STEP {
+                true => BACK,
+                false => FRONT
+            };
+
+            if refined.is_valid(x, y) && (refined.get(x, y) - truth as f32).abs() <= 1.0 {
+                filled += 1;
+            }
+        }
+    }
+
+    // The hidden background alone is FRONT - BACK columns wide
+    assert!(failed >= (FRONT - BACK) * (HEIGHT - 16), "only {} pixels fail the check", failed);
+
+    let rate = filled as f32 / failed as f32;
+    assert!(rate > 0.8, "only {:.1}% of inconsistent pixels are filled", rate * 100.0);
+
+    Ok(())
+}
+
+/// A step from a dark background to a bright foreground, with the synthetic texture of each
+/// surface squeezed into its own intensity band, so that the tree joins the hidden background to
+/// the visible background.
+fn two_tone_step() -> cv_disparity::Result<StereoFrame> {
+    let params = synthetic::Params {
+        width: WIDTH,
+        height: HEIGHT,
+        ..Default::default()
+    };
+    let mut frame = Scene::step(WIDTH, HEIGHT, STEP, BACK as f32, FRONT as f32)
+        .generate(&params)?
+        .frame;
+
+    let tone = |value: f32, front: bool| match front {
+        true => 0.7 + 0.2 * value,
+        false => 0.1 + 0.2 * value
+    };
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let left = tone(frame.left.get(x, y), x >= STEP);
+            frame.left.put(x, y, left);
+
+            let right = tone(frame.right.get(x, y), x + FRONT >= STEP && x + FRONT < WIDTH);
+            frame.right.put(x, y, right);
+        }
+    }
+
+    Ok(frame)
+}
@@ -0,0 +1,44 @@
+//! Test the PatchMatch algorithm on a slanted plane.
+
+use cv_disparity::{
+    prelude::*,
+    eval::{Metrics, Region},
+    patch_match::{Params, PatchMatch},
+    synthetic::{self, Plane, Scene}
+};
+
+#[test]
+fn seeded_slanted_plane() -> Result<(), Box<dyn std::error::Error>> {
+    let plane = Plane {
+        disparity: 4.0,
+        dx: 0.05,
+        dy: 0.02
+    };
+    let pair = Scene::slanted(plane).generate(&synthetic::Params::default())?;
+
+    let params = Params {
+        max_disparity: 16,
+        window_size: 15,
+        seed: Some(7),
+        ..Default::default()
+    };
+
+    let first = PatchMatch::new(params.clone()).compute(&pair.frame)?;
+    let second = PatchMatch::new(params).compute(&pair.frame)?;
+
+    // The same seed must give the same map
+    for y in 0..first.height() {
+        for x in 0..first.width() {
+            assert_eq!(first.validity(x, y), second.validity(x, y), "validity at ({}, {})", x, y);
+            assert_eq!(first.get(x, y), second.get(x, y), "disparity at ({}, {})", x, y);
+        }
+    }
+
+    // Slanted support windows should recover the plane to well under a pixel
+    let metrics = Metrics::compute(&first, &pair.truth, Region::NonOccluded)?;
+    assert!(metrics.coverage > 0.95, "coverage of {}", metrics.coverage);
+    assert!(metrics.bad[1] < 5.0, "bad1 of {:.2}%", metrics.bad[1]);
+    assert!(metrics.avg_error < 0.3, "average error of {} px", metrics.avg_error);
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Test disparity search range priors.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, mcmanamon::{McManamon, Params}};
+use image;
+
+#[test]
+fn prior_search_range() {
+    let mut prior = DisparityPrior::new(64, 48, 16, 0.0, f32::INFINITY);
+    prior.set_tile(1, 0, 10.2, 19.7);
+
+    assert_eq!(prior.grid_size(), (4, 3));
+    assert_eq!(prior.search_range(5, 5, 0..100), 0..100);
+    assert_eq!(prior.search_range(20, 5, 0..100), 10..21);
+    assert_eq!(prior.search_range(20, 5, 15..18), 15..18);
+    assert_eq!(prior.search_range(20, 5, 30..40).len(), 0);
+}
+
+#[test]
+fn prior_from_images() {
+    let min = GrayFloatImage::new(8, 4);
+    let mut max = GrayFloatImage::new(8, 4);
+    max.put(3, 2, 12.0);
+
+    let prior = DisparityPrior::from_images(&min, &max).unwrap();
+    assert_eq!(prior.search_range(3, 2, 0..100), 0..13);
+
+    let result = DisparityPrior::from_images(&min, &GrayFloatImage::new(8, 5));
+    assert!(matches!(result, Err(cv_disparity::Error::InvalidParams(_))));
+}
+
+#[test]
+fn unbounded_prior_matches_compute() -> Result<(), Box<dyn std::error::Error>> {
+
+    // Load images
+    let left_img = image::open("res/renders/simple_01_left.png")?;
+    let right_img = image::open("res/renders/simple_01_right.png")?;
+
+    let frame = StereoFrame {
+        left: GrayFloatImage::from_dynamic(&left_img),
+        left_timestamp: 0,
+        right: GrayFloatImage::from_dynamic(&right_img),
+        right_timestamp: 0
+    };
+
+    let mut disp = McManamon::new(Params {
+        min_disparity: 0,
+        max_disparity: 40,
+        dyn_disparity_threshold: 10,
+        correlation_window_size: (7, 7),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    });
+
+    let prior = DisparityPrior::unbounded(
+        frame.width() as usize,
+        frame.height() as usize,
+        8
+    );
+
+    let plain = disp.compute(&frame)?;
+    let with_prior = disp.compute_with_prior(&frame, &prior)?;
+
+    for y in 0..plain.height() {
+        for x in 0..plain.width() {
+            assert_eq!(plain.get(x, y), with_prior.get(x, y));
+        }
+    }
+
+    Ok(())
+}
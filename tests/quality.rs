@@ -0,0 +1,105 @@
+//! Test unsupervised quality metrics.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, quality::{self, QualityParams, QualityReport}};
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 8;
+
+/// Texture value at column `x`.
+fn texture(x: usize) -> f32 {
+    ((3 * x) % 17) as f32 / 17.0
+}
+
+/// Frame whose true disparity is 3 everywhere.
+fn test_frame() -> StereoFrame {
+    let mut left = GrayFloatImage::new(WIDTH, HEIGHT);
+    let mut right = GrayFloatImage::new(WIDTH, HEIGHT);
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            left.put(x, y, texture(x));
+            right.put(x, y, texture(x + 3));
+        }
+    }
+
+    StereoFrame {
+        left,
+        left_timestamp: 0,
+        right,
+        right_timestamp: 0
+    }
+}
+
+fn constant_map(d: f32) -> DisparityMap {
+    let mut map = DisparityMap::new(WIDTH, HEIGHT);
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            map.put(x, y, d);
+        }
+    }
+
+    map
+}
+
+#[test]
+fn correct_map_scores_well() -> Result<(), Box<dyn std::error::Error>> {
+    let frame = test_frame();
+    let report = QualityReport::compute(
+        &frame, &constant_map(3.0), Some(&constant_map(3.0)), &QualityParams::default()
+    )?;
+
+    assert!(report.photometric_error < 1e-6);
+    assert_eq!(report.lr_consistency, Some(1.0));
+    assert_eq!(report.density, 1.0);
+    assert_eq!(report.smoothness, 0.0);
+    assert_eq!(report.edge_alignment, 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn wrong_map_scores_worse() -> Result<(), Box<dyn std::error::Error>> {
+    let frame = test_frame();
+    let params = QualityParams::default();
+
+    let correct = QualityReport::compute(&frame, &constant_map(3.0), None, &params)?;
+
+    let mut wrong_map = constant_map(5.0);
+    wrong_map.invalidate(0, 0);
+    let wrong = QualityReport::compute(&frame, &wrong_map, Some(&constant_map(3.0)), &params)?;
+
+    assert!(wrong.photometric_error > correct.photometric_error);
+    assert_eq!(wrong.lr_consistency, Some(0.0));
+    assert!(wrong.density < 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn edge_alignment() {
+    let params = QualityParams::default();
+
+    // The image steps from dark to bright at column 16
+    let mut image = GrayFloatImage::new(WIDTH, HEIGHT);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            image.put(x, y, if x < 16 { 0.2 } else { 0.8 });
+        }
+    }
+
+    let step_map = |column: usize| {
+        let mut map = constant_map(2.0);
+        for y in 0..HEIGHT {
+            for x in column..WIDTH {
+                map.put(x, y, 6.0);
+            }
+        }
+        map
+    };
+
+    assert_eq!(quality::edge_alignment(&image, &step_map(16), &params), 1.0);
+    assert_eq!(quality::edge_alignment(&image, &step_map(8), &params), 0.0);
+    assert_eq!(quality::edge_alignment(&image, &constant_map(3.0), &params), 1.0);
+}
@@ -0,0 +1,135 @@
+//! Test disparity refinement filters.
+
+use cv_camstream::GrayFloatImage;
+use cv_disparity::{
+    prelude::*,
+    refine::{DisparityFilter, FastGlobalSmoother, Guided, JointBilateral, WeightedMedian}
+};
+
+/// Flat disparity map with an outlier at (8, 8) and a hole at (4, 4).
+fn test_map() -> DisparityMap {
+    let mut map = DisparityMap::new(16, 16);
+
+    for y in 0..16 {
+        for x in 0..16 {
+            map.put(x, y, 10.0);
+        }
+    }
+
+    map.put(8, 8, 30.0);
+    map.invalidate(4, 4);
+
+    map
+}
+
+/// Guide with a vertical step edge at column 16, and a map stepping from 4 to 12 along it with
+/// a checkerboard of noise and an outlier on each side.
+fn step_edge() -> (DisparityMap, GrayFloatImage) {
+    let mut map = DisparityMap::new(32, 16);
+    let mut guide = GrayFloatImage::new(32, 16);
+
+    for y in 0..16 {
+        for x in 0..32 {
+            let noise = match (x + y) % 2 {
+                0 => 0.5,
+                _ => -0.5
+            };
+
+            match x < 16 {
+                true => {
+                    guide.put(x, y, 0.2);
+                    map.put(x, y, 4.0 + noise);
+                },
+                false => {
+                    guide.put(x, y, 0.8);
+                    map.put(x, y, 12.0 + noise);
+                }
+            }
+        }
+    }
+
+    map.put(8, 4, 30.0);
+    map.put(24, 4, 0.0);
+
+    (map, guide)
+}
+
+#[test]
+fn filters_respect_guide_edges() -> Result<(), Box<dyn std::error::Error>> {
+    let (map, guide) = step_edge();
+
+    let filters: [(&str, Box<dyn DisparityFilter>); 4] = [
+        ("joint bilateral", Box::new(JointBilateral::default())),
+        ("weighted median", Box::new(WeightedMedian::default())),
+        ("fast global smoother", Box::new(FastGlobalSmoother::default())),
+        ("guided", Box::new(Guided::default()))
+    ];
+
+    for (name, filter) in filters.iter() {
+        let output = filter.filter(&map, &guide)?;
+
+        // Neither side bleeds across the edge, where a plain box filter would meet in the middle
+        for y in 0..16 {
+            assert!((output.get(15, y) - 4.0).abs() <= 0.6, "{} at (15, {})", name, y);
+            assert!((output.get(16, y) - 12.0).abs() <= 0.6, "{} at (16, {})", name, y);
+        }
+
+        // The outliers are pulled back towards their side
+        assert!((output.get(8, 4) - 4.0).abs() <= 1.0, "{} gives {}", name, output.get(8, 4));
+        assert!((output.get(24, 4) - 12.0).abs() <= 1.0, "{} gives {}", name, output.get(24, 4));
+    }
+
+    // The averaging filters also smooth out the noise within each side, which the median cannot
+    // since its output is always one of the samples
+    for (name, filter) in filters.iter().filter(|f| f.0 != "weighted median") {
+        let output = filter.filter(&map, &guide)?;
+
+        assert!((output.get(4, 12) - 4.0).abs() < 0.25, "{} gives {}", name, output.get(4, 12));
+        assert!((output.get(27, 12) - 12.0).abs() < 0.25, "{} gives {}", name, output.get(27, 12));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn weighted_median_removes_outlier() -> Result<(), Box<dyn std::error::Error>> {
+    let guide = GrayFloatImage::new(16, 16);
+
+    let output = WeightedMedian::default().filter(&test_map(), &guide)?;
+
+    assert_eq!(output.get(8, 8), 10.0);
+    assert!(!output.is_valid(4, 4));
+
+    Ok(())
+}
+
+#[test]
+fn filters_fill_invalid() -> Result<(), Box<dyn std::error::Error>> {
+    let guide = GrayFloatImage::new(16, 16);
+
+    let bilateral = JointBilateral {
+        fill_invalid: true,
+        ..Default::default()
+    };
+    let smoother = FastGlobalSmoother {
+        fill_invalid: true,
+        ..Default::default()
+    };
+    let guided = Guided {
+        fill_invalid: true,
+        ..Default::default()
+    };
+
+    let outputs = [
+        bilateral.filter(&test_map(), &guide)?,
+        smoother.filter(&test_map(), &guide)?,
+        guided.filter(&test_map(), &guide)?
+    ];
+
+    for output in outputs.iter() {
+        assert!(output.is_valid(4, 4));
+        assert!((output.get(4, 4) - 10.0).abs() < 1.0);
+    }
+
+    Ok(())
+}
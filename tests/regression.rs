@@ -0,0 +1,190 @@
+//! Headless regression tests against golden disparity maps.
+//!
+//! Every scene in `res/renders/manifest.toml` and a set of synthetic scenes are computed with
+//! each algorithm under test and compared against golden float maps in `res/golden`. Scenes with
+//! ground truth must also stay within metric thresholds.
+//!
+//! To regenerate the goldens after an intended change in output, run:
+//!
+//! ```text
+//! CV_DISPARITY_REGEN_GOLDEN=1 cargo test --release --test regression
+//! ```
+//!
+//! A missing golden fails the test, so that a golden which was never committed cannot pass
+//! silently. New scenes and algorithms are added by running once with the flag and committing
+//! the goldens it writes.
+
+use std::path::{Path, PathBuf};
+
+use cv_camstream::StereoFrame;
+use cv_disparity::{
+    prelude::*,
+    config::Config,
+    datasets::blender::Manifest,
+    eval::{GroundTruth, Metrics, Region},
+    io::{read_pfm, write_pfm},
+    mcmanamon::{self, McManamon},
+    synthetic::{self, Scene}
+};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Environment variable which, when set, regenerates the goldens instead of checking them.
+const REGEN_VAR: &str = "CV_DISPARITY_REGEN_GOLDEN";
+
+/// Directory the goldens are stored in.
+const GOLDEN_DIR: &str = "res/golden";
+
+/// Largest difference between a pixel and its golden value.
+const PIXEL_TOLERANCE: f32 = 1e-3;
+
+/// Largest fraction of pixels which may differ from the golden map, by value or validity, to
+/// allow for floating point differences between platforms.
+const MAX_DIFFERING: f32 = 1e-3;
+
+/// Largest percentage of non-occluded pixels with an error over 2 pixels, for scenes with ground
+/// truth.
+const MAX_BAD_2: f32 = 35.0;
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[test]
+fn mcmanamon_renders() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = Manifest::load("res/renders/manifest.toml")?;
+    let mut disp = Config::load("res/configs/mcmanamon.toml")?.build()?;
+
+    for entry in &manifest.scenes {
+        let scene = manifest.load_scene(entry)?;
+
+        check(
+            &format!("mcmanamon_{}", scene.name),
+            &mut disp,
+            &scene.frame.grey,
+            scene.truth.as_ref()
+        )?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn mcmanamon_synthetic() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params {
+        width: 256,
+        height: 128,
+        ..Default::default()
+    };
+    let (width, height) = (params.width, params.height);
+
+    let scenes = [
+        ("random_dot", Scene::random_dot(width, height, 4.0, 12.0)),
+        ("step", Scene::step(width, height, width / 2, 6.0, 14.0)),
+        ("slanted", Scene::slanted(synthetic::Plane { disparity: 4.0, dx: 0.05, dy: 0.02 }))
+    ];
+
+    for (name, scene) in scenes.iter() {
+        let pair = scene.generate(&params)?;
+
+        check(
+            &format!("mcmanamon_synthetic_{}", name),
+            &mut mcmanamon(24),
+            &pair.frame,
+            Some(&pair.truth)
+        )?;
+    }
+
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------------------------
+// FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+fn mcmanamon(max_disparity: usize) -> McManamon {
+    McManamon::new(mcmanamon::Params {
+        min_disparity: 0,
+        max_disparity,
+        dyn_disparity_threshold: 10,
+        correlation_window_size: (11, 11),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    })
+}
+
+/// Compute a frame and check it against its golden and ground truth.
+fn check<A: DisparityAlgorithm>(
+    name: &str,
+    algorithm: &mut A,
+    frame: &StereoFrame,
+    truth: Option<&GroundTruth>
+) -> Result<(), Box<dyn std::error::Error>> {
+    let map = algorithm.compute(frame)?;
+    let golden_path = golden_path(name);
+
+    if std::env::var_os(REGEN_VAR).is_some() {
+        std::fs::create_dir_all(GOLDEN_DIR)?;
+        write_pfm(&map, &golden_path)?;
+        eprintln!("wrote golden {}", golden_path.display());
+    }
+    else {
+        assert!(
+            golden_path.exists(),
+            "{}: golden {} is missing, set {} to write it",
+            name, golden_path.display(), REGEN_VAR
+        );
+
+        let golden = read_pfm(&golden_path)?;
+        let differing = differing_fraction(&map, &golden);
+
+        assert!(
+            differing <= MAX_DIFFERING,
+            "{}: {:.3}% of pixels differ from the golden map, set {} to regenerate it",
+            name, differing * 100.0, REGEN_VAR
+        );
+    }
+
+    if let Some(truth) = truth {
+        let metrics = Metrics::compute(&map, truth, Region::NonOccluded)?;
+
+        assert!(
+            metrics.bad[2] <= MAX_BAD_2,
+            "{}: bad2 of {:.2}% is over the {:.2}% threshold",
+            name, metrics.bad[2], MAX_BAD_2
+        );
+    }
+
+    Ok(())
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(GOLDEN_DIR).join(format!("{}.pfm", name))
+}
+
+/// Fraction of pixels whose validity differs between the maps, or whose value differs by more
+/// than the tolerance.
+fn differing_fraction(map: &DisparityMap, golden: &DisparityMap) -> f32 {
+    if (map.width(), map.height()) != (golden.width(), golden.height()) {
+        return 1.0;
+    }
+
+    let mut differing = 0usize;
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            let same = match (map.is_valid(x, y), golden.is_valid(x, y)) {
+                (true, true) => (map.get(x, y) - golden.get(x, y)).abs() <= PIXEL_TOLERANCE,
+                (a, b) => a == b
+            };
+
+            if !same {
+                differing += 1;
+            }
+        }
+    }
+
+    differing as f32 / (map.width() * map.height()).max(1) as f32
+}
@@ -1,4 +1,7 @@
 //! Test disparity generation with the live real-time stereo bench
+//!
+//! This needs the bench cameras and a display so is ignored by default, run it with
+//! `cargo test --test stereo_bench -- --ignored`.
 
 // -----------------------------------------------------------------------------------------------
 // IMPORTS
@@ -20,6 +23,7 @@ const HEIGHT: usize = 480;
 // -----------------------------------------------------------------------------------------------
 
 #[test]
+#[ignore]
 fn stereo_bench() -> Result<(), Box<dyn std::error::Error>> {
     println!("Starting...");
 
@@ -51,7 +55,9 @@ fn stereo_bench() -> Result<(), Box<dyn std::error::Error>> {
         min_disparity: 0,
         max_disparity: 100,
         dyn_disparity_threshold: 2,
-        correlation_window_size: (11, 11)
+        correlation_window_size: (11, 11),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
     });
 
     // Flag indicating whether or not to compute disparity
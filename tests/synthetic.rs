@@ -0,0 +1,176 @@
+//! Test the synthetic stereo pair generator, and check algorithms against it.
+
+use cv_disparity::{
+    prelude::*,
+    eval::Region,
+    mcmanamon::{McManamon, Params},
+    synthetic::{self, Plane, Scene}
+};
+
+#[test]
+fn fronto_parallel_pair() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params {
+        width: 32,
+        height: 4,
+        ..Default::default()
+    };
+
+    let pair = Scene::fronto_parallel(4.0).generate(&params)?;
+
+    for x in 0..28 {
+        assert!((pair.frame.right.get(x, 1) - pair.frame.left.get(x + 4, 1)).abs() < 1e-6);
+    }
+
+    assert_eq!(pair.truth.disparity.get(10, 2), 4.0);
+    assert!(!pair.truth.in_region(3, 0, Region::NonOccluded));
+    assert!(pair.truth.in_region(4, 0, Region::NonOccluded));
+
+    Ok(())
+}
+
+#[test]
+fn occluding_box() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params {
+        width: 48,
+        height: 4,
+        ..Default::default()
+    };
+
+    // The box covers left columns 20 to 29 and right columns 14 to 23, hiding the background
+    // at left columns 16 to 19
+    let scene = Scene::boxes(2.0, &[(Rect::new(20, 0, 10, 4), 6.0)]);
+    let pair = scene.generate(&params)?;
+
+    assert_eq!(pair.truth.disparity.get(19, 0), 2.0);
+    assert_eq!(pair.truth.disparity.get(20, 0), 6.0);
+
+    assert!(pair.truth.in_region(15, 0, Region::NonOccluded));
+    for x in 16..20 {
+        assert!(!pair.truth.in_region(x, 0, Region::NonOccluded));
+    }
+    assert!(pair.truth.in_region(20, 0, Region::NonOccluded));
+
+    Ok(())
+}
+
+#[test]
+fn generation_is_reproducible() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params {
+        noise: 0.05,
+        gain: 1.2,
+        ..Default::default()
+    };
+
+    let scene = Scene::random_dot(params.width, params.height, 4.0, 10.0);
+
+    let a = scene.generate(&params)?;
+    let b = scene.generate(&params)?;
+
+    for y in 0..params.height {
+        for x in 0..params.width {
+            assert_eq!(a.frame.left.get(x, y), b.frame.left.get(x, y));
+            assert_eq!(a.frame.right.get(x, y), b.frame.right.get(x, y));
+            assert_eq!(a.truth.disparity.get(x, y), b.truth.disparity.get(x, y));
+            assert_eq!(a.truth.disparity.validity(x, y), b.truth.disparity.validity(x, y));
+        }
+    }
+
+    assert_eq!(a.truth.non_occluded, b.truth.non_occluded);
+
+    Ok(())
+}
+
+#[test]
+fn slanted_plane_pair() -> Result<(), Box<dyn std::error::Error>> {
+    // Large dots keep the texture linear over most of each pixel, so the right image can be
+    // interpolated at the sub pixel match
+    let params = synthetic::Params {
+        dot_size: 8,
+        ..Default::default()
+    };
+    let plane = Plane {
+        disparity: 3.0,
+        dx: 0.05,
+        dy: 0.03
+    };
+
+    let pair = Scene::slanted(plane).generate(&params)?;
+
+    let mut checked = 0;
+    let mut total_error = 0.0;
+
+    for y in 0..params.height {
+        for x in 0..params.width {
+            let d = plane.at(x as f32, y as f32);
+            assert!((pair.truth.disparity.get(x, y) - d).abs() < 1e-4);
+
+            let xr = x as f32 - d;
+            assert_eq!(
+                pair.truth.in_region(x, y, Region::NonOccluded),
+                xr >= 0.0 && xr <= (params.width - 1) as f32
+            );
+
+            if !pair.truth.in_region(x, y, Region::NonOccluded) {
+                continue;
+            }
+
+            let x0 = xr.floor() as usize;
+            let x1 = (x0 + 1).min(params.width - 1);
+            let t = xr - x0 as f32;
+            let right = (1.0 - t) * pair.frame.right.get(x0, y) + t * pair.frame.right.get(x1, y);
+
+            // Interpolation is only inexact where the texture changes slope between dots
+            let error = (right - pair.frame.left.get(x, y)).abs();
+            assert!(
+                error < 0.08,
+                "right({}, {}) differs from left({}, {}) by {}", xr, y, x, y, error
+            );
+
+            checked += 1;
+            total_error += error;
+        }
+    }
+
+    assert!(checked > 0);
+    assert!(total_error / (checked as f32) < 0.01);
+
+    Ok(())
+}
+
+#[test]
+fn mcmanamon_recovers_random_dot() -> Result<(), Box<dyn std::error::Error>> {
+    let params = synthetic::Params::default();
+    let pair = Scene::random_dot(params.width, params.height, 4.0, 10.0).generate(&params)?;
+
+    let mut disp = McManamon::new(Params {
+        min_disparity: 0,
+        max_disparity: 16,
+        dyn_disparity_threshold: 2,
+        correlation_window_size: (7, 7),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    });
+
+    let map = disp.compute(&pair.frame)?;
+
+    // Of the non-occluded pixels with an estimate, almost all should be within a pixel
+    let mut estimated = 0;
+    let mut correct = 0;
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if pair.truth.in_region(x, y, Region::NonOccluded) && map.is_valid(x, y) {
+                estimated += 1;
+
+                if (map.get(x, y) - pair.truth.disparity.get(x, y)).abs() <= 1.0 {
+                    correct += 1;
+                }
+            }
+        }
+    }
+
+    assert!(estimated > 0);
+    assert!(correct as f32 / estimated as f32 > 0.9);
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! Test region of interest and tiled disparity computation.
+
+use cv_camstream::{GrayFloatImage, StereoFrame};
+use cv_disparity::{prelude::*, mcmanamon::{McManamon, Params}, tiling};
+use image;
+
+/// Largest disparity searched by [`BlockMatch`].
+const MAX_DISPARITY: usize = 12;
+
+/// Radius of the [`BlockMatch`] window.
+const RADIUS: usize = 2;
+
+/// Winner-take-all block matching on absolute differences, which only reads the window around
+/// each pixel, so its margins cover everything a tile needs.
+struct BlockMatch;
+
+impl DisparityAlgorithm for BlockMatch {
+    fn compute(&mut self, frame: &StereoFrame) -> cv_disparity::Result<DisparityMap> {
+        let width = frame.width() as usize;
+        let height = frame.height() as usize;
+        let radius = RADIUS as isize;
+
+        let clamp = |v: isize, size: usize| v.max(0).min(size as isize - 1) as usize;
+
+        let mut map = DisparityMap::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut best = (0, f32::INFINITY);
+
+                for d in 0..=MAX_DISPARITY.min(x) {
+                    let mut cost = 0.0f32;
+
+                    for j in -radius..=radius {
+                        let yj = clamp(y as isize + j, height);
+
+                        for i in -radius..=radius {
+                            let xl = clamp(x as isize + i, width);
+                            let xr = clamp(x as isize + i - d as isize, width);
+
+                            cost += (frame.left.get(xl, yj) - frame.right.get(xr, yj)).abs();
+                        }
+                    }
+
+                    if cost < best.1 {
+                        best = (d, cost);
+                    }
+                }
+
+                map.put(x, y, best.0 as f32);
+            }
+        }
+
+        Ok(map)
+    }
+
+    fn margins(&self) -> Margins {
+        Margins {
+            left: RADIUS + MAX_DISPARITY,
+            right: RADIUS,
+            top: RADIUS,
+            bottom: RADIUS
+        }
+    }
+}
+
+fn rocks_frame() -> Result<StereoFrame, Box<dyn std::error::Error>> {
+    let left_img = image::open("res/renders/simple_rocks_01_left.png")?;
+    let right_img = image::open("res/renders/simple_rocks_01_right.png")?;
+
+    Ok(StereoFrame {
+        left: GrayFloatImage::from_dynamic(&left_img),
+        left_timestamp: 0,
+        right: GrayFloatImage::from_dynamic(&right_img),
+        right_timestamp: 0
+    })
+}
+
+fn params() -> Params {
+    Params {
+        min_disparity: 0,
+        max_disparity: 40,
+        dyn_disparity_threshold: 10,
+        correlation_window_size: (7, 7),
+        border_mode: BorderMode::Invalid,
+        verify_criterion: false
+    }
+}
+
+#[test]
+fn tiles_cover_region() {
+    let tiled = TiledExecutor::new(McManamon::new(params()), (64, 48));
+    let rect = Rect::new(10, 20, 150, 100);
+
+    let tiles = tiled.tiles(rect);
+    assert_eq!(tiles.len(), 3 * 3);
+
+    for y in rect.y..rect.bottom() {
+        for x in rect.x..rect.right() {
+            assert_eq!(tiles.iter().filter(|t| t.contains(x, y)).count(), 1);
+        }
+    }
+}
+
+#[test]
+fn roi_only_fills_region() -> Result<(), Box<dyn std::error::Error>> {
+    let frame = rocks_frame()?;
+
+    let rect = Rect::new(
+        200,
+        frame.height() as usize / 2,
+        240,
+        frame.height() as usize / 4
+    );
+
+    let mut disp = McManamon::new(params());
+    let map = disp.compute_roi(&frame, rect)?;
+
+    assert_eq!(map.width(), frame.width() as usize);
+    assert_eq!(map.height(), frame.height() as usize);
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if rect.contains(x, y) {
+                assert!(map.is_valid(x, y), "({}, {}) inside the region is invalid", x, y);
+            }
+            else {
+                assert!(!map.is_valid(x, y));
+                assert_eq!(map.get(x, y), 0.0);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tiled_matches_whole_frame() -> Result<(), Box<dyn std::error::Error>> {
+    let frame = tiling::crop_frame(&rocks_frame()?, Rect::new(200, 240, 96, 64));
+
+    let whole = BlockMatch.compute(&frame)?;
+
+    let mut tiled = TiledExecutor::new(BlockMatch, (20, 16));
+    let map = tiled.compute(&frame)?;
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            assert_eq!(map.is_valid(x, y), whole.is_valid(x, y));
+            assert_eq!(map.get(x, y), whole.get(x, y), "differs at ({}, {})", x, y);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Test image warping by disparity.
+
+use cv_camstream::GrayFloatImage;
+use cv_disparity::{prelude::*, warp::{backward_warp, forward_warp, occlusion_mask}};
+
+/// Texture value at column `x`.
+fn texture(x: usize) -> f32 {
+    ((3 * x) % 17) as f32 / 17.0
+}
+
+#[test]
+fn warp_constant_disparity() -> Result<(), Box<dyn std::error::Error>> {
+    let width = 32;
+
+    let mut left = GrayFloatImage::new(width, 2);
+    let mut right = GrayFloatImage::new(width, 2);
+    let mut disp = DisparityMap::new(width, 2);
+
+    for y in 0..2 {
+        for x in 0..width {
+            left.put(x, y, texture(x));
+            right.put(x, y, texture(x + 3));
+            disp.put(x, y, 3.0);
+        }
+    }
+
+    // Right image into the left view
+    let to_left = backward_warp(&right, &disp, Reference::Left)?;
+
+    for x in 0..width {
+        assert_eq!(to_left.is_valid(x, 0), x >= 3);
+
+        if x >= 3 {
+            assert!((to_left.image.get(x, 0) - left.get(x, 0)).abs() < 1e-6);
+        }
+    }
+
+    // Left image into the right view
+    let to_right = forward_warp(&left, &disp, Reference::Left)?;
+
+    for x in 0..width - 3 {
+        assert!(to_right.is_valid(x, 1));
+        assert!((to_right.image.get(x, 1) - right.get(x, 1)).abs() < 1e-6);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn occlusion_behind_foreground() {
+    let width = 32;
+    let mut disp = DisparityMap::new(width, 1);
+
+    // A foreground strip at disparity 6 in front of a background at disparity 2
+    for x in 0..width {
+        disp.put(x, 0, if (16..20).contains(&x) { 6.0 } else { 2.0 });
+    }
+
+    let mask = occlusion_mask(&disp, Reference::Left);
+
+    // The strip lands on 10..14 in the right image, hiding the background pixels which land there
+    for (x, &occluded) in mask.iter().enumerate() {
+        assert_eq!(occluded, (12..16).contains(&x), "x = {}", x);
+    }
+}